@@ -25,19 +25,42 @@ impl AABB {
     AABB::new(small, big)
   }
 
+  pub fn intersection(box0: &AABB, box1: &AABB) -> AABB {
+    let small = Point3::new(
+      fmax(box0.min().x(), box1.min().x()),
+      fmax(box0.min().y(), box1.min().y()),
+      fmax(box0.min().z(), box1.min().z()),
+    );
+
+    let big = Point3::new(
+      fmin(box0.max().x(), box1.max().x()),
+      fmin(box0.max().y(), box1.max().y()),
+      fmin(box0.max().z(), box1.max().z()),
+    );
+
+    // Boxes that don't overlap leave nothing, but still need a place.
+    if (0..3).all(|c| small[c] <= big[c]) { AABB::new(small, big) } else { AABB::new(box0.min(), box0.min()) }
+  }
+
   pub fn min(&self) -> Point3 { self.minimum }
   pub fn max(&self) -> Point3 { self.maximum }
 
   pub fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+    self.clip(r, t_min, t_max).is_some()
+  }
+
+  // The part of t_min..t_max where the ray is inside the box.
+  pub fn clip(&self, r: &Ray, mut t_min: f64, mut t_max: f64) -> Option<(f64, f64)> {
+    // The interval narrows slab by slab.
     for a in 0..3 {
       let inv_d = 1.0 / r.direction()[a];
       let mut t0 = (self.minimum[a] - r.origin()[a]) * inv_d;
       let mut t1 = (self.maximum[a] - r.origin()[a]) * inv_d;
       if inv_d < 0.0 { (t0, t1) = (t1, t0) };
-      let t_min = fmax(t0, t_min);
-      let t_max = fmin(t1, t_max);
-      if t_max <= t_min { return false };
+      t_min = fmax(t0, t_min);
+      t_max = fmin(t1, t_max);
+      if t_max <= t_min { return None };
     }
-    true
+    Some((t_min, t_max))
   }
 }
\ No newline at end of file
@@ -1,6 +1,6 @@
 use std::cmp::Ordering;
 
-use crate::{hittable::{Hittable, HitRecord}, aabb::AABB, util::random_int};
+use crate::{hittable::{Hittable, HitRecord}, aabb::AABB, util::{Rng, random_int}};
 
 enum BVHNode {
   Branch { left: Box<BVH>, right: Box<BVH> },
@@ -13,10 +13,10 @@ pub struct BVH {
 }
 
 impl BVH {
-  pub fn new(mut objects: Vec<Box<dyn Hittable>>, time0: f64, time1: f64) -> Self {
+  pub fn new(mut objects: Vec<Box<dyn Hittable>>, time0: f64, time1: f64, rng: &mut Rng) -> Self {
     let len = objects.len();
 
-    let comparator = match random_int(0, 2) {
+    let comparator = match random_int(rng, 0, 2) {
       0 => box_x_compare,
       1 => box_y_compare,
       _ => box_z_compare
@@ -33,10 +33,10 @@ impl BVH {
         }
       },
       _ => {
-        objects.sort_unstable_by(comparator);
+        objects.sort_unstable_by(|a, b| comparator(a.as_ref(), b.as_ref()));
 
-        let left = BVH::new(objects.drain(len/2..).collect(), time0, time1);
-        let right = BVH::new(objects, time0, time1);
+        let left = BVH::new(objects.drain(len/2..).collect(), time0, time1, rng);
+        let right = BVH::new(objects, time0, time1, rng);
         let bbox = AABB::surrounding_box(&left.bbox, &right.bbox);
         BVH { tree: BVHNode::Branch { left: Box::new(left), right: Box::new(right) }, bbox }
       }
@@ -45,15 +45,15 @@ impl BVH {
 }
 
 impl Hittable for BVH {
-  fn hit(&self, r: &crate::ray::Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-    if !self.bbox.hit(&r, t_min, t_max) { return None };
+  fn hit(&self, r: &crate::ray::Ray, t_min: f64, t_max: f64, rng: &mut Rng) -> Option<HitRecord<'_>> {
+    if !self.bbox.hit(r, t_min, t_max) { return None };
 
     match &self.tree {
-      BVHNode::Leaf(leaf) => leaf.hit(&r, t_min, t_max),
+      BVHNode::Leaf(leaf) => leaf.hit(r, t_min, t_max, rng),
       BVHNode::Branch { left, right } => {
-        let hit_left = left.hit(&r, t_min, t_max);
+        let hit_left = left.hit(r, t_min, t_max, rng);
         let t_max = if let Some(rec) = &hit_left { rec.t } else { t_max };
-        let hit_right = right.hit(&r, t_min, t_max);
+        let hit_right = right.hit(r, t_min, t_max, rng);
 
         hit_right.or(hit_left)
       }
@@ -65,7 +65,7 @@ impl Hittable for BVH {
   }
 }
 
-fn box_compare(a: &Box<dyn Hittable>, b: &Box<dyn Hittable>, axis: i32) -> Ordering {
+fn box_compare(a: &dyn Hittable, b: &dyn Hittable, axis: i32) -> Ordering {
   let box_a = a.bounding_box(0.0, 0.0);
   let box_b = b.bounding_box(0.0, 0.0);
 
@@ -77,6 +77,6 @@ fn box_compare(a: &Box<dyn Hittable>, b: &Box<dyn Hittable>, axis: i32) -> Order
   }
 }
 
-fn box_x_compare(a: &Box<dyn Hittable>, b: &Box<dyn Hittable>) -> Ordering { box_compare(a, b, 0) }
-fn box_y_compare(a: &Box<dyn Hittable>, b: &Box<dyn Hittable>) -> Ordering { box_compare(a, b, 1) }
-fn box_z_compare(a: &Box<dyn Hittable>, b: &Box<dyn Hittable>) -> Ordering { box_compare(a, b, 2) }
\ No newline at end of file
+fn box_x_compare(a: &dyn Hittable, b: &dyn Hittable) -> Ordering { box_compare(a, b, 0) }
+fn box_y_compare(a: &dyn Hittable, b: &dyn Hittable) -> Ordering { box_compare(a, b, 1) }
+fn box_z_compare(a: &dyn Hittable, b: &dyn Hittable) -> Ordering { box_compare(a, b, 2) }
\ No newline at end of file
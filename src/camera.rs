@@ -1,4 +1,4 @@
-use crate::{vec3::{Point3, Vec3, unit_vector, cross}, ray::Ray, util::random_double_in_range};
+use crate::{vec3::{Point3, Vec3, unit_vector, cross}, ray::Ray, sampler::sample_unit_disk};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Camera {
@@ -47,14 +47,14 @@ impl Camera {
     }
   }
 
-  pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-    let rd = self.lens_radius * Vec3::random_in_unit_disk();
+  pub fn get_ray(&self, s: f64, t: f64, lens: (f64, f64), time: f64) -> Ray {
+    let rd = self.lens_radius * sample_unit_disk(lens);
     let offset = self.u * rd.x() + self.v * rd.y();
 
     Ray::new(
       self.origin + offset,
       self.lower_left_corner + s*self.horizontal + t*self.vertical - self.origin - offset,
-      random_double_in_range(self.time0, self.time1)
+      self.time0 + time*(self.time1 - self.time0)
     )
   }
 }
\ No newline at end of file
@@ -0,0 +1,74 @@
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+
+use crate::sampler::SamplerKind;
+
+#[derive(Debug, Parser)]
+#[command(about = "Render a scene with the path tracer")]
+pub struct Args {
+  /// Built-in scene name, bundled scene name (scenes/<name>.json) or path to a scene or glTF file
+  #[arg(default_value = "random_scene")]
+  pub scene: String,
+
+  /// Image width in pixels [default: from scene]
+  #[arg(short = 'W', long, value_parser = clap::value_parser!(i32).range(1..))]
+  pub width: Option<i32>,
+
+  /// Image height in pixels [default: width / scene aspect ratio]
+  #[arg(short = 'H', long, value_parser = clap::value_parser!(i32).range(1..))]
+  pub height: Option<i32>,
+
+  /// Samples per pixel [default: from scene]
+  #[arg(short, long, value_parser = clap::value_parser!(i32).range(1..))]
+  pub spp: Option<i32>,
+
+  /// Maximum ray bounce depth [default: from scene]
+  #[arg(short, long, value_parser = clap::value_parser!(i32).range(1..))]
+  pub depth: Option<i32>,
+
+  /// Bounces before paths may be terminated by Russian roulette [default: from scene]
+  #[arg(long, value_parser = clap::value_parser!(i32).range(0..))]
+  pub rr_depth: Option<i32>,
+
+  /// Trace sampled wavelengths instead of RGB, for dispersion; --spectral=false traces RGB [default: from scene]
+  #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+  pub spectral: Option<bool>,
+
+  /// Number of render threads [default: number of CPUs]
+  #[arg(short, long, value_parser = clap::value_parser!(u16).range(1..))]
+  pub threads: Option<u16>,
+
+  /// Output file (.png, .hdr, .exr or .ppm), or - for binary PPM on stdout
+  #[arg(short, long, default_value = "-")]
+  pub output: PathBuf,
+
+  /// Bits per channel for PNG output
+  #[arg(long, default_value_t = 8)]
+  pub bit_depth: u8,
+
+  /// Sample generator for pixel, lens, time and bounce dimensions
+  #[arg(long, value_enum, default_value_t = SamplerKind::Sobol)]
+  pub sampler: SamplerKind,
+
+  /// Seed for scene generation and sampling; renders are reproducible for a given seed [default: random]
+  #[arg(long)]
+  pub seed: Option<u64>
+}
+
+impl Args {
+  pub fn scene_path(&self) -> Option<PathBuf> {
+    let path = PathBuf::from(&self.scene);
+    let bundled = Path::new("scenes").join(&self.scene).with_extension("json");
+    [path, bundled].into_iter().find(|p| p.is_file())
+  }
+
+  pub fn resolution(&self, aspect_ratio: f64, scene_width: i32) -> (i32, i32) {
+    match (self.width, self.height) {
+      (Some(w), Some(h)) => (w, h),
+      (Some(w), None) => (w, ((w as f64 / aspect_ratio) as i32).max(1)),
+      (None, Some(h)) => (((h as f64 * aspect_ratio) as i32).max(1), h),
+      (None, None) => (scene_width, ((scene_width as f64 / aspect_ratio) as i32).max(1))
+    }
+  }
+}
\ No newline at end of file
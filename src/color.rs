@@ -1,13 +1,23 @@
 use crate::{vec3::Color};
 
-pub fn write_color(pixel_color: Color) {
-  let r = pixel_color.x().sqrt();
-  let g = pixel_color.y().sqrt();
-  let b = pixel_color.z().sqrt();
-
-  println!("{} {} {}",
-    (256.0 * r.clamp(0.0, 0.999)) as i32,
-    (256.0 * g.clamp(0.0, 0.999)) as i32,
-    (256.0 * b.clamp(0.0, 0.999)) as i32
-  );
+fn gamma_corrected(pixel_color: Color) -> [f64; 3] {
+  // Gamma-correct for gamma=2.0 and clamp to the displayable range.
+  [pixel_color.x(), pixel_color.y(), pixel_color.z()].map(|c| c.sqrt().clamp(0.0, 1.0))
+}
+
+pub fn to_rgb8(pixel_color: Color) -> [u8; 3] {
+  gamma_corrected(pixel_color).map(|c| (256.0 * c).min(255.0) as u8)
+}
+
+pub fn to_rgb16(pixel_color: Color) -> [u16; 3] {
+  gamma_corrected(pixel_color).map(|c| (65536.0 * c).min(65535.0) as u16)
+}
+
+// Decodes an sRGB encoded value between 0 and 1.
+pub fn srgb_to_linear(c: f64) -> f64 {
+  if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+pub fn luminance(color: Color) -> f64 {
+  0.2126*color.x() + 0.7152*color.y() + 0.0722*color.z()
 }
\ No newline at end of file
@@ -1,6 +1,4 @@
-use std::f64::{NEG_INFINITY, INFINITY};
-
-use crate::{hittable::{Hittable, HitRecord}, material::{Isotropic}, texture::{Texture, SolidColor}, vec3::{Color, Vec3}, aabb::AABB, util::random_double};
+use crate::{hittable::{Hittable, HitRecord}, material::{Isotropic}, texture::Texture, vec3::Vec3, aabb::AABB, util::{Rng, random_double}};
 
 pub struct ConstantMedium<H: Hittable, T: Texture> {
   boundary: H,
@@ -14,44 +12,36 @@ impl<H: Hittable, T: Texture> ConstantMedium<H, T> {
   }
 }
 
-impl<H: Hittable> ConstantMedium<H, SolidColor> {
-  pub fn solid(b: H, d: f64, c: Color) -> Self {
-    Self { boundary: b, neg_inv_density: -1.0/d, phase_function: Isotropic::solid(c) }
-  }
-}
-
 impl<H: Hittable, T: Texture> Hittable for ConstantMedium<H, T> {
-  fn hit(&self, r: &crate::ray::Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+  fn hit(&self, r: &crate::ray::Ray, t_min: f64, t_max: f64, rng: &mut Rng) -> Option<HitRecord<'_>> {
     // Print occasional samples when debugging. To enable, set enableDebug true.
     let enable_debug = false;
-    let debugging = enable_debug && random_double() < 0.00001;
-
-    let mut rec1 = match self.boundary.hit(r, NEG_INFINITY, INFINITY) {
-      Some(rec) => rec,
-      None => return None
-    };
-
-    let mut rec2 = match self.boundary.hit(r, rec1.t+0.0001, INFINITY) {
-      Some(rec) => rec,
-      None => return None
-    };
+    let debugging = enable_debug && random_double(rng) < 0.00001;
 
-    if debugging { eprintln!("\nt_min={}, tmax={}", rec1.t, rec2.t) };
-
-    if rec1.t < t_min { rec1.t = t_min };
-    if rec2.t > t_max { rec2.t = t_max };
-
-    if rec1.t >= rec2.t { return None }
-
-    if rec1.t < 0.0 { rec1.t = 0.0 }
+    // The boundary may be entered and left several times along the ray.
+    // Distance to scattering is memoryless, so one sampled distance can be
+    // spent across all the stretches inside it.
+    let spans: Vec<(f64, f64)> = self.boundary.intervals(r, rng).into_iter()
+      .map(|i| (i.entry.t.max(t_min).max(0.0), i.exit.t.min(t_max)))
+      .filter(|(t0, t1)| t0 < t1)
+      .collect();
+    if spans.is_empty() { return None }
 
     let ray_length = r.direction().length();
-    let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
-    let hit_distance = self.neg_inv_density * random_double().ln();
-
-    if hit_distance > distance_inside_boundary { return None }
+    let mut hit_distance = self.neg_inv_density * random_double(rng).ln();
+    let mut scatter = None;
+    for (t0, t1) in spans {
+      if debugging { eprintln!("\nt_min={t0}, tmax={t1}") };
+
+      let distance_inside_boundary = (t1 - t0) * ray_length;
+      if hit_distance <= distance_inside_boundary {
+        scatter = Some(t0 + hit_distance / ray_length);
+        break
+      }
+      hit_distance -= distance_inside_boundary;
+    }
 
-    let t = rec1.t + hit_distance / ray_length;
+    let t = scatter?;
     let p = r.at(t);
 
     if debugging {
@@ -61,7 +51,7 @@ impl<H: Hittable, T: Texture> Hittable for ConstantMedium<H, T> {
     let normal = Vec3::new(1.0, 0.0, 0.0); // arbitrary
     let front_face = true; // also arbitrary
 
-    Some(HitRecord { p, normal, material: &self.phase_function, t, u: 0.0, v: 0.0, front_face })
+    Some(HitRecord { p, normal, material: &self.phase_function, t, u: 0.0, v: 0.0, tangent: Vec3::zero(), color: None, front_face })
   }
 
   fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
@@ -0,0 +1,158 @@
+use crate::{hittable::{Hittable, HitRecord, Interval}, aabb::AABB, ray::Ray, util::Rng};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsgOperation {
+  Union,
+  Intersection,
+  // The first object with the second cut out of it.
+  Difference
+}
+
+impl CsgOperation {
+  fn inside(self, in_a: bool, in_b: bool) -> bool {
+    match self {
+      CsgOperation::Union => in_a || in_b,
+      CsgOperation::Intersection => in_a && in_b,
+      CsgOperation::Difference => in_a && !in_b
+    }
+  }
+}
+
+// Solid combined from two closed objects. Every part of its surface is part
+// of one of theirs and keeps that object's material, so a cut takes on the
+// material of the object it was cut with.
+pub struct Csg<A: Hittable, B: Hittable> {
+  a: A,
+  b: B,
+  operation: CsgOperation
+}
+
+impl<A: Hittable, B: Hittable> Csg<A, B> {
+  pub fn new(a: A, b: B, operation: CsgOperation) -> Self {
+    Self { a, b, operation }
+  }
+}
+
+impl<A: Hittable, B: Hittable> Hittable for Csg<A, B> {
+  fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut Rng) -> Option<HitRecord<'_>> {
+    self.intervals(r, rng).into_iter()
+      .flat_map(|i| [i.entry, i.exit])
+      .find(|rec| rec.t >= t_min && rec.t <= t_max)
+  }
+
+  fn intervals(&self, r: &Ray, rng: &mut Rng) -> Vec<Interval<'_>> {
+    // Walk the boundaries of both objects in order, tracking which of them
+    // the ray is inside, and keep those where that changes for the result.
+    let mut boundaries: Vec<(bool, bool, HitRecord<'_>)> = Vec::new();
+    for i in self.a.intervals(r, rng) { boundaries.extend([(true, true, i.entry), (true, false, i.exit)]) }
+    for i in self.b.intervals(r, rng) { boundaries.extend([(false, true, i.entry), (false, false, i.exit)]) }
+    boundaries.sort_by(|x, y| x.2.t.total_cmp(&y.2.t));
+
+    let (mut in_a, mut in_b) = (false, false);
+    let mut entry = None;
+    let mut intervals: Vec<Interval<'_>> = Vec::new();
+    for (from_a, entering, mut rec) in boundaries {
+      let was_inside = self.operation.inside(in_a, in_b);
+      if from_a { in_a = entering } else { in_b = entering }
+      let inside = self.operation.inside(in_a, in_b);
+      if inside == was_inside { continue }
+
+      // Hit normals always face the ray, so only which side is the front
+      // can change.
+      rec.front_face = inside;
+      if inside {
+        // Where one object ends just as the other starts there is no gap.
+        entry = match intervals.last() {
+          Some(last) if last.exit.t >= rec.t => intervals.pop().map(|last| last.entry),
+          _ => Some(rec)
+        };
+      } else if let Some(entry) = entry.take() {
+        intervals.push(Interval { entry, exit: rec });
+      }
+    }
+    intervals
+  }
+
+  fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
+    let a = self.a.bounding_box(time0, time1);
+    let b = self.b.bounding_box(time0, time1);
+    match self.operation {
+      CsgOperation::Union => Some(AABB::surrounding_box(&a?, &b?)),
+      CsgOperation::Intersection => match (a, b) {
+        (Some(a), Some(b)) => Some(AABB::intersection(&a, &b)),
+        (a, b) => a.or(b)
+      },
+      CsgOperation::Difference => a
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{sphere::Sphere, material::Lambertian, texture::SolidColor, vec3::{Point3, Color, Vec3}, util::seeded_rng};
+
+  // A ray along +X from the origin meets a ball at x = c with radius r over
+  // t in [c - r, c + r].
+  fn ball(c: f64, r: f64) -> Sphere<Lambertian<SolidColor>> {
+    Sphere::new(Point3::new(c, 0.0, 0.0), r, Lambertian::new(SolidColor::new(Color::new(0.5, 0.5, 0.5))))
+  }
+
+  fn spans(a: (f64, f64), b: (f64, f64), operation: CsgOperation) -> Vec<(f64, f64)> {
+    let csg = Csg::new(ball(a.0, a.1), ball(b.0, b.1), operation);
+    let r = Ray::new(Point3::zero(), Vec3::new(1.0, 0.0, 0.0), 0.0);
+    csg.intervals(&r, &mut seeded_rng(0)).iter().map(|i| {
+      assert!(i.entry.front_face && !i.exit.front_face);
+      (i.entry.t, i.exit.t)
+    }).collect()
+  }
+
+  fn assert_spans(actual: Vec<(f64, f64)>, expected: &[(f64, f64)]) {
+    assert_eq!(actual.len(), expected.len(), "{actual:?}");
+    for (a, e) in actual.iter().zip(expected) {
+      assert!((a.0 - e.0).abs() < 1e-9 && (a.1 - e.1).abs() < 1e-9, "{actual:?} != {expected:?}");
+    }
+  }
+
+  #[test]
+  fn disjoint() {
+    let (a, b) = ((3.0, 1.0), (7.0, 1.0));
+    assert_spans(spans(a, b, CsgOperation::Union), &[(2.0, 4.0), (6.0, 8.0)]);
+    assert_spans(spans(a, b, CsgOperation::Intersection), &[]);
+    assert_spans(spans(a, b, CsgOperation::Difference), &[(2.0, 4.0)]);
+  }
+
+  #[test]
+  fn overlapping() {
+    let (a, b) = ((4.0, 2.0), (6.0, 2.0));
+    assert_spans(spans(a, b, CsgOperation::Union), &[(2.0, 8.0)]);
+    assert_spans(spans(a, b, CsgOperation::Intersection), &[(4.0, 6.0)]);
+    assert_spans(spans(a, b, CsgOperation::Difference), &[(2.0, 4.0)]);
+    assert_spans(spans(b, a, CsgOperation::Difference), &[(6.0, 8.0)]);
+  }
+
+  #[test]
+  fn nested() {
+    let (outer, inner) = ((6.0, 4.0), (6.0, 2.0));
+    assert_spans(spans(outer, inner, CsgOperation::Union), &[(2.0, 10.0)]);
+    assert_spans(spans(outer, inner, CsgOperation::Intersection), &[(4.0, 8.0)]);
+    assert_spans(spans(outer, inner, CsgOperation::Difference), &[(2.0, 4.0), (8.0, 10.0)]);
+    assert_spans(spans(inner, outer, CsgOperation::Difference), &[]);
+  }
+
+  #[test]
+  fn touching_objects_leave_no_gap() {
+    assert_spans(spans((3.0, 1.0), (5.0, 1.0), CsgOperation::Union), &[(2.0, 6.0)]);
+  }
+
+  #[test]
+  fn hit_finds_the_first_boundary_in_range() {
+    let csg = Csg::new(ball(6.0, 4.0), ball(6.0, 2.0), CsgOperation::Difference);
+    let r = Ray::new(Point3::zero(), Vec3::new(1.0, 0.0, 0.0), 0.0);
+    let mut rng = seeded_rng(0);
+    assert!((csg.hit(&r, 0.001, f64::INFINITY, &mut rng).unwrap().t - 2.0).abs() < 1e-9);
+    // Inside the hole the next surface is where the shell starts again.
+    let rec = csg.hit(&r, 5.0, f64::INFINITY, &mut rng).unwrap();
+    assert!((rec.t - 8.0).abs() < 1e-9 && rec.front_face);
+  }
+}
\ No newline at end of file
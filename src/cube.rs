@@ -1,4 +1,4 @@
-use crate::{vec3::Point3, hittable_list::HittableList, material::Material, aarect::{XYRect, XZRect, YZRect}, hittable::{Hittable, HitRecord}, aabb::AABB};
+use crate::{vec3::{Point3, Vec3}, hittable_list::HittableList, material::Material, planar::Quad, hittable::{Hittable, HitRecord}, aabb::AABB, util::Rng};
 
 pub struct Cube {
   box_min: Point3,
@@ -7,28 +7,35 @@ pub struct Cube {
 }
 
 impl Cube {
-  pub fn new<M: Material + Copy + 'static>(p0: Point3, p1: Point3, material: M) -> Self {
+  pub fn new<M: Material + Clone + 'static>(p0: Point3, p1: Point3, material: M) -> Self {
     let mut sides = HittableList::new();
+    let d = p1 - p0;
+    let (dx, dy, dz) = (Vec3::new(d.x(), 0.0, 0.0), Vec3::new(0.0, d.y(), 0.0), Vec3::new(0.0, 0.0, d.z()));
 
-    sides.add(Box::new(XYRect::new(p0.x(), p1.x(), p0.y(), p1.y(), p1.z(), material)));
-    sides.add(Box::new(XYRect::new(p0.x(), p1.x(), p0.y(), p1.y(), p0.z(), material)));
+    // Each side faces out of the box.
+    sides.add(Box::new(Quad::new(Point3::new(p0.x(), p0.y(), p1.z()), dx, dy, material.clone())));
+    sides.add(Box::new(Quad::new(Point3::new(p1.x(), p0.y(), p0.z()), -dx, dy, material.clone())));
 
-    sides.add(Box::new(XZRect::new(p0.x(), p1.x(), p0.z(), p1.z(), p1.y(), material)));
-    sides.add(Box::new(XZRect::new(p0.x(), p1.x(), p0.z(), p1.z(), p0.y(), material)));
+    sides.add(Box::new(Quad::new(Point3::new(p0.x(), p1.y(), p1.z()), dx, -dz, material.clone())));
+    sides.add(Box::new(Quad::new(p0, dx, dz, material.clone())));
 
-    sides.add(Box::new(YZRect::new(p0.y(), p1.y(), p0.z(), p1.z(), p1.x(), material)));
-    sides.add(Box::new(YZRect::new(p0.y(), p1.y(), p0.z(), p1.z(), p0.x(), material)));
+    sides.add(Box::new(Quad::new(Point3::new(p1.x(), p0.y(), p1.z()), -dz, dy, material.clone())));
+    sides.add(Box::new(Quad::new(p0, dz, dy, material)));
 
     Self { box_min: p0, box_max: p1, sides }
   }
 }
 
 impl Hittable for Cube {
-  fn hit(&self, r: &crate::ray::Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-    self.sides.hit(r, t_min, t_max)
+  fn hit(&self, r: &crate::ray::Ray, t_min: f64, t_max: f64, rng: &mut Rng) -> Option<HitRecord<'_>> {
+    self.sides.hit(r, t_min, t_max, rng)
   }
 
   fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
     Some(AABB::new(self.box_min, self.box_max))
   }
+
+  fn is_light(&self) -> bool { self.sides.is_light() }
+  fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 { self.sides.pdf_value(o, v) }
+  fn random(&self, o: &Point3, u: (f64, f64)) -> Vec3 { self.sides.random(o, u) }
 }
\ No newline at end of file
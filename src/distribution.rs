@@ -0,0 +1,154 @@
+// Piecewise-constant distributions for importance sampling tabulated
+// functions such as environment maps.
+
+pub struct Distribution1D {
+  func: Vec<f64>,
+  cdf: Vec<f64>,
+  integral: f64
+}
+
+impl Distribution1D {
+  pub fn new(func: Vec<f64>) -> Self {
+    let n = func.len();
+    let mut cdf = vec![0.0; n + 1];
+    for i in 0..n {
+      cdf[i + 1] = cdf[i] + func[i].abs() / n as f64;
+    }
+    let integral = cdf[n];
+
+    // A zero function is sampled uniformly.
+    for i in 1..=n {
+      cdf[i] = if integral == 0.0 { i as f64 / n as f64 } else { cdf[i] / integral };
+    }
+
+    Self { func, cdf, integral }
+  }
+
+  pub fn integral(&self) -> f64 { self.integral }
+
+  // Returns a point in [0,1), its density and the segment it fell in.
+  pub fn sample(&self, u: f64) -> (f64, f64, usize) {
+    let n = self.func.len();
+    let offset = (self.cdf.partition_point(|&c| c <= u) - 1).min(n - 1);
+
+    let mut du = u - self.cdf[offset];
+    let width = self.cdf[offset + 1] - self.cdf[offset];
+    if width > 0.0 { du /= width }
+
+    ((offset as f64 + du) / n as f64, self.pdf(offset), offset)
+  }
+
+  pub fn pdf(&self, offset: usize) -> f64 {
+    if self.integral == 0.0 { 0.0 } else { self.func[offset].abs() / self.integral }
+  }
+}
+
+// Rows are sampled by their totals, then a column within the chosen row.
+pub struct Distribution2D {
+  conditional: Vec<Distribution1D>,
+  marginal: Distribution1D
+}
+
+impl Distribution2D {
+  // func: width * height values, row by row.
+  pub fn new(func: &[f64], width: usize, height: usize) -> Self {
+    let conditional: Vec<Distribution1D> = func.chunks(width).take(height).map(|row| Distribution1D::new(row.to_vec())).collect();
+    let marginal = Distribution1D::new(conditional.iter().map(|d| d.integral()).collect());
+    Self { conditional, marginal }
+  }
+
+  pub fn integral(&self) -> f64 { self.marginal.integral() }
+
+  pub fn sample(&self, u: (f64, f64)) -> ((f64, f64), f64) {
+    let (y, pdf_y, row) = self.marginal.sample(u.1);
+    let (x, pdf_x, _) = self.conditional[row].sample(u.0);
+    ((x, y), pdf_x * pdf_y)
+  }
+
+  pub fn pdf(&self, x: f64, y: f64) -> f64 {
+    let width = self.conditional[0].func.len();
+    let height = self.conditional.len();
+    let column = ((x * width as f64) as usize).min(width - 1);
+    let row = ((y * height as f64) as usize).min(height - 1);
+    if self.marginal.integral == 0.0 { 0.0 } else { self.conditional[row].func[column] / self.marginal.integral }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn pdf_1d_integrates_to_one() {
+    let d = Distribution1D::new(vec![1.0, 0.0, 3.0, 4.0, 2.0]);
+    let total: f64 = (0..5).map(|i| d.pdf(i) / 5.0).sum();
+    assert!((total - 1.0).abs() < 1e-12);
+    assert!((d.integral() - 2.0).abs() < 1e-12);
+  }
+
+  #[test]
+  fn samples_1d_follow_pdf() {
+    let func = vec![1.0, 0.0, 3.0, 4.0, 2.0];
+    let d = Distribution1D::new(func.clone());
+    let n = 100000;
+    let mut counts = [0usize; 5];
+    let mut last = 0.0;
+    for k in 0..n {
+      let (x, pdf, offset) = d.sample((k as f64 + 0.5) / n as f64);
+      // Sampling is monotonic and lands in the segment it reports.
+      assert!(x >= last && (0.0..1.0).contains(&x));
+      assert_eq!(offset, (x * 5.0) as usize);
+      assert_eq!(pdf, d.pdf(offset));
+      last = x;
+      counts[offset] += 1;
+    }
+    for i in 0..5 {
+      let expected = d.pdf(i) / 5.0;
+      assert!((counts[i] as f64 / n as f64 - expected).abs() < 1e-4, "{i}: {counts:?}");
+    }
+    assert_eq!(counts[1], 0);
+  }
+
+  #[test]
+  fn zero_function_samples_uniformly() {
+    let d = Distribution1D::new(vec![0.0; 4]);
+    assert_eq!(d.sample(0.3).0, 0.3);
+    assert_eq!(d.pdf(2), 0.0);
+  }
+
+  #[test]
+  fn pdf_2d_integrates_to_one() {
+    let (width, height) = (4, 3);
+    let func: Vec<f64> = (0..width * height).map(|i| (i % 5) as f64).collect();
+    let d = Distribution2D::new(&func, width, height);
+    let mut total = 0.0;
+    for row in 0..height {
+      for column in 0..width {
+        let (x, y) = ((column as f64 + 0.5) / width as f64, (row as f64 + 0.5) / height as f64);
+        total += d.pdf(x, y) / (width * height) as f64;
+      }
+    }
+    assert!((total - 1.0).abs() < 1e-12);
+  }
+
+  #[test]
+  fn samples_2d_agree_with_pdf() {
+    let (width, height) = (4, 3);
+    let func: Vec<f64> = (0..width * height).map(|i| (i % 5) as f64).collect();
+    let d = Distribution2D::new(&func, width, height);
+    let n = 300;
+    let mut counts = vec![0usize; width * height];
+    for i in 0..n {
+      for j in 0..n {
+        let ((x, y), pdf) = d.sample(((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64));
+        assert!((pdf - d.pdf(x, y)).abs() < 1e-12);
+        let (column, row) = ((x * width as f64) as usize, (y * height as f64) as usize);
+        counts[row * width + column] += 1;
+      }
+    }
+    for (cell, &count) in counts.iter().enumerate() {
+      let expected = func[cell] / func.iter().sum::<f64>();
+      assert!((count as f64 / (n * n) as f64 - expected).abs() < 1e-3, "{cell}: {counts:?}");
+    }
+  }
+}
\ No newline at end of file
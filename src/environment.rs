@@ -0,0 +1,141 @@
+use std::{f64::consts::PI, fs::File, io::BufReader, path::Path, sync::Arc};
+
+use image::{io::Reader as ImageReader, DynamicImage, ImageResult, codecs::hdr::HdrDecoder};
+
+use crate::{vec3::{Vec3, Color, unit_vector}, color::{luminance, srgb_to_linear}, distribution::Distribution2D};
+
+// Radiance arriving from infinitely far away, seen by rays that escape the
+// scene. Environments that can be importance sampled join the scene's lights.
+pub trait Environment: Send + Sync {
+  fn value(&self, direction: &Vec3) -> Color;
+
+  fn is_light(&self) -> bool { false }
+  fn pdf_value(&self, _direction: &Vec3) -> f64 { 0.0 }
+  fn random(&self, _u: (f64, f64)) -> Vec3 { Vec3::new(0.0, 1.0, 0.0) }
+}
+
+impl<E: Environment + ?Sized> Environment for Arc<E> {
+  fn value(&self, direction: &Vec3) -> Color { (**self).value(direction) }
+  fn is_light(&self) -> bool { (**self).is_light() }
+  fn pdf_value(&self, direction: &Vec3) -> f64 { (**self).pdf_value(direction) }
+  fn random(&self, u: (f64, f64)) -> Vec3 { (**self).random(u) }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ConstantEnvironment {
+  color: Color
+}
+
+impl ConstantEnvironment {
+  pub fn new(color: Color) -> Self { Self { color } }
+}
+
+impl Environment for ConstantEnvironment {
+  fn value(&self, _direction: &Vec3) -> Color { self.color }
+}
+
+// Blends from bottom straight down to top straight up.
+#[derive(Debug, Clone, Copy)]
+pub struct GradientEnvironment {
+  bottom: Color,
+  top: Color
+}
+
+impl GradientEnvironment {
+  pub fn new(bottom: Color, top: Color) -> Self { Self { bottom, top } }
+}
+
+impl Environment for GradientEnvironment {
+  fn value(&self, direction: &Vec3) -> Color {
+    let t = 0.5*(unit_vector(*direction).y() + 1.0);
+    (1.0-t)*self.bottom + t*self.top
+  }
+}
+
+// Equirectangular (latitude-longitude) image with +Y up. The center of the
+// image looks down -Z; rotation turns the map about the Y axis.
+pub struct ImageEnvironment {
+  width: usize,
+  height: usize,
+  pixels: Vec<Color>,
+  sin_rotation: f64,
+  cos_rotation: f64,
+  intensity: f64,
+  distribution: Distribution2D
+}
+
+impl ImageEnvironment {
+  pub fn load(filename: &str, rotation: f64, intensity: f64) -> ImageResult<Self> {
+    let (width, height, pixels) = read_linear(filename)?;
+
+    // Rows near the poles cover less solid angle.
+    let func: Vec<f64> = pixels.iter().enumerate().map(|(i, pixel)| {
+      let theta = PI * ((i / width) as f64 + 0.5) / height as f64;
+      luminance(*pixel).max(0.0) * theta.sin()
+    }).collect();
+    let distribution = Distribution2D::new(&func, width, height);
+
+    let radians = rotation.to_radians();
+    Ok(Self { width, height, pixels, sin_rotation: radians.sin(), cos_rotation: radians.cos(), intensity, distribution })
+  }
+
+  fn direction_to_uv(&self, direction: &Vec3) -> (f64, f64) {
+    let d = unit_vector(*direction);
+    let x = self.cos_rotation*d.x() - self.sin_rotation*d.z();
+    let z = self.sin_rotation*d.x() + self.cos_rotation*d.z();
+
+    let u = 0.5 + x.atan2(-z) / (2.0*PI);
+    let v = d.y().clamp(-1.0, 1.0).acos() / PI;
+    (u, v)
+  }
+
+  fn uv_to_direction(&self, u: f64, v: f64) -> Vec3 {
+    let theta = v*PI;
+    let phi = (u - 0.5)*2.0*PI;
+    let (x, y, z) = (theta.sin()*phi.sin(), theta.cos(), -theta.sin()*phi.cos());
+    Vec3::new(self.cos_rotation*x + self.sin_rotation*z, y, -self.sin_rotation*x + self.cos_rotation*z)
+  }
+}
+
+impl Environment for ImageEnvironment {
+  fn value(&self, direction: &Vec3) -> Color {
+    let (u, v) = self.direction_to_uv(direction);
+    let i = ((u * self.width as f64) as usize).min(self.width - 1);
+    let j = ((v * self.height as f64) as usize).min(self.height - 1);
+    self.intensity * self.pixels[j*self.width + i]
+  }
+
+  fn is_light(&self) -> bool { self.intensity > 0.0 && self.distribution.integral() > 0.0 }
+
+  fn pdf_value(&self, direction: &Vec3) -> f64 {
+    let (u, v) = self.direction_to_uv(direction);
+    let sin_theta = (v*PI).sin();
+    if sin_theta <= 0.0 { return 0.0 }
+    self.distribution.pdf(u, v) / (2.0*PI*PI*sin_theta)
+  }
+
+  fn random(&self, u: (f64, f64)) -> Vec3 {
+    let ((u, v), _) = self.distribution.sample(u);
+    self.uv_to_direction(u, v)
+  }
+}
+
+// Decoding Radiance files through DynamicImage tone maps them to 8 bits, so
+// they are read with the HDR decoder directly. Other floating point images,
+// like OpenEXR, are already linear; 8 and 16 bit ones are taken to be sRGB.
+fn read_linear(filename: &str) -> ImageResult<(usize, usize, Vec<Color>)> {
+  let is_hdr = Path::new(filename).extension().is_some_and(|e| e.eq_ignore_ascii_case("hdr"));
+  if is_hdr {
+    let decoder = HdrDecoder::new(BufReader::new(File::open(filename)?))?;
+    let metadata = decoder.metadata();
+    let pixels = decoder.read_image_hdr()?.iter().map(|p| Color::new(p[0] as f64, p[1] as f64, p[2] as f64)).collect();
+    Ok((metadata.width as usize, metadata.height as usize, pixels))
+  } else {
+    let image = ImageReader::open(filename)?.decode()?;
+    let linear = matches!(image, DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_));
+    let decode = |c: f32| if linear { c as f64 } else { srgb_to_linear(c as f64) };
+    let image = image.into_rgb32f();
+    let pixels = image.pixels().map(|p| Color::new(decode(p[0]), decode(p[1]), decode(p[2]))).collect();
+    Ok((image.width() as usize, image.height() as usize, pixels))
+  }
+}
\ No newline at end of file
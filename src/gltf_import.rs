@@ -0,0 +1,401 @@
+use std::{collections::HashMap, fmt, path::{Path, PathBuf}, sync::Arc};
+
+use gltf::{camera::Projection, image::Format, khr_lights_punctual::Kind, mesh::Mode, texture::WrappingMode};
+
+use crate::{
+  vec3::{Point3, Vec3, Color, unit_vector},
+  color::srgb_to_linear,
+  mat4::Mat4,
+  hittable::Hittable,
+  material::Material,
+  texture::{Texture, SolidColor},
+  principled::Principled,
+  punctual::{PunctualLight, PointLight, SpotLight, DirectionalLight},
+  triangle::{MeshData, TriangleMesh},
+  util::Rng
+};
+
+#[derive(Debug)]
+pub struct GltfError {
+  path: PathBuf,
+  message: String
+}
+
+impl fmt::Display for GltfError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}: {}", self.path.display(), self.message)
+  }
+}
+
+impl std::error::Error for GltfError {}
+
+// The first perspective camera in the scene, placed by its node.
+#[derive(Debug, Clone, Copy)]
+pub struct GltfCamera {
+  pub lookfrom: Point3,
+  pub lookat: Point3,
+  pub vup: Vec3,
+  pub vfov: f64,
+  pub aspect_ratio: Option<f64>
+}
+
+pub struct GltfScene {
+  pub objects: Vec<Box<dyn Hittable>>,
+  pub punctual: Vec<Box<dyn PunctualLight>>,
+  pub camera: Option<GltfCamera>
+}
+
+// Loads the default scene of a glTF 2.0 file, either .gltf with its buffers
+// and images in files alongside or in data URIs, or a binary .glb. Node
+// transforms are baked into the vertices and each primitive becomes a mesh
+// of its own. Metallic-roughness materials become Principled ones, and
+// KHR_lights_punctual lights keep their intensities as they are, ignoring
+// range. Point and spot lights are still from time0 to time1.
+pub fn load_gltf(path: &Path, time0: f64, time1: f64, rng: &mut Rng) -> std::result::Result<GltfScene, GltfError> {
+  let error = |message: String| GltfError { path: path.to_path_buf(), message };
+  let (document, buffers, images) = gltf::import(path).map_err(|e| error(e.to_string()))?;
+  let scene = document.default_scene().or_else(|| document.scenes().next()).ok_or_else(|| error("file has no scenes".to_string()))?;
+
+  let mut importer = Importer {
+    buffers: &buffers,
+    images: &images,
+    texels: HashMap::new(),
+    materials: HashMap::new(),
+    time0, time1,
+    rng,
+    scene: GltfScene { objects: Vec::new(), punctual: Vec::new(), camera: None }
+  };
+  for node in scene.nodes() {
+    importer.node(&node, &Mat4::identity()).map_err(error)?;
+  }
+  if importer.scene.objects.is_empty() { return Err(error("scene has no triangles".to_string())) }
+  Ok(importer.scene)
+}
+
+struct Importer<'a> {
+  buffers: &'a [gltf::buffer::Data],
+  images: &'a [gltf::image::Data],
+  // Decoded images, by index and whether they hold sRGB colors.
+  texels: HashMap<(usize, bool), Arc<Texels>>,
+  materials: HashMap<Option<usize>, Arc<dyn Material>>,
+  time0: f64,
+  time1: f64,
+  rng: &'a mut Rng,
+  scene: GltfScene
+}
+
+type Result<T> = std::result::Result<T, String>;
+
+impl Importer<'_> {
+  fn node(&mut self, node: &gltf::Node, parent: &Mat4) -> Result<()> {
+    let transform = *parent * Mat4::from_cols(node.transform().matrix().map(|col| col.map(f64::from)));
+    let describe = |kind: &str, index: usize, name: Option<&str>| match name {
+      Some(name) => format!("{kind} '{name}'"),
+      None => format!("{kind} {index}")
+    };
+
+    if let Some(mesh) = node.mesh() {
+      for primitive in mesh.primitives() {
+        self.primitive(&primitive, &transform).map_err(|e| format!("{}: {e}", describe("mesh", mesh.index(), mesh.name())))?;
+      }
+    }
+    if let Some(light) = node.light() {
+      let light = self.light(&light, &transform).map_err(|e| format!("{}: {e}", describe("light", light.index(), light.name())))?;
+      self.scene.punctual.push(light);
+    }
+    if let (None, Some(camera)) = (self.scene.camera, node.camera()) {
+      // Orthographic cameras have no equivalent, so are passed over.
+      if let Projection::Perspective(perspective) = camera.projection() {
+        let lookfrom = transform.transform_point(&Point3::zero());
+        self.scene.camera = Some(GltfCamera {
+          lookfrom,
+          lookat: lookfrom + unit_vector(transform.transform_vector(&Vec3::new(0.0, 0.0, -1.0))),
+          vup: transform.transform_vector(&Vec3::new(0.0, 1.0, 0.0)),
+          vfov: f64::from(perspective.yfov()).to_degrees(),
+          aspect_ratio: perspective.aspect_ratio().map(f64::from)
+        });
+      }
+    }
+
+    for child in node.children() {
+      self.node(&child, &transform)?;
+    }
+    Ok(())
+  }
+
+  fn primitive(&mut self, primitive: &gltf::Primitive, transform: &Mat4) -> Result<()> {
+    let buffers = self.buffers;
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data[..]));
+    let positions: Vec<Point3> = reader.read_positions().ok_or("primitive has no positions")?
+      .map(|p| transform.transform_point(&vec3(p)))
+      .collect();
+    let normals: Vec<Vec3> = match reader.read_normals() {
+      Some(normals) => normals.map(|n| unit_vector(transform.transform_normal(&vec3(n)))).collect(),
+      None => Vec::new()
+    };
+    let uvs: Vec<(f64, f64)> = match reader.read_tex_coords(tex_coord_set(&primitive.material())?) {
+      Some(uvs) => uvs.into_f32().map(|[u, v]| (f64::from(u), f64::from(v))).collect(),
+      None => Vec::new()
+    };
+    let order: Vec<usize> = match reader.read_indices() {
+      Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+      None => (0..positions.len()).collect()
+    };
+
+    let mut indices: Vec<[usize; 3]> = match primitive.mode() {
+      Mode::Triangles => order.chunks_exact(3).map(|f| [f[0], f[1], f[2]]).collect(),
+      Mode::TriangleStrip => (2..order.len())
+        .map(|i| if i % 2 == 0 { [order[i - 2], order[i - 1], order[i]] } else { [order[i - 1], order[i - 2], order[i]] })
+        .collect(),
+      Mode::TriangleFan => (2..order.len()).map(|i| [order[0], order[i - 1], order[i]]).collect(),
+      // Points and lines have no surface to render.
+      _ => return Ok(())
+    };
+    if indices.is_empty() { return Ok(()) }
+    // Mirroring transforms reverse the winding, which would turn faces
+    // without normals inside out.
+    if transform.determinant3() < 0.0 {
+      indices.iter_mut().for_each(|f| f.swap(1, 2));
+    }
+
+    let material = self.material(&primitive.material())?;
+    let mesh = MeshData { positions, normals, uvs, colors: Vec::new(), indices, materials: vec![material], face_materials: Vec::new() };
+    mesh.validate()?;
+    self.scene.objects.push(Box::new(TriangleMesh::new(Arc::new(mesh), self.rng)));
+    Ok(())
+  }
+
+  fn material(&mut self, material: &gltf::Material) -> Result<Arc<dyn Material>> {
+    if let Some(material) = self.materials.get(&material.index()) { return Ok(material.clone()) }
+
+    let pbr = material.pbr_metallic_roughness();
+    let [r, g, b, _] = pbr.base_color_factor();
+    let metallic = f64::from(pbr.metallic_factor());
+    let roughness = f64::from(pbr.roughness_factor());
+    // The specular parameter stands for the index of refraction.
+    let ior = f64::from(material.ior().unwrap_or(1.5));
+    let specular = ((ior - 1.0) / (ior + 1.0)).powi(2) / 0.08;
+    let emissive = vec3(material.emissive_factor()) * f64::from(material.emissive_strength().unwrap_or(1.0));
+
+    let principled = Principled {
+      base_color: self.texture(pbr.base_color_texture(), vec3([r, g, b]), None, true)?,
+      // Metalness is in the blue channel and roughness in the green.
+      metallic: self.texture(pbr.metallic_roughness_texture(), Color::new(metallic, metallic, metallic), Some(2), false)?,
+      roughness: self.texture(pbr.metallic_roughness_texture(), Color::new(roughness, roughness, roughness), Some(1), false)?,
+      specular: solid(specular),
+      transmission: match material.transmission() {
+        Some(transmission) => {
+          let factor = f64::from(transmission.transmission_factor());
+          self.texture(transmission.transmission_texture(), Color::new(factor, factor, factor), Some(0), false)?
+        },
+        None => solid(0.0)
+      },
+      clearcoat: solid(0.0),
+      clearcoat_roughness: solid(0.0),
+      sheen: solid(0.0),
+      emission: if emissive.near_zero() { None } else { Some(self.texture(material.emissive_texture(), emissive, None, true)?) }
+    };
+    let material_ref: Arc<dyn Material> = Arc::new(principled);
+    self.materials.insert(material.index(), material_ref.clone());
+    Ok(material_ref)
+  }
+
+  // factor alone if there is no texture, or else times the texture, or just
+  // its given channel for scalar parameters.
+  fn texture(&mut self, info: Option<gltf::texture::Info>, factor: Color, channel: Option<usize>, srgb: bool) -> Result<Arc<dyn Texture>> {
+    let Some(info) = info else { return Ok(Arc::new(SolidColor::new(factor))) };
+    let texture = info.texture();
+    let index = texture.source().index();
+    let texels = match self.texels.get(&(index, srgb)) {
+      Some(texels) => texels.clone(),
+      None => {
+        let data = self.images.get(index).ok_or_else(|| format!("image {index} was not loaded"))?;
+        let texels = Arc::new(Texels::new(data, srgb).ok_or_else(|| format!("image {index} is empty"))?);
+        self.texels.insert((index, srgb), texels.clone());
+        texels
+      }
+    };
+    let sampler = texture.sampler();
+    Ok(Arc::new(ImageMap { texels, wrap: [sampler.wrap_s(), sampler.wrap_t()], channel, factor }))
+  }
+
+  fn light(&self, light: &gltf::khr_lights_punctual::Light, transform: &Mat4) -> Result<Box<dyn PunctualLight>> {
+    let intensity = vec3(light.color()) * f64::from(light.intensity());
+    let position = transform.transform_point(&Point3::zero());
+    let direction = transform.transform_vector(&Vec3::new(0.0, 0.0, -1.0));
+    if direction.length() == 0.0 { return Err("light has no direction".to_string()) }
+
+    Ok(match light.kind() {
+      Kind::Directional => Box::new(DirectionalLight::new(direction, intensity)),
+      Kind::Point => Box::new(PointLight::new(position, position, self.time0, self.time1, intensity)),
+      Kind::Spot { inner_cone_angle, outer_cone_angle } => {
+        let (inner, outer) = (f64::from(inner_cone_angle).to_degrees(), f64::from(outer_cone_angle).to_degrees());
+        Box::new(SpotLight::new(position, position, self.time0, self.time1, direction, outer, inner, intensity))
+      }
+    })
+  }
+}
+
+// The texture coordinate set the material's textures read. Meshes keep just
+// one, so they all need to use the same.
+fn tex_coord_set(material: &gltf::Material) -> Result<u32> {
+  let pbr = material.pbr_metallic_roughness();
+  let mut sets = [
+    pbr.base_color_texture(),
+    pbr.metallic_roughness_texture(),
+    material.transmission().and_then(|t| t.transmission_texture()),
+    material.emissive_texture()
+  ].into_iter().flatten().map(|info| info.tex_coord());
+  let Some(set) = sets.next() else { return Ok(0) };
+  if sets.any(|other| other != set) { return Err("textures use different texture coordinate sets".to_string()) }
+  Ok(set)
+}
+
+fn vec3(v: [f32; 3]) -> Vec3 {
+  Vec3::new(f64::from(v[0]), f64::from(v[1]), f64::from(v[2]))
+}
+
+fn solid(value: f64) -> Arc<dyn Texture> {
+  Arc::new(SolidColor::new(Color::new(value, value, value)))
+}
+
+// A decoded image in linear values, with its rows from the top as glTF
+// texture coordinates expect.
+struct Texels {
+  width: usize,
+  height: usize,
+  texels: Vec<Color>
+}
+
+impl Texels {
+  fn new(data: &gltf::image::Data, srgb: bool) -> Option<Self> {
+    let (width, height) = (data.width as usize, data.height as usize);
+    if width == 0 || height == 0 { return None }
+
+    let (channels, size) = match data.format {
+      Format::R8 => (1, 1), Format::R8G8 => (2, 1), Format::R8G8B8 => (3, 1), Format::R8G8B8A8 => (4, 1),
+      Format::R16 => (1, 2), Format::R16G16 => (2, 2), Format::R16G16B16 => (3, 2), Format::R16G16B16A16 => (4, 2),
+      Format::R32G32B32FLOAT => (3, 4), Format::R32G32B32A32FLOAT => (4, 4)
+    };
+    let component = |bytes: &[u8]| {
+      let value = match size {
+        1 => f64::from(bytes[0]) / 255.0,
+        2 => f64::from(u16::from_ne_bytes([bytes[0], bytes[1]])) / 65535.0,
+        _ => return f64::from(f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+      };
+      if srgb { srgb_to_linear(value) } else { value }
+    };
+
+    // Grey images, with or without alpha, have the same value in every
+    // channel.
+    let texels = data.pixels.chunks_exact(channels * size).map(|pixel| {
+      let c = |i: usize| component(&pixel[i * size..(i + 1) * size]);
+      if channels < 3 { Color::new(c(0), c(0), c(0)) } else { Color::new(c(0), c(1), c(2)) }
+    }).collect::<Vec<_>>();
+    if texels.len() < width * height { return None }
+    Some(Self { width, height, texels })
+  }
+}
+
+struct ImageMap {
+  texels: Arc<Texels>,
+  wrap: [WrappingMode; 2],
+  channel: Option<usize>,
+  factor: Color
+}
+
+impl Texture for ImageMap {
+  fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+    let texels = &self.texels;
+    let i = wrap(u, self.wrap[0], texels.width);
+    let j = wrap(v, self.wrap[1], texels.height);
+    let texel = texels.texels[j * texels.width + i];
+    self.factor * match self.channel {
+      Some(c) => Color::new(texel[c], texel[c], texel[c]),
+      None => texel
+    }
+  }
+}
+
+fn wrap(x: f64, mode: WrappingMode, size: usize) -> usize {
+  let x = match mode {
+    WrappingMode::ClampToEdge => x.clamp(0.0, 1.0),
+    WrappingMode::Repeat => x.rem_euclid(1.0),
+    WrappingMode::MirroredRepeat => {
+      let x = x.rem_euclid(2.0);
+      if x > 1.0 { 2.0 - x } else { x }
+    }
+  };
+  ((x * size as f64) as usize).min(size - 1)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{ray::Ray, util::seeded_rng};
+
+  // One triangle in the z = 0 plane, used by a child node under a
+  // translated and scaled parent, and by a mirrored node.
+  const SCENE: &str = r#"{
+    "asset": { "version": "2.0" },
+    "scene": 0,
+    "scenes": [{ "nodes": [0, 2] }],
+    "nodes": [
+      { "translation": [10, 0, 0], "scale": [2, 2, 2], "children": [1] },
+      { "translation": [0, 0, 1], "mesh": 0 },
+      { "translation": [0, 5, 0], "scale": [-1, 1, 1], "mesh": 0 }
+    ],
+    "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 } }] }],
+    "accessors": [{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] }],
+    "bufferViews": [{ "buffer": 0, "byteLength": 36 }],
+    "buffers": [{ "byteLength": 36, "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAA" }]
+  }"#;
+
+  fn load(test: &str, source: &str) -> GltfScene {
+    let path = std::env::temp_dir().join(format!("raytracer-gltf-{test}-{}.gltf", std::process::id()));
+    std::fs::write(&path, source).unwrap();
+    let scene = load_gltf(&path, 0.0, 1.0, &mut seeded_rng(1));
+    std::fs::remove_file(&path).unwrap();
+    match scene {
+      Ok(scene) => scene,
+      Err(e) => panic!("{e}")
+    }
+  }
+
+  fn corners(object: &dyn Hittable) -> [[f64; 3]; 2] {
+    let bbox = object.bounding_box(0.0, 1.0).unwrap();
+    let round = |p: Point3| [p.x(), p.y(), p.z()].map(|x| (x * 100.0).round() / 100.0);
+    [round(bbox.min()), round(bbox.max())]
+  }
+
+  #[test]
+  fn node_transforms_are_baked_into_the_vertices() {
+    let scene = load("nodes", SCENE);
+    assert_eq!(scene.objects.len(), 2);
+    // The child's offset is scaled by its parent before the parent's.
+    assert_eq!(corners(scene.objects[0].as_ref()), [[10.0, 0.0, 2.0], [12.0, 2.0, 2.0]]);
+    assert_eq!(corners(scene.objects[1].as_ref()), [[-1.0, 5.0, 0.0], [0.0, 6.0, 0.0]]);
+  }
+
+  #[test]
+  fn mirrored_nodes_keep_their_faces_outward() {
+    let scene = load("mirror", SCENE);
+    let down = Vec3::new(0.0, 0.0, -1.0);
+    for (object, origin) in scene.objects.iter().zip([Point3::new(10.5, 0.5, 5.0), Point3::new(-0.25, 5.25, 5.0)]) {
+      let rec = object.hit(&Ray::new(origin, down, 0.0), 0.001, f64::INFINITY, &mut seeded_rng(2)).unwrap();
+      assert!(rec.front_face);
+    }
+  }
+
+  #[test]
+  fn scenes_without_triangles_are_rejected() {
+    let path = std::env::temp_dir().join(format!("raytracer-gltf-empty-{}.gltf", std::process::id()));
+    std::fs::write(&path, r#"{ "asset": { "version": "2.0" }, "scenes": [{ "nodes": [] }] }"#).unwrap();
+    let scene = load_gltf(&path, 0.0, 1.0, &mut seeded_rng(1));
+    std::fs::remove_file(&path).unwrap();
+    match scene {
+      Ok(_) => panic!("expected an error"),
+      Err(e) => assert_eq!(e.to_string(), format!("{}: scene has no triangles", path.display()))
+    }
+  }
+}
\ No newline at end of file
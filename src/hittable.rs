@@ -1,6 +1,6 @@
-use std::f64::{INFINITY, NEG_INFINITY};
+use std::sync::Arc;
 
-use crate::{vec3::{Point3, Vec3, dot}, ray::Ray, material::Material, aabb::AABB, util::{fmin, fmax}};
+use crate::{vec3::{Point3, Vec3, Color, dot, unit_vector}, ray::Ray, material::Material, aabb::AABB, mat4::Mat4, util::Rng};
 
 pub struct HitRecord<'a> {
   pub p: Point3,
@@ -9,6 +9,11 @@ pub struct HitRecord<'a> {
   pub t: f64,
   pub u: f64,
   pub v: f64,
+  // Direction along the surface in which u increases, lining up anisotropic
+  // materials; zero where there is none.
+  pub tangent: Vec3,
+  // Color interpolated from the vertices of meshes that have them.
+  pub color: Option<Color>,
   pub front_face: bool
 }
 
@@ -19,120 +24,261 @@ impl HitRecord<'_> {
   }
 }
 
-pub trait Hittable: Sync {
-  fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
-  fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB>;
+// A stretch of a ray inside a closed object, from the hit entering it to
+// the hit leaving it.
+pub struct Interval<'a> {
+  pub entry: HitRecord<'a>,
+  pub exit: HitRecord<'a>
 }
 
-pub struct Translate<H: Hittable> {
-  hittable: H,
-  offset: Vec3
+pub trait Hittable: Send + Sync {
+  fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut Rng) -> Option<HitRecord<'_>>;
+  fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB>;
+
+  // Box around the object once placed by to_world. By default that is the
+  // box around the corners of its own box, which is only tight for boxes
+  // that stay lined up with the axes.
+  fn transformed_bounding_box(&self, to_world: &Mat4, time0: f64, time1: f64) -> Option<AABB> {
+    self.bounding_box(time0, time1).map(|b| to_world.transform_bbox(&b))
+  }
+
+  // Where the whole line along r is inside the object, in order. Only
+  // closed objects, whose hits face outwards, have an inside. By default
+  // this steps through every hit, entering at those on the front and leaving
+  // at those on the back, so that overlapping parts merge.
+  fn intervals(&self, r: &Ray, rng: &mut Rng) -> Vec<Interval<'_>> {
+    let mut intervals = Vec::new();
+    let mut entry = None;
+    let mut depth = 0;
+    let mut t_min = f64::NEG_INFINITY;
+    while let Some(rec) = self.hit(r, t_min, f64::INFINITY, rng) {
+      if !rec.t.is_finite() { break }
+      t_min = rec.t + 1e-9*(1.0 + rec.t.abs());
+      if rec.front_face {
+        depth += 1;
+        if depth == 1 { entry = Some(rec) }
+      } else if depth > 0 {
+        depth -= 1;
+        if depth == 0 {
+          intervals.push(Interval { entry: entry.take().expect("entered before leaving"), exit: rec });
+        }
+      }
+    }
+    intervals
+  }
+
+  // Objects that emit light and can be sampled by direction from a point.
+  fn is_light(&self) -> bool { false }
+
+  // Solid angle density of random() choosing direction v from o.
+  fn pdf_value(&self, _o: &Point3, _v: &Vec3) -> f64 { 0.0 }
+
+  // Direction from o towards a point on the object.
+  fn random(&self, _o: &Point3, _u: (f64, f64)) -> Vec3 { Vec3::new(1.0, 0.0, 0.0) }
 }
 
-impl<H: Hittable> Translate<H> {
-  pub fn new(hittable: H, offset: Vec3) -> Self { Self { hittable, offset } }
+impl<H: Hittable + ?Sized> Hittable for Box<H> {
+  fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut Rng) -> Option<HitRecord<'_>> {
+    (**self).hit(r, t_min, t_max, rng)
+  }
+
+  fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
+    (**self).bounding_box(time0, time1)
+  }
+
+  fn transformed_bounding_box(&self, to_world: &Mat4, time0: f64, time1: f64) -> Option<AABB> {
+    (**self).transformed_bounding_box(to_world, time0, time1)
+  }
+
+  fn intervals(&self, r: &Ray, rng: &mut Rng) -> Vec<Interval<'_>> {
+    (**self).intervals(r, rng)
+  }
+
+  fn is_light(&self) -> bool { (**self).is_light() }
+  fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 { (**self).pdf_value(o, v) }
+  fn random(&self, o: &Point3, u: (f64, f64)) -> Vec3 { (**self).random(o, u) }
 }
 
-impl<H: Hittable> Hittable for Translate<H> {
-  fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-    let moved_r = Ray::new(r.origin() - self.offset, r.direction(), r.time());
-    if let Some(mut rec) = self.hittable.hit(&moved_r, t_min, t_max) {
-      rec.p += self.offset;
-      let normal = rec.normal;
-      rec.set_face_normal(&moved_r, &normal);
-
-      Some(rec)
-    } else {
-      None
-    }
+impl<H: Hittable + ?Sized> Hittable for Arc<H> {
+  fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut Rng) -> Option<HitRecord<'_>> {
+    (**self).hit(r, t_min, t_max, rng)
   }
 
   fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
-    if let Some(b) = self.hittable.bounding_box(time0, time1) {
-      Some(AABB::new(b.min() + self.offset, b.max() + self.offset))
-    } else {
-      None
-    }
+    (**self).bounding_box(time0, time1)
   }
+
+  fn transformed_bounding_box(&self, to_world: &Mat4, time0: f64, time1: f64) -> Option<AABB> {
+    (**self).transformed_bounding_box(to_world, time0, time1)
+  }
+
+  fn intervals(&self, r: &Ray, rng: &mut Rng) -> Vec<Interval<'_>> {
+    (**self).intervals(r, rng)
+  }
+
+  fn is_light(&self) -> bool { (**self).is_light() }
+  fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 { (**self).pdf_value(o, v) }
+  fn random(&self, o: &Point3, u: (f64, f64)) -> Vec3 { (**self).random(o, u) }
 }
 
-pub struct RotateY<H: Hittable> {
+// Places a hittable with an affine transform: rays are carried into its own
+// space to be hit, and the hit carried back out.
+pub struct Transform<H: Hittable> {
   hittable: H,
-  sin_theta: f64,
-  cos_theta: f64,
-  bbox: Option<AABB>
+  to_world: Mat4,
+  to_object: Mat4
 }
 
-impl<H: Hittable> RotateY<H> {
-  pub fn new(hittable: H, angle: f64) -> Self {
-    let radians = angle.to_radians();
-    let sin_theta = (radians).sin();
-    let cos_theta = (radians).cos();
-    if let Some(bbox) = hittable.bounding_box(0.0, 1.0) {
-
-      let mut min = Point3::new(INFINITY, INFINITY, INFINITY);
-      let mut max = Point3::new(NEG_INFINITY, NEG_INFINITY, NEG_INFINITY);
-
-      for i in 0..2 {
-        for j in 0..2 {
-          for k in 0..2 {
-            let x = (i as f64)*bbox.max().x() + (1.0-i as f64)*bbox.min().x();
-            let y = (j as f64)*bbox.max().y() + (1.0-j as f64)*bbox.min().y();
-            let z = (k as f64)*bbox.max().z() + (1.0-k as f64)*bbox.min().z();
-
-            let x = cos_theta*x + sin_theta*z;
-            let z = -sin_theta*x + cos_theta*z;
-
-            let tester = Vec3::new(x, y, z);
-
-            for c in 0..3 {
-              min[c] = fmin(min[c], tester[c]);
-              max[c] = fmax(max[c], tester[c]);
-            }
-          }
-        }
-      }
+impl<H: Hittable> Transform<H> {
+  // to_world must be invertible.
+  pub fn new(hittable: H, to_world: Mat4) -> Self {
+    let to_object = to_world.inverse().expect("transform should be invertible");
+    Self { hittable, to_world, to_object }
+  }
 
-      Self { hittable, sin_theta, cos_theta, bbox: Some(AABB::new(min, max)) }
-    } else {
-      Self { hittable, sin_theta, cos_theta, bbox: None }
-    }
+  pub fn translate(hittable: H, offset: Vec3) -> Self {
+    Self::new(hittable, Mat4::translation(offset))
+  }
+
+  pub fn rotate_y(hittable: H, angle: f64) -> Self {
+    Self::new(hittable, Mat4::rotation(Vec3::new(0.0, 1.0, 0.0), angle))
   }
 }
 
-impl<H: Hittable> Hittable for RotateY<H> {
-  fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-    let mut origin = r.origin();
-    let mut direction = r.direction();
+impl<H: Hittable> Transform<H> {
+  // The direction isn't renormalized, so distances along both rays match.
+  fn to_object_ray(&self, r: &Ray) -> Ray {
+    Ray::new(self.to_object.transform_point(&r.origin()), self.to_object.transform_vector(&r.direction()), r.time())
+      .with_wavelength(r.wavelength())
+  }
 
-    origin[0] = self.cos_theta*r.origin()[0] - self.sin_theta*r.origin()[2];
-    origin[2] = self.sin_theta*r.origin()[0] + self.cos_theta*r.origin()[2];
+  fn to_world_record<'a>(&self, mut rec: HitRecord<'a>) -> HitRecord<'a> {
+    rec.p = self.to_world.transform_point(&rec.p);
+    // The normal already faces the ray, which transforming preserves.
+    rec.normal = unit_vector(self.to_world.transform_normal(&rec.normal));
+    rec.tangent = self.to_world.transform_vector(&rec.tangent);
+    rec
+  }
+}
 
-    direction[0] = self.cos_theta*r.direction()[0] - self.sin_theta*r.direction()[2];
-    direction[2] = self.sin_theta*r.direction()[0] + self.cos_theta*r.direction()[2];
+impl<H: Hittable> Hittable for Transform<H> {
+  fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut Rng) -> Option<HitRecord<'_>> {
+    let rec = self.hittable.hit(&self.to_object_ray(r), t_min, t_max, rng)?;
+    Some(self.to_world_record(rec))
+  }
 
-    let rotated_r = Ray::new(origin, direction, r.time());
+  fn intervals(&self, r: &Ray, rng: &mut Rng) -> Vec<Interval<'_>> {
+    self.hittable.intervals(&self.to_object_ray(r), rng).into_iter()
+      .map(|i| Interval { entry: self.to_world_record(i.entry), exit: self.to_world_record(i.exit) })
+      .collect()
+  }
 
-    if let Some(mut rec) = self.hittable.hit(&rotated_r, t_min, t_max) {
-      let mut p = rec.p;
-      let mut normal = rec.normal;
+  fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
+    self.hittable.transformed_bounding_box(&self.to_world, time0, time1)
+  }
 
-      p[0] = self.cos_theta*rec.p[0] + self.sin_theta*rec.p[2];
-      p[2] = -self.sin_theta*rec.p[0] + self.cos_theta*rec.p[2];
+  // Nested transforms compose, so the innermost object bounds itself under
+  // all of them at once.
+  fn transformed_bounding_box(&self, to_world: &Mat4, time0: f64, time1: f64) -> Option<AABB> {
+    self.hittable.transformed_bounding_box(&(*to_world * self.to_world), time0, time1)
+  }
 
-      normal[0] = self.cos_theta*rec.normal[0] + self.sin_theta*rec.normal[2];
-      normal[2] = self.sin_theta*rec.normal[0] + self.cos_theta*rec.normal[2];
+  fn is_light(&self) -> bool { self.hittable.is_light() }
 
-      rec.p = p;
-      rec.set_face_normal(&rotated_r, &normal);
+  fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+    let w = self.to_object.transform_vector(&unit_vector(*v));
+    let pdf = self.hittable.pdf_value(&self.to_object.transform_point(o), &w);
+    // Solid angles change by how much the transform stretches directions
+    // around v.
+    pdf * self.to_object.determinant3().abs() / w.length().powi(3)
+  }
 
-      Some(rec)
-    } else {
-      None
-    }
+  fn random(&self, o: &Point3, u: (f64, f64)) -> Vec3 {
+    self.to_world.transform_vector(&self.hittable.random(&self.to_object.transform_point(o), u))
   }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{sphere::Sphere, cube::Cube, revolution::{Paraboloid, Torus}, material::Lambertian, texture::SolidColor, util::seeded_rng};
+
+  fn grey() -> Lambertian<SolidColor> { Lambertian::new(SolidColor::new(Color::new(0.5, 0.5, 0.5))) }
 
-  fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
-    self.bbox
+  fn assert_box(bbox: &AABB, min: Point3, max: Point3) {
+    assert!((bbox.min() - min).length() < 1e-9 && (bbox.max() - max).length() < 1e-9, "{:?} != {min:?}..{max:?}", bbox);
+  }
+
+  #[test]
+  fn rotated_box_bounds() {
+    // Rotating a box that isn't square by 45 degrees about Y: every corner
+    // moves on both X and Z, which is where computing Z from the already
+    // rotated X went wrong.
+    let cube = Cube::new(Point3::new(0.0, 0.0, 0.0), Point3::new(2.0, 1.0, 1.0), grey());
+    let bbox = Transform::rotate_y(cube, 45.0).bounding_box(0.0, 1.0).unwrap();
+    let h = 0.5f64.sqrt();
+    assert_box(&bbox, Point3::new(0.0, 0.0, -2.0*h), Point3::new(3.0*h, 1.0, h));
+  }
+
+  #[test]
+  fn rotated_sphere_and_torus_bounds_are_tight() {
+    let rotation = Mat4::rotation(Vec3::new(1.0, 0.0, 0.0), 90.0);
+    let sphere = Transform::new(Sphere::new(Point3::new(1.0, 0.0, 0.0), 1.0, grey()), rotation);
+    assert_box(&sphere.bounding_box(0.0, 1.0).unwrap(), Point3::new(0.0, -1.0, -1.0), Point3::new(2.0, 1.0, 1.0));
+
+    // Stood up on its edge the ring spans X and Y and is tube-thick in Z.
+    let torus = Transform::new(Torus::new(Point3::zero(), 2.0, 0.5, grey()), rotation);
+    assert_box(&torus.bounding_box(0.0, 1.0).unwrap(), Point3::new(-2.5, -2.5, -0.5), Point3::new(2.5, 2.5, 0.5));
+  }
+
+  #[test]
+  fn tilted_paraboloid_bounds_reach_past_its_rim() {
+    // Tipped 45 degrees about Z, the bowl's side bulges out a quarter of
+    // the rim's reach beyond the apex on the side the rim leans away from.
+    let paraboloid = Paraboloid::new(Point3::zero(), 1.0, 1.0, true, grey());
+    let bbox = Transform::new(paraboloid, Mat4::rotation(Vec3::new(0.0, 0.0, 1.0), 45.0)).bounding_box(0.0, 1.0).unwrap();
+    let h = 0.5f64.sqrt();
+    assert_box(&bbox, Point3::new(-2.0*h, -h/4.0, -1.0), Point3::new(h/4.0, 2.0*h, 1.0));
+  }
+
+  #[test]
+  fn normals_under_non_uniform_scale() {
+    // The unit sphere stretched to x^2/4 + y^2 + z^2 = 1, whose normals go
+    // as (x/4, y, z) rather than as the stretched sphere normal.
+    let ellipsoid = Transform::new(Sphere::new(Point3::zero(), 1.0, grey()), Mat4::scale(Vec3::new(2.0, 1.0, 1.0)));
+    let mut rng = seeded_rng(0);
+    let rec = ellipsoid.hit(&Ray::new(Point3::new(1.0, 0.5, 5.0), Vec3::new(0.0, 0.0, -2.0), 0.0), 0.001, f64::INFINITY, &mut rng).unwrap();
+    let z = 0.5f64.sqrt();
+    assert!((rec.p - Point3::new(1.0, 0.5, z)).length() < 1e-9);
+    assert!((rec.t - (5.0 - z) / 2.0).abs() < 1e-9);
+    assert!((rec.normal - unit_vector(Vec3::new(0.25, 0.5, z))).length() < 1e-9);
+    assert!(rec.front_face);
+  }
+
+  #[test]
+  fn nested_transforms_compose() {
+    let inner = Mat4::scale(Vec3::new(1.0, 3.0, 0.5)) * Mat4::rotation(Vec3::new(0.0, 0.0, 1.0), 30.0);
+    let outer = Mat4::translation(Vec3::new(2.0, -1.0, 0.5)) * Mat4::euler(Vec3::new(15.0, 40.0, -20.0));
+    let nested = Transform::new(Transform::new(Sphere::new(Point3::new(0.5, 0.0, 0.0), 1.0, grey()), inner), outer);
+    let flat = Transform::new(Sphere::new(Point3::new(0.5, 0.0, 0.0), 1.0, grey()), outer * inner);
+
+    let (a, b) = (nested.bounding_box(0.0, 1.0).unwrap(), flat.bounding_box(0.0, 1.0).unwrap());
+    assert_box(&a, b.min(), b.max());
+
+    let mut rng = seeded_rng(1);
+    let mut hits = 0;
+    for i in 0..1000 {
+      let target = Point3::new(2.0, -1.0, 0.5) + Vec3::new((i % 10) as f64 * 0.3 - 1.5, (i / 10 % 10) as f64 * 0.6 - 3.0, (i / 100) as f64 * 0.2 - 1.0);
+      let r = Ray::new(Point3::new(-5.0, 4.0, 6.0), target - Point3::new(-5.0, 4.0, 6.0), 0.0);
+      match (nested.hit(&r, 0.001, f64::INFINITY, &mut rng), flat.hit(&r, 0.001, f64::INFINITY, &mut rng)) {
+        (Some(x), Some(y)) => {
+          hits += 1;
+          assert!((x.t - y.t).abs() < 1e-9 && (x.normal - y.normal).length() < 1e-9);
+        },
+        (None, None) => {},
+        _ => panic!("nested and composed transforms disagree")
+      }
+    }
+    assert!(hits > 100);
   }
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-use crate::{hittable::{Hittable, HitRecord}, aabb::AABB};
+use crate::{hittable::{Hittable, HitRecord}, aabb::AABB, util::Rng, vec3::{Point3, Vec3}};
 
 pub struct HittableList {
   objects: Vec<Box<dyn Hittable>>
@@ -12,12 +12,12 @@ impl HittableList {
 }
 
 impl Hittable for HittableList {
-  fn hit(&self, r: &crate::ray::Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+  fn hit(&self, r: &crate::ray::Ray, t_min: f64, t_max: f64, rng: &mut Rng) -> Option<HitRecord<'_>> {
     let mut hit_record: Option<HitRecord> = None;
     let mut closest_so_far = t_max;
 
     for object in &self.objects {
-      if let Some(temp_rec) = object.hit(r, t_min, closest_so_far) {
+      if let Some(temp_rec) = object.hit(r, t_min, closest_so_far, rng) {
         closest_so_far = temp_rec.t;
         hit_record = Some(temp_rec);
       }
@@ -42,4 +42,21 @@ impl Hittable for HittableList {
 
     output_box
   }
+
+  fn is_light(&self) -> bool {
+    self.objects.iter().any(|object| object.is_light())
+  }
+
+  fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+    if self.objects.is_empty() { return 0.0 }
+    let sum: f64 = self.objects.iter().map(|object| object.pdf_value(o, v)).sum();
+    sum / self.objects.len() as f64
+  }
+
+  fn random(&self, o: &Point3, u: (f64, f64)) -> Vec3 {
+    // Pick an object with the first dimension and reuse what's left of it.
+    let n = self.objects.len() as f64;
+    let index = ((u.0 * n) as usize).min(self.objects.len() - 1);
+    self.objects[index].random(o, ((u.0 * n - index as f64).min(1.0 - f64::EPSILON), u.1))
+  }
 }
\ No newline at end of file
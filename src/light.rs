@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use crate::{hittable::Hittable, environment::Environment, punctual::PunctualLight, vec3::{Point3, Vec3}};
+
+// Emissive objects, and the environment if it can be importance sampled, that
+// the integrator can sample directly. Each light is chosen with equal
+// probability. Punctual lights can't be hit, so they are kept apart and all
+// evaluated at every bounce.
+pub struct Lights {
+  lights: Vec<Arc<dyn Hittable>>,
+  environment: Option<Arc<dyn Environment>>,
+  punctual: Vec<Box<dyn PunctualLight>>
+}
+
+impl Lights {
+  // Splits out the objects that can be sampled as lights, keeping them in the
+  // returned object list as well so they are still visible to rays.
+  pub fn collect(objects: Vec<Box<dyn Hittable>>, environment: &Arc<dyn Environment>, punctual: Vec<Box<dyn PunctualLight>>) -> (Vec<Box<dyn Hittable>>, Lights) {
+    let mut lights = Vec::new();
+    let objects = objects.into_iter().map(|object| {
+      if object.is_light() {
+        let light: Arc<dyn Hittable> = Arc::from(object);
+        lights.push(light.clone());
+        Box::new(light) as Box<dyn Hittable>
+      } else {
+        object
+      }
+    }).collect();
+
+    let environment = if environment.is_light() { Some(environment.clone()) } else { None };
+    (objects, Lights { lights, environment, punctual })
+  }
+
+  fn len(&self) -> usize { self.lights.len() + self.environment.is_some() as usize }
+
+  pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+  pub fn punctual(&self) -> &[Box<dyn PunctualLight>] { &self.punctual }
+
+  pub fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+    if self.is_empty() { return 0.0 }
+    let mut sum: f64 = self.lights.iter().map(|light| light.pdf_value(o, v)).sum();
+    if let Some(environment) = &self.environment { sum += environment.pdf_value(v) }
+    sum / self.len() as f64
+  }
+
+  pub fn random(&self, o: &Point3, uc: f64, u: (f64, f64)) -> Vec3 {
+    let index = ((uc * self.len() as f64) as usize).min(self.len() - 1);
+    match self.lights.get(index) {
+      Some(light) => light.random(o, u),
+      None => self.environment.as_ref().expect("index past the lights is the environment").random(u)
+    }
+  }
+}
\ No newline at end of file
@@ -0,0 +1,262 @@
+use std::ops;
+
+use crate::{vec3::{Point3, Vec3, unit_vector}, aabb::AABB};
+
+// Affine transform as a 4x4 matrix acting on column vectors, stored by rows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4 {
+  m: [[f64; 4]; 4]
+}
+
+impl Mat4 {
+  pub fn identity() -> Self {
+    let mut m = [[0.0; 4]; 4];
+    for i in 0..4 { m[i][i] = 1.0 }
+    Self { m }
+  }
+
+  pub fn from_rows(rows: [[f64; 4]; 4]) -> Self {
+    Self { m: rows }
+  }
+
+  // From columns, the order glTF and most graphics APIs store matrices in.
+  pub fn from_cols(cols: [[f64; 4]; 4]) -> Self {
+    let mut m = [[0.0; 4]; 4];
+    for i in 0..4 {
+      for j in 0..4 { m[i][j] = cols[j][i] }
+    }
+    Self { m }
+  }
+
+  fn linear(a: [[f64; 3]; 3]) -> Self {
+    let mut m = Self::identity();
+    for i in 0..3 {
+      for j in 0..3 { m.m[i][j] = a[i][j] }
+    }
+    m
+  }
+
+  pub fn translation(offset: Vec3) -> Self {
+    let mut m = Self::identity();
+    for i in 0..3 { m.m[i][3] = offset[i] }
+    m
+  }
+
+  pub fn scale(factors: Vec3) -> Self {
+    Self::linear([[factors.x(), 0.0, 0.0], [0.0, factors.y(), 0.0], [0.0, 0.0, factors.z()]])
+  }
+
+  // Counterclockwise by angle degrees looking down axis towards the origin.
+  pub fn rotation(axis: Vec3, angle: f64) -> Self {
+    let a = unit_vector(axis);
+    let (x, y, z) = (a.x(), a.y(), a.z());
+    let (s, c) = angle.to_radians().sin_cos();
+    let t = 1.0 - c;
+    Self::linear([
+      [t*x*x + c, t*x*y - s*z, t*x*z + s*y],
+      [t*x*y + s*z, t*y*y + c, t*y*z - s*x],
+      [t*x*z - s*y, t*y*z + s*x, t*z*z + c]
+    ])
+  }
+
+  // Degrees about the X axis, then Y, then Z, each about the fixed world
+  // axes.
+  pub fn euler(angles: Vec3) -> Self {
+    Self::rotation(Vec3::new(0.0, 0.0, 1.0), angles.z())
+      * Self::rotation(Vec3::new(0.0, 1.0, 0.0), angles.y())
+      * Self::rotation(Vec3::new(1.0, 0.0, 0.0), angles.x())
+  }
+
+  // Rotation by the quaternion x, y, z, w, which needn't be normalized.
+  pub fn quaternion(q: [f64; 4]) -> Self {
+    let norm = q.iter().map(|c| c*c).sum::<f64>().sqrt();
+    let [x, y, z, w] = q.map(|c| c / norm);
+    Self::linear([
+      [1.0 - 2.0*(y*y + z*z), 2.0*(x*y - z*w), 2.0*(x*z + y*w)],
+      [2.0*(x*y + z*w), 1.0 - 2.0*(x*x + z*z), 2.0*(y*z - x*w)],
+      [2.0*(x*z - y*w), 2.0*(y*z + x*w), 1.0 - 2.0*(x*x + y*y)]
+    ])
+  }
+
+  pub fn is_affine(&self) -> bool {
+    self.m[3] == [0.0, 0.0, 0.0, 1.0]
+  }
+
+  // Inverse of an affine transform, or None if it collapses space onto a
+  // plane or worse.
+  pub fn inverse(&self) -> Option<Self> {
+    let det = self.determinant3();
+    if det == 0.0 || !det.is_finite() { return None }
+    let m = &self.m;
+    // The inverse of the linear part is its transposed cofactor matrix over
+    // the determinant.
+    let mut a = [[0.0; 3]; 3];
+    for i in 0..3 {
+      for j in 0..3 { a[j][i] = cofactor(m, i, j) / det }
+    }
+    let mut inverse = Self::linear(a);
+    let t = inverse.transform_vector(&Vec3::new(m[0][3], m[1][3], m[2][3]));
+    for i in 0..3 { inverse.m[i][3] = -t[i] }
+    Some(inverse)
+  }
+
+  // The smallest box around the transformed corners of bbox. That holds
+  // everything that was inside it, but once rotated is looser than the box
+  // around the object itself; objects that can do better override
+  // Hittable::transformed_bounding_box.
+  pub fn transform_bbox(&self, bbox: &AABB) -> AABB {
+    let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+    let mut max = Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for corner in 0..8 {
+      let pick = |axis: usize| if corner & (1 << axis) == 0 { bbox.min()[axis] } else { bbox.max()[axis] };
+      let p = self.transform_point(&Point3::new(pick(0), pick(1), pick(2)));
+      for c in 0..3 {
+        min[c] = min[c].min(p[c]);
+        max[c] = max[c].max(p[c]);
+      }
+    }
+    AABB::new(min, max)
+  }
+
+  // Exact box around a transformed sphere: along each axis it reaches as far
+  // as the row of the linear part for that axis is long.
+  pub fn transform_sphere_bbox(&self, center: &Point3, radius: f64) -> AABB {
+    let c = self.transform_point(center);
+    let reach = Vec3::new(self.row(0).length(), self.row(1).length(), self.row(2).length()) * radius;
+    AABB::new(c - reach, c + reach)
+  }
+
+  // Exact box around a transformed circle lying in the XZ plane.
+  pub fn transform_circle_bbox(&self, center: &Point3, radius: f64) -> AABB {
+    let c = self.transform_point(center);
+    let reach = |i: usize| {
+      let row = self.row(i);
+      radius * (row.x()*row.x() + row.z()*row.z()).sqrt()
+    };
+    let reach = Vec3::new(reach(0), reach(1), reach(2));
+    AABB::new(c - reach, c + reach)
+  }
+
+  fn row(&self, i: usize) -> Vec3 {
+    Vec3::new(self.m[i][0], self.m[i][1], self.m[i][2])
+  }
+
+  pub fn transform_point(&self, p: &Point3) -> Point3 {
+    let m = &self.m;
+    self.transform_vector(p) + Vec3::new(m[0][3], m[1][3], m[2][3])
+  }
+
+  pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+    let m = &self.m;
+    Vec3::new(
+      m[0][0]*v.x() + m[0][1]*v.y() + m[0][2]*v.z(),
+      m[1][0]*v.x() + m[1][1]*v.y() + m[1][2]*v.z(),
+      m[2][0]*v.x() + m[2][1]*v.y() + m[2][2]*v.z()
+    )
+  }
+
+  // Transforms a surface normal by the inverse transpose of the linear part,
+  // leaving it unnormalized. The cofactor matrix is the inverse transpose
+  // scaled by the determinant, whose sign is taken back out so normals keep
+  // pointing the same way through mirroring transforms.
+  pub fn transform_normal(&self, n: &Vec3) -> Vec3 {
+    let c = |i: usize, j: usize| cofactor(&self.m, i, j);
+    let sign = self.determinant3().signum();
+    Vec3::new(
+      c(0, 0)*n.x() + c(0, 1)*n.y() + c(0, 2)*n.z(),
+      c(1, 0)*n.x() + c(1, 1)*n.y() + c(1, 2)*n.z(),
+      c(2, 0)*n.x() + c(2, 1)*n.y() + c(2, 2)*n.z()
+    ) * sign
+  }
+
+  // Determinant of the linear part; negative if the transform mirrors.
+  pub fn determinant3(&self) -> f64 {
+    let m = &self.m;
+    m[0][0]*(m[1][1]*m[2][2] - m[1][2]*m[2][1])
+      - m[0][1]*(m[1][0]*m[2][2] - m[1][2]*m[2][0])
+      + m[0][2]*(m[1][0]*m[2][1] - m[1][1]*m[2][0])
+  }
+}
+
+impl ops::Mul for Mat4 {
+  type Output = Mat4;
+
+  fn mul(self, rhs: Mat4) -> Mat4 {
+    let mut m = [[0.0; 4]; 4];
+    for i in 0..4 {
+      for j in 0..4 {
+        m[i][j] = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+      }
+    }
+    Mat4 { m }
+  }
+}
+
+// Cofactor of row i and column j of the linear part.
+fn cofactor(m: &[[f64; 4]; 4], i: usize, j: usize) -> f64 {
+  let (i0, i1, j0, j1) = ((i + 1) % 3, (i + 2) % 3, (j + 1) % 3, (j + 2) % 3);
+  m[i0][j0]*m[i1][j1] - m[i0][j1]*m[i1][j0]
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn assert_near(a: &Mat4, b: &Mat4) {
+    for i in 0..4 {
+      for j in 0..4 { assert!((a.m[i][j] - b.m[i][j]).abs() < 1e-12, "{a:?} != {b:?}") }
+    }
+  }
+
+  #[test]
+  fn inverse_round_trips() {
+    let transforms = [
+      Mat4::translation(Vec3::new(1.0, -2.0, 3.0)),
+      Mat4::scale(Vec3::new(2.0, 0.5, -3.0)),
+      Mat4::rotation(Vec3::new(1.0, 2.0, 3.0), 37.0),
+      Mat4::euler(Vec3::new(10.0, 20.0, 30.0)) * Mat4::scale(Vec3::new(1.0, 4.0, 0.25)),
+      Mat4::translation(Vec3::new(5.0, 0.0, -1.0)) * Mat4::quaternion([0.1, 0.2, 0.3, 0.9]) * Mat4::scale(Vec3::new(3.0, 3.0, 3.0)),
+      Mat4::from_rows([[1.0, 2.0, 0.0, 4.0], [0.0, 1.0, 3.0, 5.0], [2.0, 0.0, 1.0, 6.0], [0.0, 0.0, 0.0, 1.0]])
+    ];
+    for m in transforms {
+      let inverse = m.inverse().unwrap();
+      assert_near(&(m * inverse), &Mat4::identity());
+      assert_near(&(inverse * m), &Mat4::identity());
+    }
+  }
+
+  #[test]
+  fn flattening_transforms_have_no_inverse() {
+    assert!(Mat4::scale(Vec3::new(1.0, 0.0, 1.0)).inverse().is_none());
+  }
+
+  #[test]
+  fn rotation_turns_counterclockwise() {
+    // Looking down +Y, a quarter turn takes +X to -Z.
+    let p = Mat4::rotation(Vec3::new(0.0, 1.0, 0.0), 90.0).transform_point(&Point3::new(1.0, 0.0, 0.0));
+    assert!((p - Point3::new(0.0, 0.0, -1.0)).length() < 1e-12);
+  }
+
+  #[test]
+  fn sphere_box_is_tight() {
+    let m = Mat4::translation(Vec3::new(1.0, 2.0, 3.0)) * Mat4::rotation(Vec3::new(1.0, 1.0, 0.0), 30.0) * Mat4::scale(Vec3::new(2.0, 1.0, 0.5));
+    let bbox = m.transform_sphere_bbox(&Point3::new(0.5, 0.0, 0.0), 1.5);
+    // Sample the sphere densely; the extremes should reach the box.
+    let (mut min, mut max) = (Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY), Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY));
+    for i in 0..=200 {
+      for j in 0..400 {
+        let (theta, phi) = (std::f64::consts::PI * i as f64 / 200.0, std::f64::consts::TAU * j as f64 / 400.0);
+        let n = Vec3::new(theta.sin()*phi.cos(), theta.cos(), theta.sin()*phi.sin());
+        let p = m.transform_point(&(Point3::new(0.5, 0.0, 0.0) + 1.5*n));
+        for c in 0..3 {
+          assert!(p[c] >= bbox.min()[c] - 1e-9 && p[c] <= bbox.max()[c] + 1e-9);
+          min[c] = min[c].min(p[c]);
+          max[c] = max[c].max(p[c]);
+        }
+      }
+    }
+    for c in 0..3 {
+      assert!((min[c] - bbox.min()[c]).abs() < 1e-3 && (max[c] - bbox.max()[c]).abs() < 1e-3);
+    }
+  }
+}
\ No newline at end of file
@@ -1,9 +1,56 @@
-use std::f64;
-use crate::{ray::Ray, hittable::HitRecord, vec3::{Color, Vec3, reflect, unit_vector, dot, refract, Point3}, util::{random_double, fmin}, texture::{Texture, SolidColor}};
+use std::{f64::{self, consts::PI}, sync::Arc};
+use crate::{ray::Ray, hittable::HitRecord, vec3::{Color, Vec3, reflect, unit_vector, dot, refract, Point3}, util::{fmin, fmax}, sampler::{sample_unit_sphere, sample_unit_ball}, texture::{Texture, SolidColor}, onb::Onb, microfacet::{TrowbridgeReitz, reflect_about, refract_through, half_vector, fresnel_conductor, fresnel_dielectric}};
 
-pub trait Material: Sync {
-  fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)>;
+pub struct ScatterRecord {
+  pub attenuation: Color,
+  pub scattered: Ray,
+  // Specular bounces can't be evaluated for an arbitrary direction, so they
+  // are skipped by light sampling.
+  pub is_specular: bool
+}
+
+pub trait Material: Send + Sync {
+  fn scatter(&self, r_in: &Ray, rec: &HitRecord, uc: f64, u: (f64, f64)) -> Option<ScatterRecord>;
   fn emitted(&self, _u: f64, _v: f64, _p: &Point3) -> Color { Color::zero() }
+  fn is_emissive(&self) -> bool { false }
+
+  // BSDF times cosine for light arriving from direction, used when sampling
+  // lights directly.
+  fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> Color { Color::zero() }
+
+  // Solid angle density of scatter choosing direction, used to weight light
+  // sampling against BSDF sampling.
+  fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> f64 { 0.0 }
+
+  // Whether the scattered direction depends on the ray's wavelength, so a
+  // spectral path can only follow its hero wavelength past this surface.
+  fn is_dispersive(&self) -> bool { false }
+}
+
+impl<M: Material + ?Sized> Material for Arc<M> {
+  fn scatter(&self, r_in: &Ray, rec: &HitRecord, uc: f64, u: (f64, f64)) -> Option<ScatterRecord> {
+    (**self).scatter(r_in, rec, uc, u)
+  }
+
+  fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+    (**self).emitted(u, v, p)
+  }
+
+  fn is_emissive(&self) -> bool {
+    (**self).is_emissive()
+  }
+
+  fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+    (**self).eval(r_in, rec, direction)
+  }
+
+  fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+    (**self).scattering_pdf(r_in, rec, direction)
+  }
+
+  fn is_dispersive(&self) -> bool {
+    (**self).is_dispersive()
+  }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -20,18 +67,27 @@ impl Lambertian<SolidColor> {
 }
 
 impl<T: Texture> Material for Lambertian<T> {
-  fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-    let mut scatter_direction = rec.normal + Vec3::random_unit_vector();
+  fn scatter(&self, r_in: &Ray, rec: &HitRecord, _uc: f64, u: (f64, f64)) -> Option<ScatterRecord> {
+    let mut scatter_direction = rec.normal + sample_unit_sphere(u);
 
     // Catch degenerate scatter direction
     if scatter_direction.near_zero() {
       scatter_direction = rec.normal;
     }
 
-    Some((
-      self.albedo.value(rec.u, rec.v, &rec.p),
-      Ray::new(rec.p, scatter_direction, r_in.time())
-    ))
+    Some(ScatterRecord {
+      attenuation: self.albedo.value_at(rec),
+      scattered: Ray::new(rec.p, scatter_direction, r_in.time()),
+      is_specular: false
+    })
+  }
+
+  fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+    self.albedo.value_at(rec) * self.scattering_pdf(r_in, rec, direction)
+  }
+
+  fn scattering_pdf(&self, _r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+    fmax(dot(&rec.normal, &unit_vector(*direction)), 0.0) / PI
   }
 }
 
@@ -50,21 +106,170 @@ impl Metal<SolidColor> {
 }
 
 impl<T: Texture> Material for Metal<T> {
-  fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+  fn scatter(&self, r_in: &Ray, rec: &HitRecord, uc: f64, u: (f64, f64)) -> Option<ScatterRecord> {
       let reflected = reflect(&unit_vector(r_in.direction()), &rec.normal);
-      let scattered = Ray::new(rec.p, reflected + self.fuzz*Vec3::random_in_unit_sphere(), r_in.time());
+      let scattered = Ray::new(rec.p, reflected + self.fuzz*sample_unit_ball(u, uc), r_in.time());
 
       if dot(&scattered.direction(), &rec.normal) > 0.0 {
-        Some((self.albedo.value(rec.u, rec.v, &rec.p), scattered))
+        Some(ScatterRecord { attenuation: self.albedo.value_at(rec), scattered, is_specular: self.fuzz == 0.0 })
       } else {
         None
       }
   }
+
+  fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+    self.albedo.value_at(rec) * self.scattering_pdf(r_in, rec, direction)
+  }
+
+  fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+    // Scattered directions point at uniform points in a ball of radius fuzz
+    // around the unit mirror direction, so the density along a direction is
+    // the ball's volume along that ray weighted by r^2.
+    if self.fuzz == 0.0 || dot(direction, &rec.normal) <= 0.0 { return 0.0 }
+    let reflected = reflect(&unit_vector(r_in.direction()), &rec.normal);
+    let d = unit_vector(*direction);
+
+    let half_b = dot(&d, &reflected);
+    let discriminant = half_b*half_b - (1.0 - self.fuzz*self.fuzz);
+    if discriminant <= 0.0 { return 0.0 }
+    let t1 = fmax(half_b - discriminant.sqrt(), 0.0);
+    let t2 = half_b + discriminant.sqrt();
+    if t2 <= 0.0 { return 0.0 }
+
+    (t2.powi(3) - t1.powi(3)) / (4.0*PI*self.fuzz.powi(3))
+  }
+}
+
+// Physically based metal: GGX microfacets with the Fresnel reflectance of a
+// complex index of refraction eta + ik. Roughness is read from the first
+// channel of its textures, separately along the surface's tangent, where u
+// increases, and across it.
+#[derive(Debug, Clone, Copy)]
+pub struct Conductor<T: Texture> {
+  pub eta: Color,
+  pub k: Color,
+  pub roughness_u: T,
+  pub roughness_v: T
+}
+
+impl<T: Texture> Conductor<T> {
+  pub fn new(eta: Color, k: Color, roughness_u: T, roughness_v: T) -> Self {
+    Self { eta, k, roughness_u, roughness_v }
+  }
+
+  // Shading frame, distribution and outgoing direction in that frame.
+  fn local(&self, r_in: &Ray, rec: &HitRecord) -> (Onb, TrowbridgeReitz, Vec3) {
+    let frame = Onb::from_normal_tangent(&rec.normal, &rec.tangent);
+    let alpha_x = TrowbridgeReitz::roughness_to_alpha(self.roughness_u.value_at(rec).x());
+    let alpha_y = TrowbridgeReitz::roughness_to_alpha(self.roughness_v.value_at(rec).x());
+    let wo = frame.world_to_local(&-unit_vector(r_in.direction()));
+    (frame, TrowbridgeReitz::new(alpha_x, alpha_y), wo)
+  }
+}
+
+// Complex index of refraction (eta, k) at red, green and blue wavelengths.
+pub fn conductor_preset(name: &str) -> Option<(Color, Color)> {
+  match name {
+    "gold" => Some((Color::new(0.143, 0.374, 1.442), Color::new(3.983, 2.385, 1.603))),
+    "copper" => Some((Color::new(0.200, 0.924, 1.102), Color::new(3.912, 2.452, 2.142))),
+    "aluminum" => Some((Color::new(1.657, 0.880, 0.521), Color::new(9.224, 6.270, 4.837))),
+    "silver" => Some((Color::new(0.155, 0.117, 0.138), Color::new(4.828, 3.122, 2.147))),
+    _ => None
+  }
+}
+
+impl<T: Texture> Material for Conductor<T> {
+  fn scatter(&self, r_in: &Ray, rec: &HitRecord, _uc: f64, u: (f64, f64)) -> Option<ScatterRecord> {
+    let (frame, distribution, wo) = self.local(r_in, rec);
+    if wo.z() <= 0.0 { return None }
+
+    if distribution.effectively_smooth() {
+      let wi = Vec3::new(-wo.x(), -wo.y(), wo.z());
+      return Some(ScatterRecord {
+        attenuation: fresnel_conductor(wo.z(), &self.eta, &self.k),
+        scattered: Ray::new(rec.p, frame.local(&wi), r_in.time()),
+        is_specular: true
+      })
+    }
+
+    let wm = distribution.sample_wm(&wo, u);
+    let wi = reflect_about(&wo, &wm);
+    if wi.z() <= 0.0 { return None }
+
+    // f * cos / pdf, with most of the terms cancelling.
+    let fresnel = fresnel_conductor(dot(&wo, &wm).abs(), &self.eta, &self.k);
+    Some(ScatterRecord {
+      attenuation: fresnel * (distribution.g(&wo, &wi) / distribution.g1(&wo)),
+      scattered: Ray::new(rec.p, frame.local(&wi), r_in.time()),
+      is_specular: false
+    })
+  }
+
+  fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+    let (frame, distribution, wo) = self.local(r_in, rec);
+    let wi = frame.world_to_local(&unit_vector(*direction));
+    if wo.z() <= 0.0 || wi.z() <= 0.0 || distribution.effectively_smooth() { return Color::zero() }
+
+    let wm = unit_vector(wo + wi);
+    let fresnel = fresnel_conductor(dot(&wo, &wm).abs(), &self.eta, &self.k);
+    fresnel * (distribution.d(&wm) * distribution.g(&wo, &wi) / (4.0 * wo.z()))
+  }
+
+  fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+    let (frame, distribution, wo) = self.local(r_in, rec);
+    let wi = frame.world_to_local(&unit_vector(*direction));
+    if wo.z() <= 0.0 || wi.z() <= 0.0 || distribution.effectively_smooth() { return 0.0 }
+
+    let wm = unit_vector(wo + wi);
+    distribution.pdf(&wo, &wm) / (4.0 * dot(&wo, &wm).abs())
+  }
+}
+
+// Index of refraction as a function of wavelength. Sellmeier and Cauchy
+// coefficients take wavelengths in micrometres. Rays without a wavelength
+// see the index at the Fraunhofer d line.
+#[derive(Debug, Clone, Copy)]
+pub enum Ior {
+  Constant(f64),
+  Cauchy { a: f64, b: f64 },
+  Sellmeier { b: [f64; 3], c: [f64; 3] }
+}
+
+const D_LINE: f64 = 0.5876;
+const F_LINE: f64 = 0.4861;
+const C_LINE: f64 = 0.6563;
+
+impl Ior {
+  // Cauchy fit to an index at the d line and an Abbe number.
+  pub fn abbe(n_d: f64, v_d: f64) -> Self {
+    let b = (n_d - 1.0) / (v_d * (1.0/(F_LINE*F_LINE) - 1.0/(C_LINE*C_LINE)));
+    Ior::Cauchy { a: n_d - b / (D_LINE*D_LINE), b }
+  }
+
+  pub fn preset(name: &str) -> Option<Self> {
+    match name {
+      "bk7" => Some(Ior::Sellmeier { b: [1.03961212, 0.231792344, 1.01046945], c: [0.00600069867, 0.0200179144, 103.560653] }),
+      "fused_silica" => Some(Ior::Sellmeier { b: [0.6961663, 0.4079426, 0.8974794], c: [0.0046791483, 0.0135120631, 97.9340025] }),
+      "sf11" => Some(Ior::Sellmeier { b: [1.73759695, 0.313747346, 1.89878101], c: [0.013188707, 0.0623068142, 155.23629] }),
+      _ => None
+    }
+  }
+
+  pub fn at(&self, wavelength: Option<f64>) -> f64 {
+    let l = wavelength.map_or(D_LINE, |nm| nm / 1000.0);
+    match self {
+      Ior::Constant(n) => *n,
+      Ior::Cauchy { a, b } => a + b / (l*l),
+      Ior::Sellmeier { b, c } => (1.0 + (0..3).map(|i| b[i]*l*l / (l*l - c[i])).sum::<f64>()).sqrt()
+    }
+  }
+
+  pub fn is_dispersive(&self) -> bool { !matches!(self, Ior::Constant(_)) }
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct Dialectric {
-  pub ir: f64
+  pub ir: Ior
 }
 
 fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
@@ -75,8 +280,9 @@ fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
 }
 
 impl Material for Dialectric {
-  fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-    let refraction_ratio = if rec.front_face { 1.0/self.ir } else { self.ir };
+  fn scatter(&self, r_in: &Ray, rec: &HitRecord, uc: f64, _u: (f64, f64)) -> Option<ScatterRecord> {
+    let ir = self.ir.at(r_in.wavelength());
+    let refraction_ratio = if rec.front_face { 1.0/ir } else { ir };
 
     let unit_direction = unit_vector(r_in.direction());
     let cos_theta = fmin(dot(&-unit_direction, &rec.normal), 1.0);
@@ -84,17 +290,152 @@ impl Material for Dialectric {
 
     let cannot_refract = refraction_ratio * sin_theta > 1.0;
 
-    let direction = if cannot_refract || reflectance(cos_theta, refraction_ratio) > random_double() {
+    let direction = if cannot_refract || reflectance(cos_theta, refraction_ratio) > uc {
       reflect(&unit_direction, &rec.normal)
     } else {
       refract(&unit_direction, &rec.normal, refraction_ratio)
     };
 
-    Some((
-      Color::new(1.0, 1.0, 1.0),
-      Ray::new(rec.p, direction, r_in.time())
-    ))
+    Some(ScatterRecord {
+      attenuation: Color::new(1.0, 1.0, 1.0),
+      scattered: Ray::new(rec.p, direction, r_in.time()),
+      is_specular: true
+    })
   }
+
+  fn is_dispersive(&self) -> bool { self.ir.is_dispersive() }
+}
+
+// Glass with a GGX rough interface and colored by absorption inside it: light
+// that travels tint_distance through the glass is left with the tint color.
+// Absorption is applied when a ray leaves through the surface, so the glass
+// should be a closed shape. Thin-walled glass is a smooth slab of negligible
+// thickness, tinted once per pass, that lets light straight through.
+#[derive(Debug, Clone, Copy)]
+pub struct RoughDielectric<T: Texture> {
+  pub ir: Ior,
+  pub roughness: T,
+  pub tint: Color,
+  pub tint_distance: f64,
+  pub thin: bool
+}
+
+impl<T: Texture> RoughDielectric<T> {
+  pub fn new(ir: Ior, roughness: T, tint: Color, tint_distance: f64, thin: bool) -> Self {
+    Self { ir, roughness, tint, tint_distance, thin }
+  }
+
+  // Shading frame, distribution, outgoing direction in that frame and the
+  // index of refraction on the far side relative to this side.
+  fn local(&self, r_in: &Ray, rec: &HitRecord) -> (Onb, TrowbridgeReitz, Vec3, f64) {
+    let frame = Onb::from_normal_tangent(&rec.normal, &rec.tangent);
+    let alpha = TrowbridgeReitz::roughness_to_alpha(self.roughness.value_at(rec).x());
+    let wo = frame.world_to_local(&-unit_vector(r_in.direction()));
+    let ir = self.ir.at(r_in.wavelength());
+    let eta = if rec.front_face { ir } else { 1.0 / ir };
+    (frame, TrowbridgeReitz::new(alpha, alpha), wo, eta)
+  }
+
+  // Transmittance of the path inside the glass that ends at rec.
+  fn absorption(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+    if self.thin || rec.front_face { return Color::new(1.0, 1.0, 1.0) }
+    let distance = rec.t * r_in.direction().length();
+    let exponent = distance / self.tint_distance;
+    Color::new(self.tint.x().powf(exponent), self.tint.y().powf(exponent), self.tint.z().powf(exponent))
+  }
+
+  fn scatter_thin(&self, r_in: &Ray, rec: &HitRecord, frame: &Onb, wo: &Vec3, uc: f64) -> ScatterRecord {
+    // Sum over the light bouncing back and forth inside the slab.
+    let mut r = fresnel_dielectric(wo.z(), self.ir.at(r_in.wavelength()));
+    if r < 1.0 { r += (1.0 - r)*(1.0 - r)*r / (1.0 - r*r) }
+
+    let (attenuation, wi) = if uc < r {
+      (Color::new(1.0, 1.0, 1.0), Vec3::new(-wo.x(), -wo.y(), wo.z()))
+    } else {
+      (self.tint, -*wo)
+    };
+    ScatterRecord { attenuation, scattered: Ray::new(rec.p, frame.local(&wi), r_in.time()), is_specular: true }
+  }
+}
+
+impl<T: Texture> Material for RoughDielectric<T> {
+  fn scatter(&self, r_in: &Ray, rec: &HitRecord, uc: f64, u: (f64, f64)) -> Option<ScatterRecord> {
+    let (frame, distribution, wo, eta) = self.local(r_in, rec);
+    if wo.z() <= 0.0 { return None }
+    if self.thin { return Some(self.scatter_thin(r_in, rec, &frame, &wo, uc)) }
+    let absorption = self.absorption(r_in, rec);
+
+    if distribution.effectively_smooth() {
+      let wi = if uc < fresnel_dielectric(wo.z(), eta) {
+        Vec3::new(-wo.x(), -wo.y(), wo.z())
+      } else {
+        refract_through(&wo, &Vec3::new(0.0, 0.0, 1.0), eta)?
+      };
+      // Radiance is compressed into a smaller solid angle on refraction.
+      let scale = if wi.z() < 0.0 { 1.0 / (eta*eta) } else { 1.0 };
+      return Some(ScatterRecord {
+        attenuation: absorption * scale,
+        scattered: Ray::new(rec.p, frame.local(&wi), r_in.time()),
+        is_specular: true
+      })
+    }
+
+    let wm = distribution.sample_wm(&wo, u);
+    if dot(&wo, &wm) <= 0.0 { return None }
+    let reflectance = fresnel_dielectric(dot(&wo, &wm), eta);
+
+    // f * cos / pdf; the Fresnel terms cancel against the choice between
+    // reflection and transmission.
+    let (wi, scale) = if uc < reflectance {
+      let wi = reflect_about(&wo, &wm);
+      if wi.z() <= 0.0 { return None }
+      (wi, 1.0)
+    } else {
+      let wi = refract_through(&wo, &wm, eta)?;
+      if wi.z() >= 0.0 { return None }
+      (wi, 1.0 / (eta*eta))
+    };
+
+    Some(ScatterRecord {
+      attenuation: absorption * (scale * distribution.g(&wo, &wi) / distribution.g1(&wo)),
+      scattered: Ray::new(rec.p, frame.local(&wi), r_in.time()),
+      is_specular: false
+    })
+  }
+
+  fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+    let (frame, distribution, wo, eta) = self.local(r_in, rec);
+    if self.thin || distribution.effectively_smooth() { return Color::zero() }
+    let wi = frame.world_to_local(&unit_vector(*direction));
+    let Some((wm, reflect)) = half_vector(&wo, &wi, eta) else { return Color::zero() };
+
+    let reflectance = fresnel_dielectric(dot(&wo, &wm), eta);
+    let d_g = distribution.d(&wm) * distribution.g(&wo, &wi);
+    let f_cos = if reflect {
+      d_g * reflectance / (4.0 * wo.z())
+    } else {
+      let denom = (dot(&wi, &wm) + dot(&wo, &wm) / eta).powi(2) * wo.z();
+      d_g * (1.0 - reflectance) * (dot(&wi, &wm) * dot(&wo, &wm) / denom).abs() / (eta*eta)
+    };
+    self.absorption(r_in, rec) * f_cos
+  }
+
+  fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+    let (frame, distribution, wo, eta) = self.local(r_in, rec);
+    if self.thin || distribution.effectively_smooth() { return 0.0 }
+    let wi = frame.world_to_local(&unit_vector(*direction));
+    let Some((wm, reflect)) = half_vector(&wo, &wi, eta) else { return 0.0 };
+
+    let reflectance = fresnel_dielectric(dot(&wo, &wm), eta);
+    if reflect {
+      distribution.pdf(&wo, &wm) / (4.0 * dot(&wo, &wm).abs()) * reflectance
+    } else {
+      let denom = (dot(&wi, &wm) + dot(&wo, &wm) / eta).powi(2);
+      distribution.pdf(&wo, &wm) * dot(&wi, &wm).abs() / denom * (1.0 - reflectance)
+    }
+  }
+
+  fn is_dispersive(&self) -> bool { self.ir.is_dispersive() }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -106,18 +447,16 @@ impl<T: Texture> DiffuseLight<T> {
   pub fn new(a: T) -> Self { Self { emit: a } }
 }
 
-impl DiffuseLight<SolidColor> {
-  pub fn solid(c: Color) -> Self { Self { emit: SolidColor::new(c) } }
-}
-
 impl<T: Texture> Material for DiffuseLight<T> {
-  fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<(Color, Ray)> {
+  fn scatter(&self, _r_in: &Ray, _rec: &HitRecord, _uc: f64, _u: (f64, f64)) -> Option<ScatterRecord> {
     None
   }
 
   fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
     self.emit.value(u, v, p)
   }
+
+  fn is_emissive(&self) -> bool { true }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -129,15 +468,20 @@ impl<T: Texture> Isotropic<T> {
   pub fn new(albedo: T) -> Self { Self { albedo } }
 }
 
-impl Isotropic<SolidColor> {
-  pub fn solid(c: Color) -> Self { Self { albedo: SolidColor::new(c) } }
-}
-
 impl<T: Texture> Material for Isotropic<T> {
-  fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-      Some((
-        self.albedo.value(rec.u, rec.v, &rec.p),
-        Ray::new(rec.p, Vec3::random_in_unit_sphere(), r_in.time())
-      ))
+  fn scatter(&self, r_in: &Ray, rec: &HitRecord, _uc: f64, u: (f64, f64)) -> Option<ScatterRecord> {
+      Some(ScatterRecord {
+        attenuation: self.albedo.value_at(rec),
+        scattered: Ray::new(rec.p, sample_unit_sphere(u), r_in.time()),
+        is_specular: false
+      })
+  }
+
+  fn eval(&self, _r_in: &Ray, rec: &HitRecord, _direction: &Vec3) -> Color {
+    self.albedo.value_at(rec) / (4.0 * PI)
+  }
+
+  fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> f64 {
+    1.0 / (4.0 * PI)
   }
 }
\ No newline at end of file
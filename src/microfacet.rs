@@ -0,0 +1,157 @@
+use std::f64::consts::PI;
+
+use crate::{vec3::{Vec3, Color, unit_vector, cross, dot}, sampler::sample_unit_disk};
+
+// Trowbridge-Reitz (GGX) microfacet distribution with Smith's height
+// correlated masking-shadowing. Directions are in the local shading frame,
+// with the surface normal along +Z.
+#[derive(Debug, Clone, Copy)]
+pub struct TrowbridgeReitz {
+  alpha_x: f64,
+  alpha_y: f64
+}
+
+impl TrowbridgeReitz {
+  pub fn new(alpha_x: f64, alpha_y: f64) -> Self {
+    Self { alpha_x: alpha_x.max(1e-4), alpha_y: alpha_y.max(1e-4) }
+  }
+
+  // Perceptually linear roughness in [0,1] to the distribution's alpha.
+  pub fn roughness_to_alpha(roughness: f64) -> f64 {
+    roughness.clamp(0.0, 1.0).powi(2)
+  }
+
+  // Below this the surface is treated as a perfect mirror, since sampling a
+  // near-delta distribution is numerically unstable.
+  pub fn effectively_smooth(&self) -> bool {
+    self.alpha_x.max(self.alpha_y) < 1e-3
+  }
+
+  pub fn d(&self, wm: &Vec3) -> f64 {
+    let tan2_theta = tan2_theta(wm);
+    if tan2_theta.is_infinite() { return 0.0 }
+    let cos4_theta = wm.z().powi(4);
+    if cos4_theta < 1e-16 { return 0.0 }
+    let (cos_phi, sin_phi) = cos_sin_phi(wm);
+    let e = tan2_theta * ((cos_phi / self.alpha_x).powi(2) + (sin_phi / self.alpha_y).powi(2));
+    1.0 / (PI * self.alpha_x * self.alpha_y * cos4_theta * (1.0 + e).powi(2))
+  }
+
+  fn lambda(&self, w: &Vec3) -> f64 {
+    let tan2_theta = tan2_theta(w);
+    if tan2_theta.is_infinite() { return 0.0 }
+    let (cos_phi, sin_phi) = cos_sin_phi(w);
+    let alpha2 = (cos_phi * self.alpha_x).powi(2) + (sin_phi * self.alpha_y).powi(2);
+    ((1.0 + alpha2 * tan2_theta).sqrt() - 1.0) / 2.0
+  }
+
+  pub fn g1(&self, w: &Vec3) -> f64 {
+    1.0 / (1.0 + self.lambda(w))
+  }
+
+  pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+    1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+  }
+
+  // Density of microfacet normals visible from w.
+  pub fn pdf(&self, w: &Vec3, wm: &Vec3) -> f64 {
+    if w.z() == 0.0 { return 0.0 }
+    self.g1(w) / w.z().abs() * self.d(wm) * dot(w, wm).abs()
+  }
+
+  // Samples a visible microfacet normal, following Heitz's "Sampling the GGX
+  // Distribution of Visible Normals".
+  pub fn sample_wm(&self, w: &Vec3, u: (f64, f64)) -> Vec3 {
+    // Transform w to the hemispherical configuration.
+    let mut wh = unit_vector(Vec3::new(self.alpha_x * w.x(), self.alpha_y * w.y(), w.z()));
+    if wh.z() < 0.0 { wh = -wh }
+
+    let t1 = if wh.z() < 0.99999 { unit_vector(cross(&Vec3::new(0.0, 0.0, 1.0), &wh)) } else { Vec3::new(1.0, 0.0, 0.0) };
+    let t2 = cross(&wh, &t1);
+
+    // Uniform disk point warped to the projection of the visible hemisphere.
+    let p = sample_unit_disk(u);
+    let h = (1.0 - p.x()*p.x()).sqrt();
+    let s = (1.0 + wh.z()) / 2.0;
+    let (px, py) = (p.x(), (1.0 - s)*h + s*p.y());
+    let pz = (1.0 - px*px - py*py).max(0.0).sqrt();
+    let nh = px*t1 + py*t2 + pz*wh;
+
+    unit_vector(Vec3::new(self.alpha_x * nh.x(), self.alpha_y * nh.y(), nh.z().max(1e-6)))
+  }
+}
+
+fn tan2_theta(w: &Vec3) -> f64 {
+  let cos2_theta = w.z()*w.z();
+  (1.0 - cos2_theta).max(0.0) / cos2_theta
+}
+
+fn cos_sin_phi(w: &Vec3) -> (f64, f64) {
+  let sin_theta = (1.0 - w.z()*w.z()).max(0.0).sqrt();
+  if sin_theta == 0.0 { return (1.0, 0.0) }
+  ((w.x() / sin_theta).clamp(-1.0, 1.0), (w.y() / sin_theta).clamp(-1.0, 1.0))
+}
+
+pub fn reflect_about(wo: &Vec3, n: &Vec3) -> Vec3 {
+  -*wo + 2.0*dot(wo, n)*(*n)
+}
+
+// Refracts wi, on the same side as n, through an interface with relative
+// index of refraction eta. None on total internal reflection.
+pub fn refract_through(wi: &Vec3, n: &Vec3, eta: f64) -> Option<Vec3> {
+  let cos_theta_i = dot(n, wi);
+  let sin2_theta_t = (1.0 - cos_theta_i*cos_theta_i).max(0.0) / (eta*eta);
+  if sin2_theta_t >= 1.0 { return None }
+  let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+  Some(-*wi / eta + (cos_theta_i / eta - cos_theta_t)*(*n))
+}
+
+// Microfacet normal that scatters wo into wi, and whether that's a
+// reflection, or None if no microfacet facing both directions can.
+pub fn half_vector(wo: &Vec3, wi: &Vec3, eta: f64) -> Option<(Vec3, bool)> {
+  if wo.z() <= 0.0 || wi.z() == 0.0 { return None }
+  let reflect = wi.z() > 0.0;
+  let wm = if reflect { *wo + *wi } else { *wi * eta + *wo };
+  if wm.length_squared() == 0.0 { return None }
+  let wm = if wm.z() < 0.0 { -unit_vector(wm) } else { unit_vector(wm) };
+  if dot(&wm, wi) * wi.z() < 0.0 || dot(&wm, wo) <= 0.0 { return None }
+  Some((wm, reflect))
+}
+
+// Unpolarized Fresnel reflectance of a dielectric interface with relative
+// index of refraction eta.
+pub fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+  let (mut cos_theta_i, mut eta) = (cos_theta_i.clamp(-1.0, 1.0), eta);
+  if cos_theta_i < 0.0 {
+    eta = 1.0 / eta;
+    cos_theta_i = -cos_theta_i;
+  }
+
+  let sin2_theta_t = (1.0 - cos_theta_i*cos_theta_i) / (eta*eta);
+  if sin2_theta_t >= 1.0 { return 1.0 }
+  let cos_theta_t = (1.0 - sin2_theta_t).max(0.0).sqrt();
+
+  let r_parallel = (eta*cos_theta_i - cos_theta_t) / (eta*cos_theta_i + cos_theta_t);
+  let r_perpendicular = (cos_theta_i - eta*cos_theta_t) / (cos_theta_i + eta*cos_theta_t);
+  (r_parallel*r_parallel + r_perpendicular*r_perpendicular) / 2.0
+}
+
+// Unpolarized Fresnel reflectance of a conductor with complex index of
+// refraction eta + ik, per color channel.
+pub fn fresnel_conductor(cos_theta_i: f64, eta: &Color, k: &Color) -> Color {
+  let cos2 = cos_theta_i.clamp(0.0, 1.0).powi(2);
+  let sin2 = 1.0 - cos2;
+  let channel = |eta: f64, k: f64| {
+    let t0 = eta*eta - k*k - sin2;
+    let a2_plus_b2 = (t0*t0 + 4.0*eta*eta*k*k).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5*(a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0*a*cos_theta_i.clamp(0.0, 1.0);
+    let rs = (t1 - t2) / (t1 + t2);
+    let t3 = cos2*a2_plus_b2 + sin2*sin2;
+    let t4 = t2*sin2;
+    let rp = rs*(t3 - t4) / (t3 + t4);
+    0.5*(rp + rs)
+  };
+  Color::new(channel(eta.x(), k.x()), channel(eta.y(), k.y()), channel(eta.z(), k.z()))
+}
\ No newline at end of file
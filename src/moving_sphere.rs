@@ -1,4 +1,4 @@
-use crate::{vec3::{Point3, dot, Vec3}, hittable::{Hittable, HitRecord}, ray::Ray, material::Material, aabb::AABB, sphere::get_sphere_uv};
+use crate::{vec3::{Point3, dot, Vec3}, hittable::{Hittable, HitRecord}, ray::Ray, material::Material, aabb::AABB, mat4::Mat4, sphere::{get_sphere_uv, sphere_tangent}, util::Rng};
 
 pub struct MovingSphere<M: Material> {
   center0: Point3, pub center1: Point3,
@@ -18,7 +18,7 @@ impl<M: Material> MovingSphere<M> {
 }
 
 impl<M: Material> Hittable for MovingSphere<M> {
-  fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+  fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _rng: &mut Rng) -> Option<HitRecord<'_>> {
     let center = self.center(r.time());
     let oc = r.origin() - center;
     let a = r.direction().length_squared();
@@ -40,7 +40,7 @@ impl<M: Material> Hittable for MovingSphere<M> {
     let p = r.at(t);
     let outward_normal = (p - center) / self.radius;
     let (u, v) = get_sphere_uv(&outward_normal);
-    let mut rec = HitRecord { t, p, material: &self.material, normal: outward_normal, front_face: true, u, v };
+    let mut rec = HitRecord { t, p, material: &self.material, normal: outward_normal, front_face: true, u, v, tangent: sphere_tangent(&outward_normal), color: None };
     rec.set_face_normal(r, &outward_normal);
 
     Some(rec)
@@ -58,4 +58,10 @@ impl<M: Material> Hittable for MovingSphere<M> {
     );
     Some(AABB::surrounding_box(&box0, &box1))
   }
+
+  fn transformed_bounding_box(&self, to_world: &Mat4, time0: f64, time1: f64) -> Option<AABB> {
+    let box0 = to_world.transform_sphere_bbox(&self.center(time0), self.radius);
+    let box1 = to_world.transform_sphere_bbox(&self.center(time1), self.radius);
+    Some(AABB::surrounding_box(&box0, &box1))
+  }
 }
\ No newline at end of file
@@ -0,0 +1,448 @@
+use std::{collections::HashMap, fmt, fs, path::{Path, PathBuf}, sync::Arc};
+
+use crate::{
+  vec3::{Point3, Vec3, Color},
+  material::{Material, Lambertian, Metal, Dialectric, DiffuseLight, Ior},
+  texture::{Texture, SolidColor, ImageTexture, VertexColorTexture},
+  triangle::MeshData,
+  color::luminance
+};
+
+#[derive(Debug)]
+pub struct ObjError {
+  path: PathBuf,
+  line: Option<usize>,
+  message: String
+}
+
+impl fmt::Display for ObjError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.line {
+      Some(line) => write!(f, "{}:{line}: {}", self.path.display(), self.message),
+      None => write!(f, "{}: {}", self.path.display(), self.message)
+    }
+  }
+}
+
+impl std::error::Error for ObjError {}
+
+type Result<T> = std::result::Result<T, ObjError>;
+
+// Loads the triangles of a Wavefront OBJ file, with polygons split into
+// fans, as one mesh. MTL materials map onto the closest built-in material
+// unless material overrides them all. groups, if given, keeps only the
+// faces in the named groups or objects. Vertex colors, given after the
+// position as many tools write them, are kept if every vertex has one, and
+// color faces without a material.
+pub fn load_obj(path: &Path, material: Option<Arc<dyn Material>>, groups: Option<&[String]>) -> Result<MeshData<Arc<dyn Material>>> {
+  let source = fs::read_to_string(path).map_err(|e| ObjError { path: path.to_path_buf(), line: None, message: format!("could not read file: {e}") })?;
+  let overridden = material.is_some();
+  let mut parser = ObjParser {
+    path: path.to_path_buf(),
+    line: 0,
+    overridden,
+    positions: Vec::new(),
+    colors: Vec::new(),
+    uvs: Vec::new(),
+    normals: Vec::new(),
+    vertices: HashMap::new(),
+    mesh: MeshData {
+      positions: Vec::new(),
+      normals: Vec::new(),
+      uvs: Vec::new(),
+      colors: Vec::new(),
+      indices: Vec::new(),
+      materials: vec![material.unwrap_or_else(|| Arc::new(Lambertian::solid(Color::new(0.8, 0.8, 0.8))))],
+      face_materials: Vec::new()
+    },
+    vertex_normals: Vec::new(),
+    vertex_uvs: Vec::new(),
+    vertex_colors: Vec::new(),
+    library: HashMap::new(),
+    used: HashMap::new(),
+    textures: HashMap::new(),
+    current_material: 0,
+    current_groups: vec!["default".to_string()],
+    current_object: None
+  };
+
+  for (i, line) in source.lines().enumerate() {
+    parser.line = i + 1;
+    let line = line.split('#').next().unwrap_or("");
+    let mut tokens = line.split_whitespace();
+    let Some(keyword) = tokens.next() else { continue };
+    let args: Vec<&str> = tokens.collect();
+
+    match keyword {
+      "v" => {
+        // x y z, with an optional w, or followed by r g b and maybe alpha.
+        let v = parser.numbers(&args, 3, 7)?;
+        if v.len() == 5 { return Err(parser.error("expected 3, 4, 6 or 7 numbers, found 5".to_string())) }
+        parser.positions.push(Point3::new(v[0], v[1], v[2]));
+        parser.colors.push((v.len() >= 6).then(|| Color::new(v[3], v[4], v[5])));
+      },
+      "vt" => {
+        let v = parser.numbers(&args, 1, 3)?;
+        parser.uvs.push((v[0], v.get(1).copied().unwrap_or(0.0)));
+      },
+      "vn" => {
+        let v = parser.numbers(&args, 3, 3)?;
+        parser.normals.push(Vec3::new(v[0], v[1], v[2]));
+      },
+      "f" => {
+        if args.len() < 3 { return Err(parser.error(format!("face needs at least 3 vertices, found {}", args.len()))) }
+        let face = args.iter().map(|token| parser.face_vertex(token)).collect::<Result<Vec<usize>>>()?;
+        if parser.selected(groups) {
+          for k in 1..face.len() - 1 {
+            parser.mesh.indices.push([face[0], face[k], face[k + 1]]);
+            parser.mesh.face_materials.push(parser.current_material);
+          }
+        }
+      },
+      "g" => parser.current_groups = args.iter().map(|s| s.to_string()).collect(),
+      "o" => parser.current_object = args.first().map(|s| s.to_string()),
+      "mtllib" => {
+        if args.is_empty() { return Err(parser.error("mtllib needs a file name".to_string())) }
+        if !overridden {
+          for file in &args {
+            parser.load_mtl(&path.parent().unwrap_or(Path::new("")).join(file))?;
+          }
+        }
+      },
+      "usemtl" => {
+        let Some(name) = args.first() else { return Err(parser.error("usemtl needs a material name".to_string())) };
+        if !overridden { parser.use_material(name)? }
+      },
+      // Smoothing groups, lines, points and anything else can't be rendered.
+      _ => {}
+    }
+  }
+
+  parser.finish()
+}
+
+struct ObjParser {
+  path: PathBuf,
+  line: usize,
+  overridden: bool,
+  positions: Vec<Point3>,
+  colors: Vec<Option<Color>>,
+  uvs: Vec<(f64, f64)>,
+  normals: Vec<Vec3>,
+  // Mesh vertex for each combination of position, uv and normal indices.
+  vertices: HashMap<(usize, Option<usize>, Option<usize>), usize>,
+  mesh: MeshData<Arc<dyn Material>>,
+  vertex_normals: Vec<Option<Vec3>>,
+  vertex_uvs: Vec<Option<(f64, f64)>>,
+  vertex_colors: Vec<Option<Color>>,
+  library: HashMap<String, Arc<dyn Material>>,
+  // Index in the mesh's materials of each material used so far.
+  used: HashMap<String, usize>,
+  textures: HashMap<PathBuf, Arc<ImageTexture>>,
+  current_material: usize,
+  current_groups: Vec<String>,
+  current_object: Option<String>
+}
+
+impl ObjParser {
+  fn error(&self, message: String) -> ObjError {
+    ObjError { path: self.path.clone(), line: Some(self.line), message }
+  }
+
+  fn numbers(&self, args: &[&str], min: usize, max: usize) -> Result<Vec<f64>> {
+    if args.len() < min || args.len() > max {
+      let expected = if min == max { format!("{min}") } else { format!("{min} to {max}") };
+      return Err(self.error(format!("expected {expected} numbers, found {}", args.len())))
+    }
+    args.iter().map(|a| a.parse::<f64>().map_err(|_| self.error(format!("invalid number '{a}'")))).collect()
+  }
+
+  // Resolves a 1-based or negative, relative index into a list of len items.
+  fn index(&self, token: &str, len: usize, what: &str) -> Result<usize> {
+    let i: i64 = token.parse().map_err(|_| self.error(format!("invalid {what} index '{token}'")))?;
+    let resolved = if i > 0 { i - 1 } else { len as i64 + i };
+    if i == 0 || resolved < 0 || resolved >= len as i64 {
+      return Err(self.error(format!("{what} index {i} out of range ({len} defined)")))
+    }
+    Ok(resolved as usize)
+  }
+
+  fn face_vertex(&mut self, token: &str) -> Result<usize> {
+    let mut parts = token.split('/');
+    let position = self.index(parts.next().unwrap_or(""), self.positions.len(), "vertex")?;
+    let uv = match parts.next() {
+      Some(t) if !t.is_empty() => Some(self.index(t, self.uvs.len(), "texture coordinate")?),
+      _ => None
+    };
+    let normal = match parts.next() {
+      Some(t) if !t.is_empty() => Some(self.index(t, self.normals.len(), "normal")?),
+      _ => None
+    };
+    if parts.next().is_some() { return Err(self.error(format!("invalid face vertex '{token}'"))) }
+
+    let key = (position, uv, normal);
+    if let Some(&vertex) = self.vertices.get(&key) { return Ok(vertex) }
+    let vertex = self.mesh.positions.len();
+    self.mesh.positions.push(self.positions[position]);
+    self.vertex_uvs.push(uv.map(|i| self.uvs[i]));
+    self.vertex_normals.push(normal.map(|i| self.normals[i]));
+    self.vertex_colors.push(self.colors[position]);
+    self.vertices.insert(key, vertex);
+    Ok(vertex)
+  }
+
+  fn selected(&self, groups: Option<&[String]>) -> bool {
+    match groups {
+      None => true,
+      Some(groups) => groups.iter().any(|g| self.current_groups.contains(g) || self.current_object.as_ref() == Some(g))
+    }
+  }
+
+  fn use_material(&mut self, name: &str) -> Result<()> {
+    if let Some(&index) = self.used.get(name) {
+      self.current_material = index;
+      return Ok(())
+    }
+    let material = self.library.get(name).ok_or_else(|| self.error(format!("unknown material '{name}'")))?;
+    self.mesh.materials.push(material.clone());
+    self.current_material = self.mesh.materials.len() - 1;
+    self.used.insert(name.to_string(), self.current_material);
+    Ok(())
+  }
+
+  fn load_mtl(&mut self, path: &Path) -> Result<()> {
+    let source = fs::read_to_string(path)
+      .map_err(|e| self.error(format!("could not read material library '{}': {e}", path.display())))?;
+    let mut mtl = MtlParser { path: path.to_path_buf(), line: 0, current: None };
+
+    for (i, line) in source.lines().enumerate() {
+      mtl.line = i + 1;
+      let line = line.split('#').next().unwrap_or("");
+      let mut tokens = line.split_whitespace();
+      let Some(keyword) = tokens.next() else { continue };
+      let args: Vec<&str> = tokens.collect();
+
+      if keyword == "newmtl" {
+        let Some(name) = args.first() else { return Err(mtl.error("newmtl needs a material name".to_string())) };
+        if let Some((name, desc)) = mtl.current.take() {
+          let material = self.mtl_material(&mtl, desc)?;
+          self.library.insert(name, material);
+        }
+        mtl.current = Some((name.to_string(), MtlDesc::default()));
+        continue
+      }
+
+      let color = |mtl: &MtlParser| mtl.numbers(&args, 3).map(|v| Color::new(v[0], v[1], v[2]));
+      let number = |mtl: &MtlParser| mtl.numbers(&args, 1).map(|v| v[0]);
+      match keyword {
+        "Kd" => { let c = color(&mtl)?; mtl.desc()?.kd = c },
+        "Ks" => { let c = color(&mtl)?; mtl.desc()?.ks = c },
+        "Ke" => { let c = color(&mtl)?; mtl.desc()?.ke = c },
+        "Ns" => { let n = number(&mtl)?; mtl.desc()?.ns = n },
+        "Ni" => {
+          let n = number(&mtl)?;
+          if n <= 0.0 { return Err(mtl.error("Ni must be positive".to_string())) }
+          mtl.desc()?.ni = n
+        },
+        "d" => { let n = number(&mtl)?; mtl.desc()?.d = n },
+        "Tr" => { let n = number(&mtl)?; mtl.desc()?.d = 1.0 - n },
+        "map_Kd" => {
+          // Options such as -s come before the file name.
+          let Some(file) = args.last() else { return Err(mtl.error("map_Kd needs a file name".to_string())) };
+          let file = path.parent().unwrap_or(Path::new("")).join(file);
+          let line = mtl.line;
+          mtl.desc()?.map_kd = Some((file, line));
+        },
+        _ => {}
+      }
+    }
+
+    if let Some((name, desc)) = mtl.current.take() {
+      let material = self.mtl_material(&mtl, desc)?;
+      self.library.insert(name, material);
+    }
+    Ok(())
+  }
+
+  // Emitters become lights, transparent materials glass, and materials more
+  // specular than diffuse metal, with Ns setting how blurred it is.
+  fn mtl_material(&mut self, mtl: &MtlParser, desc: MtlDesc) -> Result<Arc<dyn Material>> {
+    if !desc.ke.near_zero() { return Ok(Arc::new(DiffuseLight::new(SolidColor::new(desc.ke)))) }
+    if desc.d < 1.0 { return Ok(Arc::new(Dialectric { ir: Ior::Constant(desc.ni) })) }
+    if desc.map_kd.is_none() && luminance(desc.ks) > luminance(desc.kd) {
+      let fuzz = (2.0 / (desc.ns.max(0.0) + 2.0)).sqrt();
+      return Ok(Arc::new(Metal::solid(desc.ks, fuzz)))
+    }
+
+    let albedo: Arc<dyn Texture> = match desc.map_kd {
+      Some((file, line)) => match self.textures.get(&file) {
+        Some(texture) => texture.clone(),
+        None => {
+          let texture = Arc::new(ImageTexture::load(&file.to_string_lossy()).map_err(|e| ObjError {
+            path: mtl.path.clone(), line: Some(line), message: format!("could not load image '{}': {e}", file.display())
+          })?);
+          self.textures.insert(file, texture.clone());
+          texture
+        }
+      },
+      None => Arc::new(SolidColor::new(desc.kd))
+    };
+    Ok(Arc::new(Lambertian::new(albedo)))
+  }
+
+  fn finish(mut self) -> Result<MeshData<Arc<dyn Material>>> {
+    if self.mesh.indices.is_empty() {
+      return Err(ObjError { path: self.path, line: None, message: "no faces".to_string() })
+    }
+    // Normals are only used if every vertex has one; missing uvs are zero.
+    if self.vertex_normals.iter().all(|n| n.is_some()) {
+      self.mesh.normals = self.vertex_normals.iter().flatten().copied().collect();
+    }
+    if self.vertex_uvs.iter().any(|uv| uv.is_some()) {
+      self.mesh.uvs = self.vertex_uvs.iter().map(|uv| uv.unwrap_or((0.0, 0.0))).collect();
+    }
+    if self.vertex_colors.iter().all(|c| c.is_some()) {
+      self.mesh.colors = self.vertex_colors.iter().flatten().copied().collect();
+      // Faces that never got a material from the MTL file show the colors.
+      if !self.overridden { self.mesh.materials[0] = Arc::new(Lambertian::new(VertexColorTexture)) }
+    }
+    Ok(self.mesh)
+  }
+}
+
+struct MtlDesc {
+  kd: Color,
+  ks: Color,
+  ke: Color,
+  ns: f64,
+  ni: f64,
+  d: f64,
+  map_kd: Option<(PathBuf, usize)>
+}
+
+impl Default for MtlDesc {
+  fn default() -> Self {
+    Self { kd: Color::new(0.8, 0.8, 0.8), ks: Color::zero(), ke: Color::zero(), ns: 0.0, ni: 1.5, d: 1.0, map_kd: None }
+  }
+}
+
+struct MtlParser {
+  path: PathBuf,
+  line: usize,
+  current: Option<(String, MtlDesc)>
+}
+
+impl MtlParser {
+  fn error(&self, message: String) -> ObjError {
+    ObjError { path: self.path.clone(), line: Some(self.line), message }
+  }
+
+  fn desc(&mut self) -> Result<&mut MtlDesc> {
+    match &mut self.current {
+      Some((_, desc)) => Ok(desc),
+      None => Err(ObjError { path: self.path.clone(), line: Some(self.line), message: "statement before newmtl".to_string() })
+    }
+  }
+
+  fn numbers(&self, args: &[&str], n: usize) -> Result<Vec<f64>> {
+    // A single value for a color stands for grey.
+    let values = args.iter().map(|a| a.parse::<f64>().map_err(|_| self.error(format!("invalid number '{a}'")))).collect::<Result<Vec<f64>>>()?;
+    match values.len() {
+      len if len == n => Ok(values),
+      1 if n == 3 => Ok(vec![values[0]; 3]),
+      len => Err(self.error(format!("expected {n} numbers, found {len}")))
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Writes the files into a directory of their own and loads mesh.obj.
+  fn load(test: &str, files: &[(&str, &str)]) -> (PathBuf, Result<MeshData<Arc<dyn Material>>>) {
+    let dir = std::env::temp_dir().join(format!("raytracer-obj-{test}-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for (name, contents) in files { fs::write(dir.join(name), contents).unwrap() }
+    let path = dir.join("mesh.obj");
+    let mesh = load_obj(&path, None, None);
+    fs::remove_dir_all(&dir).unwrap();
+    (path, mesh)
+  }
+
+  fn xyz(v: Vec3) -> [f64; 3] {
+    [v.x(), v.y(), v.z()]
+  }
+
+  fn error(result: Result<MeshData<Arc<dyn Material>>>) -> String {
+    match result {
+      Ok(_) => panic!("expected an error"),
+      Err(e) => e.to_string()
+    }
+  }
+
+  #[test]
+  fn negative_indices_count_back_from_the_last_vertex() {
+    let source = "v 0 0 0\nv 1 0 0\nv 1 1 0\nf -3 -2 -1\nv 0 1 0\nf -4 -2 -1\n";
+    let (_, mesh) = load("negative", &[("mesh.obj", source)]);
+    let mesh = mesh.unwrap();
+    assert_eq!(mesh.indices, vec![[0, 1, 2], [0, 2, 3]]);
+    assert_eq!(xyz(mesh.positions[3]), [0.0, 1.0, 0.0]);
+  }
+
+  #[test]
+  fn polygons_become_fans_sharing_their_vertices() {
+    let source = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 1\nf 1/1 2/1 3/1 4/1\nf 1/2 2/1 3/1\n";
+    let (_, mesh) = load("fan", &[("mesh.obj", source)]);
+    let mesh = mesh.unwrap();
+    // The second face reuses every vertex but the first, whose uv differs.
+    assert_eq!(mesh.indices, vec![[0, 1, 2], [0, 2, 3], [4, 1, 2]]);
+    assert_eq!(mesh.positions.len(), 5);
+    assert_eq!(mesh.uvs[4], (1.0, 1.0));
+    assert!(mesh.normals.is_empty());
+    mesh.validate().unwrap();
+  }
+
+  #[test]
+  fn vertex_colors_follow_positions() {
+    let source = "v 0 0 0 1 0 0\nv 1 0 0 0 1 0\nv 0 1 0 0 0 1\nf 1 2 3\n";
+    let (_, mesh) = load("colors", &[("mesh.obj", source)]);
+    let mesh = mesh.unwrap();
+    assert_eq!(mesh.colors.iter().map(|&c| xyz(c)).collect::<Vec<_>>(), vec![[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]);
+  }
+
+  #[test]
+  fn errors_name_the_line() {
+    let (path, mesh) = load("range", &[("mesh.obj", "v 0 0 0\nv 1 0 0\n\nv 0 1 0\nf 1 2 5\n")]);
+    assert_eq!(error(mesh), format!("{}:5: vertex index 5 out of range (3 defined)", path.display()));
+
+    let (path, mesh) = load("negative-range", &[("mesh.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf -4 1 2\n")]);
+    assert_eq!(error(mesh), format!("{}:4: vertex index -4 out of range (3 defined)", path.display()));
+
+    let (path, mesh) = load("count", &[("mesh.obj", "# comment\nv 0 0 0 1 1\n")]);
+    assert_eq!(error(mesh), format!("{}:2: expected 3, 4, 6 or 7 numbers, found 5", path.display()));
+
+    let (path, mesh) = load("material", &[("mesh.obj", "v 0 0 0\nusemtl red\n")]);
+    assert_eq!(error(mesh), format!("{}:2: unknown material 'red'", path.display()));
+
+    let (path, mesh) = load("empty", &[("mesh.obj", "v 0 0 0\n")]);
+    assert_eq!(error(mesh), format!("{}: no faces", path.display()));
+  }
+
+  #[test]
+  fn material_library_errors_name_the_library_line() {
+    let files = [("mesh.obj", "mtllib mesh.mtl\n"), ("mesh.mtl", "newmtl glass\nd 0.5\nNi 0\n")];
+    let (path, mesh) = load("mtl", &files);
+    assert_eq!(error(mesh), format!("{}:3: Ni must be positive", path.with_extension("mtl").display()));
+  }
+
+  #[test]
+  fn materials_apply_to_the_faces_after_them() {
+    let mtl = "newmtl light\nKe 4 4 4\nnewmtl red\nKd 0.8 0 0\n";
+    let obj = "mtllib mesh.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\nusemtl red\nf 1 2 3\nusemtl light\nf 1 2 3\nusemtl red\nf 1 2 3\n";
+    let (_, mesh) = load("usemtl", &[("mesh.obj", obj), ("mesh.mtl", mtl)]);
+    let mesh = mesh.unwrap();
+    assert_eq!(mesh.face_materials, vec![0, 1, 2, 1]);
+    assert_eq!(mesh.materials.len(), 3);
+  }
+}
\ No newline at end of file
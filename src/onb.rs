@@ -0,0 +1,44 @@
+use crate::vec3::{Vec3, unit_vector, cross, dot};
+
+// Orthonormal basis with w along a given direction.
+#[derive(Debug, Clone, Copy)]
+pub struct Onb {
+  axis: [Vec3; 3]
+}
+
+impl Onb {
+  pub fn build_from_w(n: &Vec3) -> Self {
+    let w = unit_vector(*n);
+    let a = if w.x().abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+    let v = unit_vector(cross(&w, &a));
+    let u = cross(&w, &v);
+    Self { axis: [u, v, w] }
+  }
+
+  // With w along n and u as close to tangent as it can be while at right
+  // angles to n, or any u if tangent is zero or along n.
+  pub fn from_normal_tangent(n: &Vec3, tangent: &Vec3) -> Self {
+    let w = unit_vector(*n);
+    let u = *tangent - dot(tangent, &w)*w;
+    if u.length_squared() <= 1e-12 * tangent.length_squared() { return Self::build_from_w(n) }
+    let u = unit_vector(u);
+    Self { axis: [u, cross(&w, &u), w] }
+  }
+
+  // From axes already known to be orthonormal.
+  pub fn from_axes(u: Vec3, v: Vec3, w: Vec3) -> Self {
+    Self { axis: [u, v, w] }
+  }
+
+  pub fn u(&self) -> Vec3 { self.axis[0] }
+  pub fn v(&self) -> Vec3 { self.axis[1] }
+  pub fn w(&self) -> Vec3 { self.axis[2] }
+
+  pub fn local(&self, a: &Vec3) -> Vec3 {
+    a.x()*self.u() + a.y()*self.v() + a.z()*self.w()
+  }
+
+  pub fn world_to_local(&self, a: &Vec3) -> Vec3 {
+    Vec3::new(dot(a, &self.u()), dot(a, &self.v()), dot(a, &self.w()))
+  }
+}
\ No newline at end of file
@@ -0,0 +1,136 @@
+use std::{fs::{self, File, OpenOptions}, io::{self, BufWriter, Write, Seek}, path::{Path, PathBuf}};
+
+use image::{DynamicImage, ImageBuffer, ImageOutputFormat, ImageResult, Rgb, codecs::hdr::HdrEncoder};
+
+use crate::{vec3::Color, color::{to_rgb8, to_rgb16}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+  Png8,
+  Png16,
+  Hdr,
+  Exr,
+  Ppm
+}
+
+impl Format {
+  pub fn from_path(path: &Path, bit_depth: u8) -> io::Result<Self> {
+    if path.as_os_str() == "-" { return Ok(Format::Ppm) }
+
+    let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+    match (extension.as_deref(), bit_depth) {
+      (Some("png"), 8) => Ok(Format::Png8),
+      (Some("png"), 16) => Ok(Format::Png16),
+      (Some("png"), _) => Err(unsupported(format!("PNG output supports a bit depth of 8 or 16, not {bit_depth}"))),
+      (Some("hdr"), _) => Ok(Format::Hdr),
+      (Some("exr"), _) => Ok(Format::Exr),
+      (Some("ppm"), _) => Ok(Format::Ppm),
+      (Some(e), _) => Err(unsupported(format!("unsupported output format '.{e}' (expected .png, .hdr, .exr or .ppm)"))),
+      (None, _) => Err(unsupported(format!("cannot tell output format of '{}' without an extension", path.display())))
+    }
+  }
+}
+
+fn unsupported(message: String) -> io::Error {
+  io::Error::new(io::ErrorKind::Unsupported, message)
+}
+
+// Destination for the rendered image. The path is checked up front so that
+// an unwritable one is reported before spending time on the render, but the
+// file is only written once the image is finished, so a render that fails or
+// is interrupted leaves an existing image alone.
+pub struct Output {
+  format: Format,
+  path: Option<PathBuf>
+}
+
+impl Output {
+  pub fn create(path: &Path, bit_depth: u8) -> io::Result<Self> {
+    let format = Format::from_path(path, bit_depth)?;
+    if path.as_os_str() == "-" { return Ok(Self { format, path: None }) }
+
+    if path.exists() {
+      OpenOptions::new().append(true).open(path)?;
+    } else {
+      File::create(path)?;
+      fs::remove_file(path)?;
+    }
+    Ok(Self { format, path: Some(path.to_path_buf()) })
+  }
+
+  // pixels: linear radiance, row by row from the top of the image.
+  pub fn write(self, width: u32, height: u32, pixels: &[Color]) -> ImageResult<()> {
+    match self.path {
+      None => write_ppm(&mut io::stdout().lock(), width, height, pixels),
+      Some(path) => {
+        let mut out = BufWriter::new(File::create(path)?);
+        match self.format {
+          Format::Png8 => write_png8(&mut out, width, height, pixels),
+          Format::Png16 => write_png16(&mut out, width, height, pixels),
+          Format::Hdr => write_hdr(&mut out, width, height, pixels),
+          Format::Exr => write_exr(&mut out, width, height, pixels),
+          Format::Ppm => write_ppm(&mut out, width, height, pixels)
+        }?;
+        out.flush()?;
+        Ok(())
+      }
+    }
+  }
+}
+
+fn write_ppm(out: &mut impl Write, width: u32, height: u32, pixels: &[Color]) -> ImageResult<()> {
+  write!(out, "P6\n{width} {height}\n255\n")?;
+  let bytes: Vec<u8> = pixels.iter().flat_map(|c| to_rgb8(*c)).collect();
+  out.write_all(&bytes)?;
+  out.flush()?;
+  Ok(())
+}
+
+fn write_png8<W: Write + Seek>(out: &mut W, width: u32, height: u32, pixels: &[Color]) -> ImageResult<()> {
+  let data = pixels.iter().flat_map(|c| to_rgb8(*c)).collect();
+  let buffer = ImageBuffer::<Rgb<u8>, Vec<u8>>::from_raw(width, height, data).expect("pixel count matches image size");
+  DynamicImage::ImageRgb8(buffer).write_to(out, ImageOutputFormat::Png)
+}
+
+fn write_png16<W: Write + Seek>(out: &mut W, width: u32, height: u32, pixels: &[Color]) -> ImageResult<()> {
+  let data = pixels.iter().flat_map(|c| to_rgb16(*c)).collect();
+  let buffer = ImageBuffer::<Rgb<u16>, Vec<u16>>::from_raw(width, height, data).expect("pixel count matches image size");
+  DynamicImage::ImageRgb16(buffer).write_to(out, ImageOutputFormat::Png)
+}
+
+fn write_hdr(out: &mut impl Write, width: u32, height: u32, pixels: &[Color]) -> ImageResult<()> {
+  let data: Vec<Rgb<f32>> = pixels.iter().map(|c| Rgb([c.x() as f32, c.y() as f32, c.z() as f32])).collect();
+  HdrEncoder::new(out).encode(&data, width as usize, height as usize)
+}
+
+fn write_exr<W: Write + Seek>(out: &mut W, width: u32, height: u32, pixels: &[Color]) -> ImageResult<()> {
+  let data = pixels.iter().flat_map(|c| [c.x() as f32, c.y() as f32, c.z() as f32]).collect();
+  let buffer = ImageBuffer::<Rgb<f32>, Vec<f32>>::from_raw(width, height, data).expect("pixel count matches image size");
+  DynamicImage::ImageRgb32F(buffer).write_to(out, ImageOutputFormat::OpenExr)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn existing_file_is_kept_until_the_image_is_written() {
+    let path = std::env::temp_dir().join(format!("raytracer-output-{}.ppm", std::process::id()));
+    fs::write(&path, b"previous").unwrap();
+    let output = Output::create(&path, 8).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"previous");
+
+    output.write(1, 1, &[Color::new(1.0, 0.0, 0.0)]).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"P6\n1 1\n255\n\xff\x00\x00");
+    fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn new_file_is_not_created_before_writing() {
+    let path = std::env::temp_dir().join(format!("raytracer-output-new-{}.png", std::process::id()));
+    Output::create(&path, 8).unwrap();
+    assert!(!path.exists());
+    assert!(Output::create(Path::new("/nonexistent-directory/image.png"), 8).is_err());
+  }
+}
\ No newline at end of file
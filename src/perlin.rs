@@ -1,4 +1,4 @@
-use crate::{util::{random_int}, vec3::{Point3, Vec3, dot}};
+use crate::{util::{Rng, random_int}, vec3::{Point3, Vec3, dot}};
 
 const POINT_COUNT: usize = 256;
 
@@ -11,15 +11,15 @@ pub struct Perlin {
 }
 
 impl Perlin {
-  pub fn new() -> Self {
+  pub fn new(rng: &mut Rng) -> Self {
     let mut ranvec = [Vec3::zero(); POINT_COUNT];
-    for i in 0..POINT_COUNT { ranvec[i] = Vec3::random_unit_vector() };
+    for i in 0..POINT_COUNT { ranvec[i] = Vec3::random_unit_vector(rng) };
 
     Self {
       ranvec,
-      perm_x: perlin_generate_perm(),
-      perm_y: perlin_generate_perm(),
-      perm_z: perlin_generate_perm(),
+      perm_x: perlin_generate_perm(rng),
+      perm_y: perlin_generate_perm(rng),
+      perm_z: perlin_generate_perm(rng),
     }
   }
 
@@ -67,19 +67,19 @@ impl Perlin {
   }
 }
 
-fn perlin_generate_perm() -> [i32; POINT_COUNT] {
+fn perlin_generate_perm(rng: &mut Rng) -> [i32; POINT_COUNT] {
   let mut p = [0; POINT_COUNT];
 
   for i in 0..POINT_COUNT { p[i] = i as i32 };
 
-  permute(&mut p);
+  permute(&mut p, rng);
 
   p
 }
 
-fn permute(p: &mut [i32; POINT_COUNT]) {
+fn permute(p: &mut [i32; POINT_COUNT], rng: &mut Rng) {
   for i in (1..POINT_COUNT).rev() {
-    let target = random_int(0, i as i32) as usize;
+    let target = random_int(rng, 0, i as i32) as usize;
     (p[i], p[target]) = (p[target], p[i])
   }
 }
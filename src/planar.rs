@@ -0,0 +1,319 @@
+use std::f64::consts::PI;
+
+use crate::{material::Material, hittable::{Hittable, HitRecord}, aabb::AABB, ray::Ray, onb::Onb, vec3::{Point3, Vec3, cross, dot, unit_vector}, sampler::{sample_unit_disk, sample_triangle}, util::Rng};
+
+// The plane through origin facing along the unit normal.
+#[derive(Debug, Clone, Copy)]
+struct Plane {
+  origin: Point3,
+  normal: Vec3
+}
+
+impl Plane {
+  // Where the ray from origin along direction crosses the plane, if within
+  // t_min..t_max.
+  fn intersect(&self, origin: &Point3, direction: &Vec3, t_min: f64, t_max: f64) -> Option<(f64, Point3)> {
+    let denominator = dot(&self.normal, direction);
+    if denominator.abs() < 1e-12 { return None }
+    let t = dot(&self.normal, &(self.origin - *origin)) / denominator;
+    if !(t >= t_min && t <= t_max) { return None }
+    Some((t, *origin + t*(*direction)))
+  }
+
+  fn hit_record<'a>(&self, r: &Ray, t: f64, p: Point3, (u, v): (f64, f64), tangent: Vec3, material: &'a dyn Material) -> HitRecord<'a> {
+    let mut rec = HitRecord { p, normal: Vec3::zero(), material, t, u, v, tangent, color: None, front_face: true };
+    rec.set_face_normal(r, &self.normal);
+    rec
+  }
+
+  // Solid angle density of uniformly sampling a shape of the given area in
+  // the plane, seen along v from o.
+  fn pdf_value(&self, o: &Point3, v: &Vec3, area: f64, inside: impl Fn(&Point3) -> bool) -> f64 {
+    let Some((t, p)) = self.intersect(o, v, 0.001, f64::INFINITY) else { return 0.0 };
+    if !inside(&p) { return 0.0 }
+    let distance_squared = t*t*v.length_squared();
+    let cosine = dot(v, &self.normal).abs() / v.length();
+    distance_squared / (cosine * area)
+  }
+}
+
+fn bounding_box(points: impl IntoIterator<Item = Point3>) -> AABB {
+  // Padded so that shapes in an axis plane still have some thickness.
+  let pad = Vec3::new(0.0001, 0.0001, 0.0001);
+  let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+  let mut max = Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+  for p in points {
+    for c in 0..3 {
+      min[c] = min[c].min(p[c]);
+      max[c] = max[c].max(p[c]);
+    }
+  }
+  AABB::new(min - pad, max + pad)
+}
+
+// Parallelogram with a corner at origin and sides along u and v, facing
+// along u cross v. Texture coordinates run from 0 to 1 along u and v.
+#[derive(Debug, Clone, Copy)]
+pub struct Quad<M: Material> {
+  origin: Point3,
+  u: Vec3,
+  v: Vec3,
+  // Maps a point's offset from origin to its coordinates along u and v.
+  w: Vec3,
+  plane: Plane,
+  material: M
+}
+
+impl<M: Material> Quad<M> {
+  // u and v must not be parallel.
+  pub fn new(origin: Point3, u: Vec3, v: Vec3, material: M) -> Self {
+    let n = cross(&u, &v);
+    let w = n / n.length_squared();
+    Self { origin, u, v, w, plane: Plane { origin, normal: unit_vector(n) }, material }
+  }
+
+  fn coordinates(&self, p: &Point3) -> (f64, f64) {
+    let offset = *p - self.origin;
+    (dot(&self.w, &cross(&offset, &self.v)), dot(&self.w, &cross(&self.u, &offset)))
+  }
+
+  fn inside(&self, p: &Point3) -> bool {
+    let (a, b) = self.coordinates(p);
+    (0.0..=1.0).contains(&a) && (0.0..=1.0).contains(&b)
+  }
+
+  fn area(&self) -> f64 {
+    cross(&self.u, &self.v).length()
+  }
+}
+
+impl<M: Material> Hittable for Quad<M> {
+  fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _rng: &mut Rng) -> Option<HitRecord<'_>> {
+    let (t, p) = self.plane.intersect(&r.origin(), &r.direction(), t_min, t_max)?;
+    if !self.inside(&p) { return None }
+    Some(self.plane.hit_record(r, t, p, self.coordinates(&p), self.u, &self.material))
+  }
+
+  fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+    let o = self.origin;
+    Some(bounding_box([o, o + self.u, o + self.v, o + self.u + self.v]))
+  }
+
+  fn is_light(&self) -> bool { self.material.is_emissive() }
+
+  fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+    self.plane.pdf_value(o, v, self.area(), |p| self.inside(p))
+  }
+
+  fn random(&self, o: &Point3, u: (f64, f64)) -> Vec3 {
+    self.origin + u.0*self.u + u.1*self.v - *o
+  }
+}
+
+// Round disk facing along normal. u runs once around counterclockwise seen
+// from the front and v from the center out to the rim.
+#[derive(Debug, Clone, Copy)]
+pub struct Disk<M: Material> {
+  radius: f64,
+  frame: Onb,
+  plane: Plane,
+  material: M
+}
+
+impl<M: Material> Disk<M> {
+  pub fn new(center: Point3, normal: Vec3, radius: f64, material: M) -> Self {
+    let frame = Onb::build_from_w(&normal);
+    Self { radius, frame, plane: Plane { origin: center, normal: frame.w() }, material }
+  }
+
+  fn inside(&self, p: &Point3) -> bool {
+    (*p - self.plane.origin).length_squared() <= self.radius*self.radius
+  }
+}
+
+impl<M: Material> Hittable for Disk<M> {
+  fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _rng: &mut Rng) -> Option<HitRecord<'_>> {
+    let (t, p) = self.plane.intersect(&r.origin(), &r.direction(), t_min, t_max)?;
+    if !self.inside(&p) { return None }
+    let local = self.frame.world_to_local(&(p - self.plane.origin));
+    let phi = local.y().atan2(local.x()).rem_euclid(2.0*PI);
+    let tangent = self.frame.local(&Vec3::new(-local.y(), local.x(), 0.0));
+    Some(self.plane.hit_record(r, t, p, (phi / (2.0*PI), local.length() / self.radius), tangent, &self.material))
+  }
+
+  fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+    // Along each axis the rim reaches as far as the disk is tilted from it.
+    let n = self.plane.normal;
+    let extent = Vec3::new(
+      (1.0 - n.x()*n.x()).max(0.0).sqrt(),
+      (1.0 - n.y()*n.y()).max(0.0).sqrt(),
+      (1.0 - n.z()*n.z()).max(0.0).sqrt()
+    ) * self.radius;
+    Some(bounding_box([self.plane.origin - extent, self.plane.origin + extent]))
+  }
+
+  fn is_light(&self) -> bool { self.material.is_emissive() }
+
+  fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+    self.plane.pdf_value(o, v, PI*self.radius*self.radius, |p| self.inside(p))
+  }
+
+  fn random(&self, o: &Point3, u: (f64, f64)) -> Vec3 {
+    self.plane.origin + self.radius*self.frame.local(&sample_unit_disk(u)) - *o
+  }
+}
+
+// Flat convex polygon facing the side its vertices run counterclockwise
+// around. Texture coordinates span the polygon's extent along its first
+// edge and across it.
+pub struct Polygon<M: Material> {
+  vertices: Vec<Point3>,
+  frame: Onb,
+  // Vertices in the frame, and the texture coordinates' offset and scale.
+  local: Vec<(f64, f64)>,
+  uv_min: (f64, f64),
+  uv_size: (f64, f64),
+  // Running totals of the areas of the triangles fanning out from the
+  // first vertex, for sampling.
+  fan_areas: Vec<f64>,
+  plane: Plane,
+  material: M
+}
+
+impl<M: Material> Polygon<M> {
+  pub fn new(vertices: Vec<Point3>, material: M) -> Result<Self, String> {
+    let n = vertices.len();
+    if n < 3 { return Err(format!("polygon needs at least 3 vertices, got {n}")) }
+
+    // Newell's method gives the normal scaled by twice the area.
+    let mut normal = Vec3::zero();
+    for i in 0..n {
+      let (a, b) = (vertices[i], vertices[(i + 1) % n]);
+      normal += Vec3::new((a.y() - b.y())*(a.z() + b.z()), (a.z() - b.z())*(a.x() + b.x()), (a.x() - b.x())*(a.y() + b.y()));
+    }
+    if normal.length_squared() == 0.0 { return Err("polygon has no area".to_string()) }
+    let normal = unit_vector(normal);
+
+    let size = bounding_box(vertices.iter().copied());
+    let tolerance = 1e-6 * (size.max() - size.min()).length();
+    if vertices.iter().any(|p| dot(&(*p - vertices[0]), &normal).abs() > tolerance) {
+      return Err("polygon vertices are not in one plane".to_string())
+    }
+
+    // The frame's first axis runs along the first edge that has a length.
+    let Some(edge) = (1..n).map(|i| vertices[i] - vertices[0]).find(|e| e.length_squared() > 0.0) else {
+      return Err("polygon has no area".to_string())
+    };
+    let u_axis = unit_vector(edge);
+    let frame = Onb::from_axes(u_axis, cross(&normal, &u_axis), normal);
+    let local: Vec<(f64, f64)> = vertices.iter().map(|p| {
+      let l = frame.world_to_local(&(*p - vertices[0]));
+      (l.x(), l.y())
+    }).collect();
+
+    // Convex if it only ever turns left, and goes around just once. Turns
+    // are compared by their sine, so short edges count as much as long ones.
+    let mut turning = 0.0;
+    for i in 0..n {
+      let (a, b, c) = (local[i], local[(i + 1) % n], local[(i + 2) % n]);
+      let (e0, e1) = ((b.0 - a.0, b.1 - a.1), (c.0 - b.0, c.1 - b.1));
+      let turn = e0.0*e1.1 - e0.1*e1.0;
+      if turn < -1e-6*e0.0.hypot(e0.1)*e1.0.hypot(e1.1) { return Err("polygon is not convex".to_string()) }
+      if e0 != (0.0, 0.0) && e1 != (0.0, 0.0) { turning += turn.atan2(e0.0*e1.0 + e0.1*e1.1) }
+    }
+    if turning > 3.0*PI { return Err("polygon is not convex".to_string()) }
+
+    let mut fan_areas = Vec::with_capacity(n - 2);
+    let mut total = 0.0;
+    for i in 2..n {
+      total += cross(&(vertices[i - 1] - vertices[0]), &(vertices[i] - vertices[0])).length() / 2.0;
+      fan_areas.push(total);
+    }
+
+    let fold = |f: fn(f64, f64) -> f64, start: f64| local.iter().fold((start, start), |a, l| (f(a.0, l.0), f(a.1, l.1)));
+    let (min, max) = (fold(f64::min, f64::INFINITY), fold(f64::max, f64::NEG_INFINITY));
+    let plane = Plane { origin: vertices[0], normal };
+    Ok(Self { vertices, frame, local, uv_min: min, uv_size: (max.0 - min.0, max.1 - min.1), fan_areas, plane, material })
+  }
+
+  fn local(&self, p: &Point3) -> (f64, f64) {
+    let l = self.frame.world_to_local(&(*p - self.plane.origin));
+    (l.x(), l.y())
+  }
+
+  // On the inner side of every edge.
+  fn inside(&self, p: &Point3) -> bool {
+    let p = self.local(p);
+    let n = self.local.len();
+    (0..n).all(|i| {
+      let (a, b) = (self.local[i], self.local[(i + 1) % n]);
+      (b.0 - a.0)*(p.1 - a.1) - (b.1 - a.1)*(p.0 - a.0) >= 0.0
+    })
+  }
+
+  fn area(&self) -> f64 {
+    *self.fan_areas.last().expect("polygon has at least one triangle")
+  }
+}
+
+impl<M: Material> Hittable for Polygon<M> {
+  fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _rng: &mut Rng) -> Option<HitRecord<'_>> {
+    let (t, p) = self.plane.intersect(&r.origin(), &r.direction(), t_min, t_max)?;
+    if !self.inside(&p) { return None }
+    let l = self.local(&p);
+    let uv = ((l.0 - self.uv_min.0) / self.uv_size.0, (l.1 - self.uv_min.1) / self.uv_size.1);
+    Some(self.plane.hit_record(r, t, p, uv, self.frame.u(), &self.material))
+  }
+
+  fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+    Some(bounding_box(self.vertices.iter().copied()))
+  }
+
+  fn is_light(&self) -> bool { self.material.is_emissive() }
+
+  fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+    self.plane.pdf_value(o, v, self.area(), |p| self.inside(p))
+  }
+
+  fn random(&self, o: &Point3, u: (f64, f64)) -> Vec3 {
+    // Pick a triangle of the fan by area, reusing u.0 within it.
+    let target = u.0 * self.area();
+    let i = self.fan_areas.partition_point(|&a| a <= target).min(self.fan_areas.len() - 1);
+    let start = if i == 0 { 0.0 } else { self.fan_areas[i - 1] };
+    let width = self.fan_areas[i] - start;
+    let u0 = if width > 0.0 { ((target - start) / width).clamp(0.0, 1.0) } else { 0.0 };
+    let b = sample_triangle((u0, u.1));
+    b[0]*self.vertices[0] + b[1]*self.vertices[i + 1] + b[2]*self.vertices[i + 2] - *o
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{material::Lambertian, texture::SolidColor, vec3::Color};
+
+  fn polygon(points: &[(f64, f64)]) -> Result<Polygon<Lambertian<SolidColor>>, String> {
+    let material = Lambertian::new(SolidColor::new(Color::new(0.5, 0.5, 0.5)));
+    Polygon::new(points.iter().map(|&(x, y)| Point3::new(x, y, 0.0)).collect(), material)
+  }
+
+  #[test]
+  fn convexity_does_not_depend_on_edge_lengths() {
+    // A notch far smaller than the polygon still makes it concave.
+    let notched = [(0.0, 0.0), (1000.0, 0.0), (1000.0, 1000.0), (500.0005, 1000.0), (500.0, 999.9995), (499.9995, 1000.0), (0.0, 1000.0)];
+    assert_eq!(polygon(&notched).err(), Some("polygon is not convex".to_string()));
+    // While a vertex all but in line with its long edges leaves it convex.
+    let bent = [(0.0, 0.0), (1000.0, 0.0), (1000.0, 1000.0), (500.0, 1000.0 - 1e-7), (0.0, 1000.0)];
+    assert!(polygon(&bent).is_ok());
+    // And scaling a polygon changes neither.
+    let tiny = notched.map(|(x, y)| (x * 1e-6, y * 1e-6));
+    assert!(polygon(&tiny).is_err());
+    assert!(polygon(&[(0.0, 0.0), (1e-6, 0.0), (1e-6, 1e-6), (0.0, 1e-6)]).is_ok());
+  }
+
+  #[test]
+  fn polygons_winding_twice_are_rejected() {
+    let star = [(1.0, 0.0), (-0.81, 0.59), (0.31, -0.95), (0.31, 0.95), (-0.81, -0.59)];
+    assert_eq!(polygon(&star).err(), Some("polygon is not convex".to_string()));
+  }
+}
\ No newline at end of file
@@ -0,0 +1,402 @@
+use std::{fmt, fs, path::{Path, PathBuf}, str::{Lines, SplitWhitespace}, sync::Arc};
+
+use crate::{
+  vec3::{Point3, Vec3, Color},
+  material::{Material, Lambertian},
+  texture::VertexColorTexture,
+  triangle::MeshData
+};
+
+#[derive(Debug)]
+pub struct PlyError {
+  path: PathBuf,
+  message: String
+}
+
+impl fmt::Display for PlyError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}: {}", self.path.display(), self.message)
+  }
+}
+
+impl std::error::Error for PlyError {}
+
+type Result<T> = std::result::Result<T, String>;
+
+#[derive(Debug, Clone, Copy)]
+enum Scalar { I8, U8, I16, U16, I32, U32, F32, F64 }
+
+impl Scalar {
+  fn parse(name: &str) -> Option<Self> {
+    Some(match name {
+      "char" | "int8" => Scalar::I8,
+      "uchar" | "uint8" => Scalar::U8,
+      "short" | "int16" => Scalar::I16,
+      "ushort" | "uint16" => Scalar::U16,
+      "int" | "int32" => Scalar::I32,
+      "uint" | "uint32" => Scalar::U32,
+      "float" | "float32" => Scalar::F32,
+      "double" | "float64" => Scalar::F64,
+      _ => return None
+    })
+  }
+
+  // What colors stored in this type are divided by to bring them to 0..1.
+  fn color_scale(self) -> f64 {
+    match self {
+      Scalar::F32 | Scalar::F64 => 1.0,
+      Scalar::I16 | Scalar::U16 => 65535.0,
+      _ => 255.0
+    }
+  }
+}
+
+enum Property {
+  Scalar(Scalar),
+  List { count: Scalar, item: Scalar }
+}
+
+struct Element {
+  name: String,
+  count: usize,
+  properties: Vec<(String, Property)>
+}
+
+impl Element {
+  fn find(&self, names: &[&str]) -> Option<usize> {
+    self.properties.iter().position(|(name, _)| names.contains(&name.as_str()))
+  }
+}
+
+// Loads the faces of a PLY file, ASCII or binary, as one mesh, with
+// polygons split into fans. Vertex normals, texture coordinates and colors
+// are kept when the file has them. Without a material, vertex colors are
+// used as a Lambertian albedo if there are any, and plain grey if not.
+pub fn load_ply(path: &Path, material: Option<Arc<dyn Material>>) -> std::result::Result<MeshData<Arc<dyn Material>>, PlyError> {
+  let error = |message: String| PlyError { path: path.to_path_buf(), message };
+  let bytes = fs::read(path).map_err(|e| error(format!("could not read file: {e}")))?;
+  let (format, elements, start, header_lines) = header(&bytes).map_err(error)?;
+  let mut reader: Box<dyn Reader> = match format.as_str() {
+    "ascii" => {
+      let text = std::str::from_utf8(&bytes[start..]).map_err(|_| error("ASCII data is not valid UTF-8".to_string()))?;
+      Box::new(AsciiReader { lines: text.lines(), tokens: "".split_whitespace(), line: header_lines, remaining: text.len() })
+    },
+    "binary_little_endian" => Box::new(BinaryReader { bytes: &bytes, pos: start, big_endian: false }),
+    "binary_big_endian" => Box::new(BinaryReader { bytes: &bytes, pos: start, big_endian: true }),
+    _ => return Err(error(format!("unknown format '{format}'")))
+  };
+
+  let mut mesh = MeshData {
+    positions: Vec::new(),
+    normals: Vec::new(),
+    uvs: Vec::new(),
+    colors: Vec::new(),
+    indices: Vec::new(),
+    materials: Vec::new(),
+    face_materials: Vec::new()
+  };
+  for element in &elements {
+    let read = match element.name.as_str() {
+      "vertex" => read_vertices(reader.as_mut(), element, &mut mesh),
+      "face" => read_faces(reader.as_mut(), element, &mut mesh),
+      _ => (0..element.count).try_for_each(|_| read_record(reader.as_mut(), element, &mut Vec::new(), &mut Vec::new(), None))
+    };
+    read.map_err(|e| error(format!("{}: {e}", reader.position())))?;
+  }
+
+  mesh.materials.push(match material {
+    Some(material) => material,
+    None if !mesh.colors.is_empty() => Arc::new(Lambertian::new(VertexColorTexture)),
+    None => Arc::new(Lambertian::solid(Color::new(0.8, 0.8, 0.8)))
+  });
+  mesh.validate().map_err(error)?;
+  Ok(mesh)
+}
+
+// Parses the header, returning the format, the elements, where the data
+// starts and the number of lines the header took.
+fn header(bytes: &[u8]) -> Result<(String, Vec<Element>, usize, usize)> {
+  let mut format = None;
+  let mut elements: Vec<Element> = Vec::new();
+  let mut pos = 0;
+  let mut line = 0;
+  loop {
+    let end = bytes[pos..].iter().position(|&b| b == b'\n').ok_or("header has no end_header")? + pos;
+    line += 1;
+    let text = std::str::from_utf8(&bytes[pos..end]).map_err(|_| format!("line {line}: header is not valid text"))?.trim();
+    pos = end + 1;
+    let at = |message: &str| format!("line {line}: {message}");
+
+    let tokens: Vec<&str> = text.split_whitespace().collect();
+    match tokens.as_slice() {
+      ["ply"] if line == 1 => {},
+      _ if line == 1 => return Err("not a PLY file".to_string()),
+      ["format", name, "1.0"] => format = Some(name.to_string()),
+      ["format", ..] => return Err(at("unsupported format")),
+      ["comment", ..] | ["obj_info", ..] | [] => {},
+      ["element", name, count] => {
+        let count = count.parse().map_err(|_| at(&format!("invalid count '{count}'")))?;
+        elements.push(Element { name: name.to_string(), count, properties: Vec::new() });
+      },
+      ["property", "list", count, item, name] => {
+        let element = elements.last_mut().ok_or_else(|| at("property before any element"))?;
+        let (count, item) = match (Scalar::parse(count), Scalar::parse(item)) {
+          (Some(count), Some(item)) => (count, item),
+          _ => return Err(at("unknown property type"))
+        };
+        element.properties.push((name.to_string(), Property::List { count, item }));
+      },
+      ["property", kind, name] => {
+        let element = elements.last_mut().ok_or_else(|| at("property before any element"))?;
+        let kind = Scalar::parse(kind).ok_or_else(|| at(&format!("unknown property type '{kind}'")))?;
+        element.properties.push((name.to_string(), Property::Scalar(kind)));
+      },
+      ["end_header"] => break,
+      _ => return Err(at(&format!("unexpected '{text}'")))
+    }
+  }
+  Ok((format.ok_or("header has no format")?, elements, pos, line))
+}
+
+fn read_vertices(reader: &mut dyn Reader, element: &Element, mesh: &mut MeshData<Arc<dyn Material>>) -> Result<()> {
+  let all = |names: [&[&str]; 3]| -> Option<[usize; 3]> {
+    Some([element.find(names[0])?, element.find(names[1])?, element.find(names[2])?])
+  };
+  let position = all([&["x"], &["y"], &["z"]]).ok_or("vertices have no x, y and z")?;
+  let normal = all([&["nx"], &["ny"], &["nz"]]);
+  let color = all([&["red", "diffuse_red", "r"], &["green", "diffuse_green", "g"], &["blue", "diffuse_blue", "b"]]);
+  let uv = match (element.find(&["u", "s", "texture_u", "texture_s"]), element.find(&["v", "t", "texture_v", "texture_t"])) {
+    (Some(u), Some(v)) => Some([u, v]),
+    _ => None
+  };
+  let color_scale = match color {
+    Some([red, ..]) => match element.properties[red].1 {
+      Property::Scalar(kind) => kind.color_scale(),
+      Property::List { .. } => return Err("vertex colors must not be lists".to_string())
+    },
+    None => 1.0
+  };
+
+  // Counts come straight from the header, so only as much is reserved as
+  // the rest of the file could hold, every record taking at least a byte.
+  mesh.positions.reserve(element.count.min(reader.remaining()));
+  let mut values = Vec::with_capacity(element.properties.len());
+  for _ in 0..element.count {
+    read_record(reader, element, &mut values, &mut Vec::new(), None)?;
+    mesh.positions.push(Point3::new(values[position[0]], values[position[1]], values[position[2]]));
+    if let Some([x, y, z]) = normal { mesh.normals.push(Vec3::new(values[x], values[y], values[z])) }
+    if let Some([u, v]) = uv { mesh.uvs.push((values[u], values[v])) }
+    if let Some([r, g, b]) = color { mesh.colors.push(Color::new(values[r], values[g], values[b]) / color_scale) }
+  }
+  Ok(())
+}
+
+fn read_faces(reader: &mut dyn Reader, element: &Element, mesh: &mut MeshData<Arc<dyn Material>>) -> Result<()> {
+  let list = element.find(&["vertex_indices", "vertex_index"]).ok_or("faces have no vertex_indices")?;
+  mesh.indices.reserve(element.count.min(reader.remaining()));
+  let mut values = Vec::with_capacity(element.properties.len());
+  let mut polygon = Vec::new();
+  for _ in 0..element.count {
+    read_record(reader, element, &mut values, &mut polygon, Some(list))?;
+    let index = |i: f64| if i >= 0.0 && i.fract() == 0.0 { Ok(i as usize) } else { Err(format!("invalid vertex index {i}")) };
+    for i in 2..polygon.len() {
+      mesh.indices.push([index(polygon[0])?, index(polygon[i - 1])?, index(polygon[i])?]);
+    }
+  }
+  Ok(())
+}
+
+// Reads one record of element into values, one per scalar property, with
+// the items of the list property kept, if any, going into items and other
+// lists skipped.
+fn read_record(reader: &mut dyn Reader, element: &Element, values: &mut Vec<f64>, items: &mut Vec<f64>, keep: Option<usize>) -> Result<()> {
+  reader.start_record()?;
+  values.clear();
+  for (i, (_, property)) in element.properties.iter().enumerate() {
+    match property {
+      Property::Scalar(kind) => values.push(reader.scalar(*kind)?),
+      Property::List { count, item } => {
+        let count = reader.scalar(*count)?;
+        if count < 0.0 || count.fract() != 0.0 { return Err(format!("invalid list length {count}")) }
+        if keep == Some(i) { items.clear() }
+        for _ in 0..count as usize {
+          let value = reader.scalar(*item)?;
+          if keep == Some(i) { items.push(value) }
+        }
+        values.push(0.0);
+      }
+    }
+  }
+  reader.end_record()
+}
+
+trait Reader {
+  fn scalar(&mut self, kind: Scalar) -> Result<f64>;
+
+  // ASCII records are a line each.
+  fn start_record(&mut self) -> Result<()> { Ok(()) }
+  fn end_record(&mut self) -> Result<()> { Ok(()) }
+
+  // Where in the file reading has got to, for errors.
+  fn position(&self) -> String;
+
+  // Bytes of the file not yet read.
+  fn remaining(&self) -> usize;
+}
+
+struct AsciiReader<'a> {
+  lines: Lines<'a>,
+  tokens: SplitWhitespace<'a>,
+  line: usize,
+  remaining: usize
+}
+
+impl Reader for AsciiReader<'_> {
+  fn scalar(&mut self, _kind: Scalar) -> Result<f64> {
+    let token = self.tokens.next().ok_or("too few values")?;
+    token.parse().map_err(|_| format!("invalid number '{token}'"))
+  }
+
+  fn start_record(&mut self) -> Result<()> {
+    loop {
+      let line = self.lines.next().ok_or("unexpected end of file")?;
+      self.line += 1;
+      self.remaining = self.remaining.saturating_sub(line.len() + 1);
+      if !line.trim().is_empty() {
+        self.tokens = line.split_whitespace();
+        return Ok(())
+      }
+    }
+  }
+
+  fn end_record(&mut self) -> Result<()> {
+    match self.tokens.next() {
+      Some(_) => Err("too many values".to_string()),
+      None => Ok(())
+    }
+  }
+
+  fn position(&self) -> String { format!("line {}", self.line) }
+  fn remaining(&self) -> usize { self.remaining }
+}
+
+struct BinaryReader<'a> {
+  bytes: &'a [u8],
+  pos: usize,
+  big_endian: bool
+}
+
+impl BinaryReader<'_> {
+  fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+    let bytes = self.bytes.get(self.pos..self.pos + N).ok_or("unexpected end of file")?;
+    self.pos += N;
+    let mut array: [u8; N] = bytes.try_into().expect("slice has N bytes");
+    if self.big_endian { array.reverse() }
+    Ok(array)
+  }
+}
+
+impl Reader for BinaryReader<'_> {
+  fn scalar(&mut self, kind: Scalar) -> Result<f64> {
+    Ok(match kind {
+      Scalar::I8 => f64::from(i8::from_le_bytes(self.take()?)),
+      Scalar::U8 => f64::from(u8::from_le_bytes(self.take()?)),
+      Scalar::I16 => f64::from(i16::from_le_bytes(self.take()?)),
+      Scalar::U16 => f64::from(u16::from_le_bytes(self.take()?)),
+      Scalar::I32 => f64::from(i32::from_le_bytes(self.take()?)),
+      Scalar::U32 => f64::from(u32::from_le_bytes(self.take()?)),
+      Scalar::F32 => f64::from(f32::from_le_bytes(self.take()?)),
+      Scalar::F64 => f64::from_le_bytes(self.take()?)
+    })
+  }
+
+  fn position(&self) -> String { format!("byte {}", self.pos) }
+  fn remaining(&self) -> usize { self.bytes.len().saturating_sub(self.pos) }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn load(test: &str, bytes: &[u8]) -> (PathBuf, std::result::Result<MeshData<Arc<dyn Material>>, PlyError>) {
+    let path = std::env::temp_dir().join(format!("raytracer-ply-{test}-{}.ply", std::process::id()));
+    fs::write(&path, bytes).unwrap();
+    let mesh = load_ply(&path, None);
+    fs::remove_file(&path).unwrap();
+    (path, mesh)
+  }
+
+  fn header(format: &str) -> String {
+    format!("ply\nformat {format} 1.0\ncomment a unit quad\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+      property uchar red\nproperty uchar green\nproperty uchar blue\nelement face 1\nproperty list uchar float texcoord\n\
+      property list uchar int vertex_indices\nelement edge 1\nproperty list uchar int vertex\nend_header\n")
+  }
+
+  const POSITIONS: [[f32; 3]; 4] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+
+  fn binary(big_endian: bool) -> Vec<u8> {
+    let mut bytes = header(if big_endian { "binary_big_endian" } else { "binary_little_endian" }).into_bytes();
+    let f32_bytes = |x: f32| if big_endian { x.to_be_bytes() } else { x.to_le_bytes() };
+    let i32_bytes = |x: i32| if big_endian { x.to_be_bytes() } else { x.to_le_bytes() };
+    for (i, p) in POSITIONS.iter().enumerate() {
+      p.iter().for_each(|&c| bytes.extend(f32_bytes(c)));
+      bytes.extend([255, 0, 51 * i as u8]);
+    }
+    // Texture coordinates on the face come first and are skipped.
+    bytes.push(8);
+    (0..8).for_each(|i| bytes.extend(f32_bytes(i as f32 / 8.0)));
+    bytes.push(4);
+    (0..4).for_each(|i| bytes.extend(i32_bytes(i)));
+    bytes.push(2);
+    [0, 2].iter().for_each(|&i| bytes.extend(i32_bytes(i)));
+    bytes
+  }
+
+  fn ascii() -> Vec<u8> {
+    let mut text = header("ascii");
+    for (i, p) in POSITIONS.iter().enumerate() {
+      text += &format!("{} {} {} 255 0 {}\n", p[0], p[1], p[2], 51 * i);
+    }
+    text += "8 0 0.125 0.25 0.375 0.5 0.625 0.75 0.875 4 0 1 2 3\n\n2 0 2\n";
+    text.into_bytes()
+  }
+
+  // Each vertex's position followed by its color.
+  fn vertices(mesh: &MeshData<Arc<dyn Material>>) -> Vec<[f64; 6]> {
+    mesh.positions.iter().zip(&mesh.colors).map(|(p, c)| [p.x(), p.y(), p.z(), c.x(), c.y(), c.z()]).collect()
+  }
+
+  #[test]
+  fn ascii_and_binary_files_agree() {
+    let (_, mesh) = load("ascii", &ascii());
+    let mesh = mesh.unwrap();
+    let expected = vertices(&mesh);
+    assert_eq!(expected.len(), 4);
+    assert_eq!(expected[2], [1.0, 1.0, 0.0, 1.0, 0.0, 0.4]);
+    assert_eq!(mesh.indices, vec![[0, 1, 2], [0, 2, 3]]);
+
+    for (test, big_endian) in [("little", false), ("big", true)] {
+      let (_, binary) = load(test, &binary(big_endian));
+      let binary = binary.unwrap();
+      assert_eq!(vertices(&binary), expected);
+      assert_eq!(binary.indices, mesh.indices);
+    }
+  }
+
+  #[test]
+  fn truncated_files_report_where_they_end() {
+    let mut bytes = binary(false);
+    bytes.truncate(bytes.len() - 2);
+    let (path, mesh) = load("truncated", &bytes);
+    match mesh {
+      Ok(_) => panic!("expected an error"),
+      Err(e) => assert_eq!(e.to_string(), format!("{}: byte {}: unexpected end of file", path.display(), bytes.len() - 2))
+    }
+
+    let (path, mesh) = load("short-line", b"ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nproperty float y\nproperty float z\nend_header\n0 1\n");
+    match mesh {
+      Ok(_) => panic!("expected an error"),
+      Err(e) => assert_eq!(e.to_string(), format!("{}: line 8: too few values", path.display()))
+    }
+  }
+}
\ No newline at end of file
@@ -0,0 +1,361 @@
+use std::f64::consts::PI;
+
+use crate::{
+  ray::Ray,
+  hittable::HitRecord,
+  material::{Material, ScatterRecord},
+  texture::Texture,
+  vec3::{Color, Vec3, Point3, unit_vector, dot},
+  onb::Onb,
+  sampler::sample_unit_sphere,
+  microfacet::{TrowbridgeReitz, reflect_about, refract_through, half_vector, fresnel_dielectric}
+};
+
+// Index of refraction of the clearcoat layer.
+const COAT_IOR: f64 = 1.5;
+
+// Keeps every lobe a proper microfacet lobe, so they can all be sampled and
+// evaluated in any direction.
+const MIN_ALPHA: f64 = 2e-3;
+
+// Disney-style uber material. From the top, an optional clearcoat over a
+// blend of metal, rough glass tinted by the base color, and a dielectric
+// specular layer over diffuse with sheen. The glass tint is split between
+// the surfaces light passes through, the square root of the base color at
+// each, so light through a closed object in and out again is tinted by the
+// base color just once. Each layer only gets the energy the ones above it
+// let through. Scalar parameters are read from the first channel of their
+// textures, and specular sets the index of refraction, with 0.5 giving 1.5.
+#[derive(Debug, Clone, Copy)]
+pub struct Principled<T: Texture> {
+  pub base_color: T,
+  pub metallic: T,
+  pub roughness: T,
+  pub specular: T,
+  pub transmission: T,
+  pub clearcoat: T,
+  pub clearcoat_roughness: T,
+  pub sheen: T,
+  pub emission: Option<T>
+}
+
+// The material at one hit, in the local shading frame.
+struct Lobes {
+  frame: Onb,
+  wo: Vec3,
+  base_color: Color,
+  sheen: Color,
+  metallic: f64,
+  transmission: f64,
+  clearcoat: f64,
+  // Index of refraction on the far side relative to this side.
+  eta: f64,
+  specular: TrowbridgeReitz,
+  coat: TrowbridgeReitz,
+  // Chances of sampling the clearcoat, metal, glass, dielectric specular and
+  // diffuse lobes.
+  weights: [f64; 5]
+}
+
+impl<T: Texture> Principled<T> {
+  fn lobes(&self, r_in: &Ray, rec: &HitRecord) -> Option<Lobes> {
+    let frame = Onb::from_normal_tangent(&rec.normal, &rec.tangent);
+    let wo = frame.world_to_local(&-unit_vector(r_in.direction()));
+    if wo.z() <= 0.0 { return None }
+
+    let value = |t: &T| t.value_at(rec);
+    let scalar = |t: &T| value(t).x().clamp(0.0, 1.0);
+    let f0 = (0.08 * scalar(&self.specular)).sqrt();
+    let ior = (1.0 + f0) / (1.0 - f0);
+
+    // Rays inside a transmissive surface have already been refracted by the
+    // glass; opaque surfaces look the same from behind, as one-sided shapes
+    // and meshes wound the other way are seen.
+    let transmission = scalar(&self.transmission);
+    let (metallic, transmission, clearcoat, eta) = if rec.front_face || transmission == 0.0 {
+      (scalar(&self.metallic), transmission, scalar(&self.clearcoat), ior)
+    } else {
+      (0.0, 1.0, 0.0, 1.0 / ior)
+    };
+
+    let alpha = TrowbridgeReitz::roughness_to_alpha(scalar(&self.roughness)).max(MIN_ALPHA);
+    let coat_alpha = TrowbridgeReitz::roughness_to_alpha(scalar(&self.clearcoat_roughness)).max(MIN_ALPHA);
+
+    let coat = clearcoat * fresnel_dielectric(wo.z(), COAT_IOR);
+    let layer = 1.0 - coat;
+    let dielectric = layer * (1.0 - metallic) * (1.0 - transmission);
+    let fresnel = fresnel_dielectric(wo.z(), eta);
+    let mut weights = [coat, layer * metallic, layer * (1.0 - metallic) * transmission, dielectric * fresnel, dielectric * (1.0 - fresnel)];
+    let total: f64 = weights.iter().sum();
+    if total <= 0.0 { return None }
+    weights.iter_mut().for_each(|w| *w /= total);
+
+    Some(Lobes {
+      frame, wo,
+      base_color: value(&self.base_color),
+      sheen: value(&self.sheen),
+      metallic, transmission, clearcoat, eta,
+      specular: TrowbridgeReitz::new(alpha, alpha),
+      coat: TrowbridgeReitz::new(coat_alpha, coat_alpha),
+      weights
+    })
+  }
+}
+
+impl Lobes {
+  // BSDF times cosine for light arriving from wi.
+  fn f(&self, wi: &Vec3) -> Color {
+    let wo = self.wo;
+    let layer = 1.0 - self.clearcoat * fresnel_dielectric(wo.z(), COAT_IOR);
+
+    if wi.z() > 0.0 {
+      let wm = unit_vector(wo + *wi);
+      let cos_h = dot(&wo, &wm);
+      let microfacet = |distribution: &TrowbridgeReitz| distribution.d(&wm) * distribution.g(&wo, wi) / (4.0 * wo.z());
+
+      let coat = self.clearcoat * fresnel_dielectric(cos_h, COAT_IOR) * microfacet(&self.coat);
+      let specular = microfacet(&self.specular);
+      let metal = schlick(&self.base_color, cos_h) * specular;
+      let diffuse = (self.base_color / PI + self.sheen * (1.0 - dot(wi, &wm)).powi(5))
+        * (wi.z() * (1.0 - fresnel_dielectric(wo.z(), self.eta)));
+
+      // Glass and the dielectric specular layer reflect alike.
+      let dielectric = Color::new(1.0, 1.0, 1.0) * (fresnel_dielectric(cos_h, self.eta) * specular) + diffuse * (1.0 - self.transmission);
+      Color::new(coat, coat, coat) + layer * (self.metallic * metal + (1.0 - self.metallic) * dielectric)
+    } else {
+      let Some((wm, false)) = half_vector(&wo, wi, self.eta) else { return Color::zero() };
+      let distribution = &self.specular;
+      let denom = (dot(wi, &wm) + dot(&wo, &wm) / self.eta).powi(2) * wo.z();
+      let transmitted = distribution.d(&wm) * distribution.g(&wo, wi) * (1.0 - fresnel_dielectric(dot(&wo, &wm), self.eta))
+        * (dot(wi, &wm) * dot(&wo, &wm) / denom).abs() / (self.eta * self.eta);
+      let tint = Color::new(self.base_color.x().sqrt(), self.base_color.y().sqrt(), self.base_color.z().sqrt());
+      tint * (layer * (1.0 - self.metallic) * self.transmission * transmitted)
+    }
+  }
+
+  fn pdf(&self, wi: &Vec3) -> f64 {
+    let wo = self.wo;
+    let [coat, metal, glass, specular, diffuse] = self.weights;
+
+    if wi.z() > 0.0 {
+      let wm = unit_vector(wo + *wi);
+      let reflected = |distribution: &TrowbridgeReitz| distribution.pdf(&wo, &wm) / (4.0 * dot(&wo, &wm).abs());
+      let fresnel = fresnel_dielectric(dot(&wo, &wm), self.eta);
+      coat * reflected(&self.coat) + (metal + glass * fresnel + specular) * reflected(&self.specular) + diffuse * wi.z() / PI
+    } else {
+      let Some((wm, false)) = half_vector(&wo, wi, self.eta) else { return 0.0 };
+      let denom = (dot(wi, &wm) + dot(&wo, &wm) / self.eta).powi(2);
+      let fresnel = fresnel_dielectric(dot(&wo, &wm), self.eta);
+      glass * self.specular.pdf(&wo, &wm) * dot(wi, &wm).abs() / denom * (1.0 - fresnel)
+    }
+  }
+
+  // Picks a lobe with uc and samples a direction from it.
+  fn sample(&self, uc: f64, u: (f64, f64)) -> Option<Vec3> {
+    let wo = self.wo;
+    let mut uc = uc;
+    let mut lobe = 0;
+    while lobe < self.weights.len() - 1 && (self.weights[lobe] == 0.0 || uc >= self.weights[lobe]) {
+      uc -= self.weights[lobe];
+      lobe += 1;
+    }
+    // What's left of uc, rescaled, chooses between glass reflection and
+    // transmission.
+    let uc = (uc / self.weights[lobe]).clamp(0.0, 1.0);
+
+    match lobe {
+      0 => Some(reflect_about(&wo, &self.coat.sample_wm(&wo, u))),
+      2 => {
+        let wm = self.specular.sample_wm(&wo, u);
+        if uc < fresnel_dielectric(dot(&wo, &wm), self.eta) {
+          Some(reflect_about(&wo, &wm))
+        } else {
+          refract_through(&wo, &wm, self.eta)
+        }
+      },
+      4 => Some(unit_vector(Vec3::new(0.0, 0.0, 1.0) + sample_unit_sphere(u))),
+      _ => Some(reflect_about(&wo, &self.specular.sample_wm(&wo, u)))
+    }
+  }
+}
+
+// Schlick's approximation to the reflectance of a metal with the given color
+// at normal incidence.
+fn schlick(f0: &Color, cos_theta: f64) -> Color {
+  let t = (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5);
+  *f0 + (Color::new(1.0, 1.0, 1.0) - *f0) * t
+}
+
+impl<T: Texture> Material for Principled<T> {
+  fn scatter(&self, r_in: &Ray, rec: &HitRecord, uc: f64, u: (f64, f64)) -> Option<ScatterRecord> {
+    let lobes = self.lobes(r_in, rec)?;
+    let wi = lobes.sample(uc, u)?;
+    if wi.z() == 0.0 { return None }
+
+    // f * cos / pdf over all the lobes, so it agrees with eval and
+    // scattering_pdf.
+    let pdf = lobes.pdf(&wi);
+    let f = lobes.f(&wi);
+    if pdf <= 0.0 || f.near_zero() { return None }
+
+    Some(ScatterRecord {
+      attenuation: f / pdf,
+      scattered: Ray::new(rec.p, lobes.frame.local(&wi), r_in.time()),
+      is_specular: false
+    })
+  }
+
+  fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+    match &self.emission {
+      Some(emission) => emission.value(u, v, p),
+      None => Color::zero()
+    }
+  }
+
+  fn is_emissive(&self) -> bool { self.emission.is_some() }
+
+  fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+    let Some(lobes) = self.lobes(r_in, rec) else { return Color::zero() };
+    lobes.f(&lobes.frame.world_to_local(&unit_vector(*direction)))
+  }
+
+  fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+    let Some(lobes) = self.lobes(r_in, rec) else { return 0.0 };
+    lobes.pdf(&lobes.frame.world_to_local(&unit_vector(*direction)))
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{texture::SolidColor, planar::Quad, hittable::Hittable, util::{seeded_rng, random_double}};
+
+  fn grey(v: f64) -> SolidColor { SolidColor::new(Color::new(v, v, v)) }
+
+  // White, with the given metallic, roughness, specular and transmission.
+  fn principled(metallic: f64, roughness: f64, specular: f64, transmission: f64) -> Principled<SolidColor> {
+    Principled {
+      base_color: grey(1.0), metallic: grey(metallic), roughness: grey(roughness), specular: grey(specular), transmission: grey(transmission),
+      clearcoat: grey(0.0), clearcoat_roughness: grey(0.0), sheen: grey(0.0), emission: None
+    }
+  }
+
+  // A ray arriving at angle theta from the normal of a surface at the origin,
+  // and its hit from the front or from behind.
+  fn hit(material: &dyn Material, theta: f64, front_face: bool) -> (Ray, HitRecord<'_>) {
+    let (sin, cos) = theta.to_radians().sin_cos();
+    let normal = if front_face { Vec3::new(0.0, 0.0, 1.0) } else { Vec3::new(0.0, 0.0, -1.0) };
+    let from = Vec3::new(sin, 0.0, 0.0) + cos * normal;
+    let r = Ray::new(Point3::zero() + from, -from, 0.0);
+    (r, HitRecord { p: Point3::zero(), normal, material, t: 1.0, u: 0.5, v: 0.5, tangent: Vec3::new(1.0, 0.0, 0.0), color: None, front_face })
+  }
+
+  // Fraction of the energy arriving from one direction that the BSDF sends
+  // on, estimated from its own samples. Transmitted radiance is scaled back
+  // by the squared relative index of refraction it was divided by.
+  fn albedo(material: &Principled<SolidColor>, theta: f64, front_face: bool) -> f64 {
+    let (r, rec) = hit(material, theta, front_face);
+    let lobes = material.lobes(&r, &rec).unwrap();
+    let mut rng = seeded_rng(1);
+    let n = 40000;
+    let total: f64 = (0..n).map(|_| {
+      let Some(wi) = lobes.sample(random_double(&mut rng), (random_double(&mut rng), random_double(&mut rng))) else { return 0.0 };
+      let pdf = lobes.pdf(&wi);
+      if pdf <= 0.0 { return 0.0 }
+      let f = lobes.f(&wi).x() / pdf;
+      if wi.z() < 0.0 { f * lobes.eta * lobes.eta } else { f }
+    }).sum();
+    total / n as f64
+  }
+
+  #[test]
+  fn white_diffuse_keeps_all_energy() {
+    // No specular layer at all leaves a Lambertian surface.
+    for theta in [0.0, 45.0, 80.0] {
+      let a = albedo(&principled(0.0, 1.0, 0.0, 0.0), theta, true);
+      assert!((a - 1.0).abs() < 0.01, "{theta}: {a}");
+    }
+  }
+
+  #[test]
+  fn lobes_never_create_energy() {
+    let cases = [
+      ("plastic", principled(0.0, 0.5, 0.5, 0.0), 0.9),
+      ("smooth metal", principled(1.0, 0.2, 0.5, 0.0), 0.95),
+      // Light scattered between microfacets more than once is lost, most of
+      // all on rough surfaces: at normal incidence, 1 - ln 2 of it stays.
+      ("rough metal", principled(1.0, 1.0, 0.5, 0.0), 0.25),
+      ("glass", principled(0.0, 0.1, 0.5, 1.0), 0.95)
+    ];
+    for (name, material, least) in cases {
+      for theta in [0.0, 30.0, 60.0] {
+        for front_face in [true, false] {
+          let a = albedo(&material, theta, front_face);
+          assert!(a <= 1.01 && a >= least, "{name} at {theta} from the {}: {a}", if front_face { "front" } else { "back" });
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn pdf_matches_sampling() {
+    // Integrating the pdf over the sphere gives the chance that sampling
+    // produces a direction at all.
+    for material in [principled(0.0, 1.0, 0.0, 0.0), principled(0.0, 0.5, 0.5, 0.0), principled(0.3, 0.4, 0.5, 0.6)] {
+      let (r, rec) = hit(&material, 40.0, true);
+      let lobes = material.lobes(&r, &rec).unwrap();
+      let k = 400;
+      let integral = (0..k * k).map(|i| {
+        let u = (((i % k) as f64 + 0.5) / k as f64, ((i / k) as f64 + 0.5) / k as f64);
+        lobes.pdf(&sample_unit_sphere(u)) * 4.0 * PI
+      }).sum::<f64>() / (k * k) as f64;
+      let mut rng = seeded_rng(2);
+      let n = 40000;
+      let sampled = (0..n).filter(|_| {
+        lobes.sample(random_double(&mut rng), (random_double(&mut rng), random_double(&mut rng))).is_some_and(|wi| lobes.pdf(&wi) > 0.0)
+      }).count() as f64 / n as f64;
+      assert!((integral - sampled).abs() < 0.02, "{integral} != {sampled}");
+    }
+  }
+
+  #[test]
+  fn scatter_agrees_with_eval_and_pdf() {
+    let mut material = principled(0.3, 0.4, 0.5, 0.6);
+    material.base_color = SolidColor::new(Color::new(0.9, 0.5, 0.2));
+    material.clearcoat = grey(0.5);
+    material.sheen = grey(0.3);
+    let mut rng = seeded_rng(3);
+    for front_face in [true, false] {
+      let (r, rec) = hit(&material, 35.0, front_face);
+      let mut scattered = 0;
+      for _ in 0..1000 {
+        let Some(srec) = material.scatter(&r, &rec, random_double(&mut rng), (random_double(&mut rng), random_double(&mut rng))) else { continue };
+        let direction = srec.scattered.direction();
+        let expected = material.eval(&r, &rec, &direction) / material.scattering_pdf(&r, &rec, &direction);
+        assert!((srec.attenuation - expected).length() < 1e-9 * expected.length().max(1.0), "{:?} != {expected:?}", srec.attenuation);
+        scattered += 1;
+      }
+      assert!(scattered > 900);
+    }
+  }
+
+  #[test]
+  fn opaque_quad_seen_from_behind_does_not_transmit() {
+    let quad = Quad::new(Point3::new(-1.0, -1.0, 0.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0), principled(0.0, 0.5, 0.5, 0.0));
+    let r = Ray::new(Point3::new(0.2, 0.1, -1.0), Vec3::new(0.1, 0.0, 1.0), 0.0);
+    let rec = quad.hit(&r, 0.001, f64::INFINITY, &mut seeded_rng(4)).unwrap();
+    assert!(!rec.front_face);
+
+    let material = principled(0.0, 0.5, 0.5, 0.0);
+    let lobes = material.lobes(&r, &rec).unwrap();
+    assert_eq!(lobes.transmission, 0.0);
+    assert_eq!(lobes.weights[2], 0.0);
+    let mut rng = seeded_rng(5);
+    for _ in 0..1000 {
+      let Some(srec) = material.scatter(&r, &rec, random_double(&mut rng), (random_double(&mut rng), random_double(&mut rng))) else { continue };
+      // Everything is reflected back to the side the ray came from.
+      assert!(dot(&srec.scattered.direction(), &rec.normal) > 0.0);
+    }
+  }
+}
\ No newline at end of file
@@ -0,0 +1,106 @@
+use crate::vec3::{Point3, Vec3, Color, dot, unit_vector};
+
+// Light from a single point or direction. These can never be hit by a ray, so
+// they only contribute through shadow rays cast by the integrator.
+pub trait PunctualLight: Send + Sync {
+  // Unit direction from p towards the light, the distance to it and the
+  // light arriving at p, or None if no light reaches p.
+  fn sample(&self, p: &Point3, time: f64) -> Option<(Vec3, f64, Color)>;
+}
+
+// Radiates intensity equally in all directions, falling off with the square
+// of the distance. The light moves from position0 to position1 between time0
+// and time1.
+#[derive(Debug, Clone, Copy)]
+pub struct PointLight {
+  position0: Point3, position1: Point3,
+  time0: f64, time1: f64,
+  intensity: Color
+}
+
+impl PointLight {
+  pub fn new(position0: Point3, position1: Point3, time0: f64, time1: f64, intensity: Color) -> Self {
+    Self { position0, position1, time0, time1, intensity }
+  }
+}
+
+impl PunctualLight for PointLight {
+  fn sample(&self, p: &Point3, time: f64) -> Option<(Vec3, f64, Color)> {
+    let position = lerp(self.position0, self.position1, self.time0, self.time1, time);
+    let (direction, distance) = towards(p, &position)?;
+    Some((direction, distance, self.intensity / (distance*distance)))
+  }
+}
+
+// A point light restricted to a cone around direction, fading out smoothly
+// from falloff_start to angle (both half-angles in degrees).
+#[derive(Debug, Clone, Copy)]
+pub struct SpotLight {
+  position0: Point3, position1: Point3,
+  time0: f64, time1: f64,
+  direction: Vec3,
+  intensity: Color,
+  cos_total: f64,
+  cos_falloff_start: f64
+}
+
+impl SpotLight {
+  pub fn new(position0: Point3, position1: Point3, time0: f64, time1: f64, direction: Vec3, angle: f64, falloff_start: f64, intensity: Color) -> Self {
+    Self {
+      position0, position1, time0, time1,
+      direction: unit_vector(direction),
+      intensity,
+      cos_total: angle.to_radians().cos(),
+      cos_falloff_start: falloff_start.min(angle).to_radians().cos()
+    }
+  }
+
+  fn falloff(&self, cos_theta: f64) -> f64 {
+    if cos_theta >= self.cos_falloff_start { return 1.0 }
+    if cos_theta <= self.cos_total { return 0.0 }
+    let t = (cos_theta - self.cos_total) / (self.cos_falloff_start - self.cos_total);
+    t*t*(3.0 - 2.0*t)
+  }
+}
+
+impl PunctualLight for SpotLight {
+  fn sample(&self, p: &Point3, time: f64) -> Option<(Vec3, f64, Color)> {
+    let position = lerp(self.position0, self.position1, self.time0, self.time1, time);
+    let (direction, distance) = towards(p, &position)?;
+    let falloff = self.falloff(dot(&-direction, &self.direction));
+    if falloff == 0.0 { return None }
+    Some((direction, distance, self.intensity * (falloff / (distance*distance))))
+  }
+}
+
+// Parallel light from infinitely far away, travelling along direction, such
+// as the sun. irradiance is measured on a surface facing the light.
+#[derive(Debug, Clone, Copy)]
+pub struct DirectionalLight {
+  direction: Vec3,
+  irradiance: Color
+}
+
+impl DirectionalLight {
+  pub fn new(direction: Vec3, irradiance: Color) -> Self {
+    Self { direction: unit_vector(direction), irradiance }
+  }
+}
+
+impl PunctualLight for DirectionalLight {
+  fn sample(&self, _p: &Point3, _time: f64) -> Option<(Vec3, f64, Color)> {
+    Some((-self.direction, f64::INFINITY, self.irradiance))
+  }
+}
+
+fn lerp(p0: Point3, p1: Point3, time0: f64, time1: f64, time: f64) -> Point3 {
+  if time0 == time1 { return p0 }
+  p0 + ((time - time0) / (time1 - time0))*(p1 - p0)
+}
+
+fn towards(p: &Point3, position: &Point3) -> Option<(Vec3, f64)> {
+  let offset = *position - *p;
+  let distance = offset.length();
+  if distance == 0.0 { return None }
+  Some((offset / distance, distance))
+}
\ No newline at end of file
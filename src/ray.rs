@@ -4,17 +4,24 @@ use crate::vec3::{Vec3, Point3};
 pub struct Ray {
   dir: Vec3,
   orig: Point3,
-  tm: f64
+  tm: f64,
+  // Hero wavelength in nanometres when rendering spectrally.
+  wavelength: Option<f64>
 }
 
 impl Ray {
   pub fn new(origin: Point3, direction: Vec3, time: f64) -> Self {
-    Ray { orig: origin, dir: direction, tm: time }
+    Ray { orig: origin, dir: direction, tm: time, wavelength: None }
   }
 
   pub fn direction(&self) -> Point3 { self.dir }
   pub fn origin(&self) -> Point3 { self.orig }
   pub fn time(&self) -> f64 { self.tm }
+  pub fn wavelength(&self) -> Option<f64> { self.wavelength }
+
+  pub fn with_wavelength(self, wavelength: Option<f64>) -> Self {
+    Ray { wavelength, ..self }
+  }
 
   pub fn at(&self, t: f64) -> Point3 {
     self.orig + t*self.dir
@@ -0,0 +1,287 @@
+use std::sync::Mutex;
+
+use indicatif::ProgressBar;
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use crate::{camera::Camera, hittable::{Hittable, HitRecord}, environment::Environment, light::Lights, ray::Ray, util::{Rng, sample_rng, fmax}, vec3::Color, sampler::{Sampler, SamplerKind}, spectrum::SampledWavelengths};
+
+const TILE_SIZE: i32 = 32;
+
+pub struct Renderer<'a> {
+  pub camera: &'a Camera,
+  pub world: &'a dyn Hittable,
+  pub lights: &'a Lights,
+  pub background: &'a dyn Environment,
+  pub image_width: i32,
+  pub image_height: i32,
+  pub samples_per_pixel: i32,
+  pub max_depth: i32,
+  pub rr_depth: i32,
+  pub spectral: bool,
+  pub sampler: SamplerKind,
+  pub seed: u64
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Tile {
+  x0: i32, x1: i32,
+  y0: i32, y1: i32
+}
+
+impl Renderer<'_> {
+  pub fn tile_count(&self) -> u64 {
+    let tiles_x = (self.image_width + TILE_SIZE - 1) / TILE_SIZE;
+    let tiles_y = (self.image_height + TILE_SIZE - 1) / TILE_SIZE;
+    tiles_x as u64 * tiles_y as u64
+  }
+
+  fn tiles(&self) -> Vec<Tile> {
+    let mut tiles = Vec::new();
+    for y0 in (0..self.image_height).step_by(TILE_SIZE as usize) {
+      for x0 in (0..self.image_width).step_by(TILE_SIZE as usize) {
+        tiles.push(Tile {
+          x0, x1: (x0 + TILE_SIZE).min(self.image_width),
+          y0, y1: (y0 + TILE_SIZE).min(self.image_height)
+        });
+      }
+    }
+    tiles
+  }
+
+  // Renders the image tile by tile on the rayon thread pool, returning the
+  // averaged pixel colors row by row from the top of the image.
+  pub fn render(&self, bar: &ProgressBar) -> Vec<Color> {
+    let width = self.image_width as usize;
+    let framebuffer = Mutex::new(vec![Color::zero(); width * self.image_height as usize]);
+
+    self.tiles().into_par_iter().for_each(|tile| {
+      let pixels = self.render_tile(&tile);
+      let tile_width = (tile.x1 - tile.x0) as usize;
+
+      let mut framebuffer = framebuffer.lock().unwrap();
+      for (row, y) in (tile.y0..tile.y1).enumerate() {
+        let start = y as usize * width + tile.x0 as usize;
+        framebuffer[start..start + tile_width].copy_from_slice(&pixels[row * tile_width..(row + 1) * tile_width]);
+      }
+      drop(framebuffer);
+
+      bar.inc(1);
+    });
+
+    framebuffer.into_inner().unwrap()
+  }
+
+  fn render_tile(&self, tile: &Tile) -> Vec<Color> {
+    let mut pixels = Vec::with_capacity(((tile.x1 - tile.x0) * (tile.y1 - tile.y0)) as usize);
+    let mut sampler = self.sampler.create(self.samples_per_pixel, self.seed);
+    for y in tile.y0..tile.y1 {
+      // Image rows are stored top to bottom, but v runs from the bottom up.
+      let j = self.image_height - 1 - y;
+      for i in tile.x0..tile.x1 {
+        let mut pixel_color = Color::zero();
+        let pixel = y as u64 * self.image_width as u64 + i as u64;
+        for s in 0..self.samples_per_pixel {
+          sampler.start_pixel_sample(i, y, s as u32);
+          let mut rng = sample_rng(self.seed, pixel, s as u64);
+
+          let (du, dv) = sampler.get_2d();
+          // Each pixel covers an equal share of the frame, so a single pixel
+          // still has a view.
+          let u = (i as f64 + du) / self.image_width as f64;
+          let v = (j as f64 + dv) / self.image_height as f64;
+          let lens = sampler.get_2d();
+          let time = sampler.get_1d();
+          let wavelengths = self.spectral.then(|| SampledWavelengths::sample(sampler.get_1d()));
+          let r = self.camera.get_ray(u, v, lens, time).with_wavelength(wavelengths.map(|w| w.hero()));
+          let radiance = self.ray_color(r, wavelengths.as_ref(), sampler.as_mut(), &mut rng);
+          pixel_color += match wavelengths {
+            Some(wavelengths) => wavelengths.to_rgb(radiance),
+            None => radiance
+          };
+        }
+        pixels.push(pixel_color / self.samples_per_pixel as f64);
+      }
+    }
+    pixels
+  }
+
+  // Follows one path, tracking the throughput of the bounces so far. bsdf_pdf
+  // is the density with which the last bounce sampled the ray, or None when
+  // it couldn't also have been found by light sampling (camera rays and
+  // specular bounces). Emission found both ways is weighted by MIS. When
+  // rendering spectrally, each channel holds one of the sampled wavelengths.
+  fn ray_color(&self, mut r: Ray, wavelengths: Option<&SampledWavelengths>, sampler: &mut dyn Sampler, rng: &mut Rng) -> Color {
+    let mut radiance = Color::zero();
+    let mut throughput = Color::new(1.0, 1.0, 1.0);
+    let mut bsdf_pdf: Option<f64> = None;
+    let mut dispersed = false;
+
+    // max_depth stays a hard cap on the number of bounces.
+    for depth in 0..self.max_depth {
+      let rec = match self.world.hit(&r, 0.001, f64::INFINITY, rng) {
+        Some(rec) => rec,
+        None => {
+          let mut background = spectrum(wavelengths, self.background.value(&r.direction()));
+          if let Some(bsdf_pdf) = bsdf_pdf {
+            background *= power_heuristic(bsdf_pdf, self.lights.pdf_value(&r.origin(), &r.direction()));
+          }
+          radiance += throughput * background;
+          break
+        }
+      };
+
+      let mut emitted = spectrum(wavelengths, rec.material.emitted(rec.u, rec.v, &rec.p));
+      if let Some(bsdf_pdf) = bsdf_pdf {
+        emitted *= power_heuristic(bsdf_pdf, self.lights.pdf_value(&r.origin(), &r.direction()));
+      }
+      radiance += throughput * emitted;
+
+      let uc = sampler.get_1d();
+      let u = sampler.get_2d();
+      let light_uc = sampler.get_1d();
+      let light_u = sampler.get_2d();
+      let rr_u = sampler.get_1d();
+
+      let srec = match rec.material.scatter(&r, &rec, uc, u) {
+        Some(srec) => srec,
+        None => break
+      };
+
+      // Past a dispersive surface the other wavelengths would have gone
+      // elsewhere, so only the hero wavelength carries on.
+      if let Some(wavelengths) = wavelengths {
+        if !dispersed && rec.material.is_dispersive() {
+          throughput = wavelengths.keep_hero(throughput);
+          dispersed = true;
+        }
+      }
+
+      if srec.is_specular {
+        bsdf_pdf = None;
+      } else {
+        radiance += throughput * (self.sample_light(&r, &rec, wavelengths, light_uc, light_u, rng) + self.punctual_light(&r, &rec, wavelengths, rng));
+        bsdf_pdf = Some(rec.material.scattering_pdf(&r, &rec, &srec.scattered.direction()));
+      }
+      throughput = throughput * spectrum(wavelengths, srec.attenuation);
+
+      // Russian roulette: end low-throughput paths early, boosting the ones
+      // that survive so the estimate stays unbiased.
+      if depth + 1 >= self.rr_depth {
+        let survive = fmax(throughput.x(), fmax(throughput.y(), throughput.z()));
+        if survive < 1.0 {
+          if rr_u >= survive { break }
+          throughput /= survive;
+        }
+      }
+
+      r = srec.scattered.with_wavelength(r.wavelength());
+    }
+
+    radiance
+  }
+
+  // Next event estimation: light arriving at rec from a direction chosen
+  // towards one of the lights, weighted against finding it by BSDF sampling.
+  fn sample_light(&self, r: &Ray, rec: &HitRecord, wavelengths: Option<&SampledWavelengths>, uc: f64, u: (f64, f64), rng: &mut Rng) -> Color {
+    if self.lights.is_empty() { return Color::zero() }
+    let direction = self.lights.random(&rec.p, uc, u);
+    let light_pdf = self.lights.pdf_value(&rec.p, &direction);
+    if light_pdf <= 0.0 { return Color::zero() }
+
+    let f = rec.material.eval(r, rec, &direction);
+    if f.near_zero() { return Color::zero() }
+
+    let shadow_ray = Ray::new(rec.p, direction, r.time());
+    let emitted = match self.world.hit(&shadow_ray, 0.001, f64::INFINITY, rng) {
+      Some(light_rec) => light_rec.material.emitted(light_rec.u, light_rec.v, &light_rec.p),
+      None => self.background.value(&direction)
+    };
+    let weight = power_heuristic(light_pdf, rec.material.scattering_pdf(r, rec, &direction));
+    spectrum(wavelengths, f) * spectrum(wavelengths, emitted) * (weight / light_pdf)
+  }
+
+  // Light from every punctual light that isn't blocked, which can only be
+  // found with shadow rays.
+  fn punctual_light(&self, r: &Ray, rec: &HitRecord, wavelengths: Option<&SampledWavelengths>, rng: &mut Rng) -> Color {
+    let mut radiance = Color::zero();
+    for light in self.lights.punctual() {
+      let Some((direction, distance, incident)) = light.sample(&rec.p, r.time()) else { continue };
+
+      let f = rec.material.eval(r, rec, &direction);
+      if f.near_zero() { continue }
+
+      let shadow_ray = Ray::new(rec.p, direction, r.time());
+      if self.world.hit(&shadow_ray, 0.001, distance - 0.001, rng).is_none() {
+        radiance += spectrum(wavelengths, f) * spectrum(wavelengths, incident);
+      }
+    }
+    radiance
+  }
+}
+
+// Color values at the sampled wavelengths, or unchanged when rendering RGB.
+fn spectrum(wavelengths: Option<&SampledWavelengths>, color: Color) -> Color {
+  match wavelengths {
+    Some(wavelengths) => wavelengths.upsample(color),
+    None => color
+  }
+}
+
+// Weight for a sample drawn with density f when another strategy would have
+// drawn it with density g.
+fn power_heuristic(f: f64, g: f64) -> f64 {
+  let (f2, g2) = (f*f, g*g);
+  if f2 + g2 == 0.0 { 0.0 } else { f2 / (f2 + g2) }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Arc;
+
+  use super::*;
+  use crate::{sphere::Sphere, material::{Material, Lambertian, ScatterRecord}, hittable::HitRecord, ray::Ray, texture::SolidColor, environment::ConstantEnvironment, sampler::IndependentSampler, vec3::{Point3, Vec3}};
+
+  // A diffuse surface that also glows.
+  struct Glow(Lambertian<SolidColor>, Color);
+
+  impl Material for Glow {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, uc: f64, u: (f64, f64)) -> Option<ScatterRecord> { self.0.scatter(r_in, rec, uc, u) }
+    fn emitted(&self, _u: f64, _v: f64, _p: &Point3) -> Color { self.1 }
+    fn is_emissive(&self) -> bool { true }
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color { self.0.eval(r_in, rec, direction) }
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 { self.0.scattering_pdf(r_in, rec, direction) }
+  }
+
+  // Seen from inside a closed diffuse sphere of albedo a that glows with e,
+  // every bounce adds e and passes on a of the rest: e / (1 - a) in all.
+  fn furnace(rr_depth: i32) -> f64 {
+    let world = Sphere::new(Point3::zero(), 1.0, Glow(Lambertian::solid(Color::new(0.5, 0.5, 0.5)), Color::new(1.0, 1.0, 1.0)));
+    let background: Arc<dyn Environment> = Arc::new(ConstantEnvironment::new(Color::zero()));
+    let (_, lights) = Lights::collect(Vec::new(), &background, Vec::new());
+    let camera = Camera::new(Point3::zero(), Point3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0), 90.0, 1.0, 0.0, 1.0, 0.0, 0.0);
+    let renderer = Renderer {
+      camera: &camera, world: &world, lights: &lights, background: background.as_ref(),
+      image_width: 1, image_height: 1, samples_per_pixel: 1,
+      max_depth: 200, rr_depth, spectral: false, sampler: SamplerKind::Independent, seed: 0
+    };
+
+    let mut sampler = IndependentSampler::new(7);
+    let mut rng = sample_rng(7, 0, 0);
+    let samples = 20000;
+    let total: f64 = (0..samples).map(|s| {
+      sampler.start_pixel_sample(0, 0, s);
+      let r = camera.get_ray(0.5, 0.5, (0.5, 0.5), 0.0);
+      renderer.ray_color(r, None, &mut sampler, &mut rng).x()
+    }).sum();
+    total / samples as f64
+  }
+
+  #[test]
+  fn russian_roulette_stays_unbiased() {
+    // Without roulette paths run to max_depth; with it from the first bounce
+    // most end after a few, but on average they agree.
+    let (full, roulette) = (furnace(1000), furnace(1));
+    assert!((full - 2.0).abs() < 0.01, "{full}");
+    assert!((roulette - 2.0).abs() < 0.05, "{roulette}");
+  }
+}
\ No newline at end of file
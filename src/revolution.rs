@@ -0,0 +1,380 @@
+use std::f64::consts::PI;
+
+use crate::{vec3::{Point3, Vec3, dot, unit_vector}, hittable::{Hittable, HitRecord}, ray::Ray, material::Material, aabb::AABB, mat4::Mat4, util::Rng};
+
+// Surfaces of revolution about the Y axis, stood on a base point. u is the
+// angle around the axis as in get_sphere_uv, and v runs up the side.
+
+fn around_y(p: &Vec3) -> f64 {
+  ((-p.z()).atan2(p.x()) + PI) / (2.0*PI)
+}
+
+// Direction around_y increases in at p.
+fn around_y_tangent(p: &Vec3) -> Vec3 {
+  Vec3::new(p.z(), 0.0, -p.x())
+}
+
+// Roots of a*t^2 + 2*half_b*t + c, computed so that neither loses precision
+// to cancellation. A zero a leaves one root, and an infinite one that no
+// range accepts.
+fn quadratic_roots(a: f64, half_b: f64, c: f64) -> Option<[f64; 2]> {
+  let discriminant = half_b*half_b - a*c;
+  if discriminant < 0.0 { return None }
+  let q = -(half_b + discriminant.sqrt().copysign(half_b));
+  let (t0, t1) = (q / a, c / q);
+  Some(if t0 <= t1 { [t0, t1] } else { [t1, t0] })
+}
+
+fn in_range(t: f64, t_min: f64, t_max: f64) -> bool {
+  t >= t_min && t <= t_max
+}
+
+// A hit in the shape's own space, with the base at the origin.
+struct LocalHit {
+  t: f64,
+  outward_normal: Vec3,
+  uv: (f64, f64),
+  tangent: Vec3
+}
+
+impl LocalHit {
+  fn record<'a>(self, r: &Ray, material: &'a dyn Material) -> HitRecord<'a> {
+    let outward_normal = unit_vector(self.outward_normal);
+    let mut rec = HitRecord { p: r.at(self.t), normal: outward_normal, material, t: self.t, u: self.uv.0, v: self.uv.1, tangent: self.tangent, color: None, front_face: true };
+    rec.set_face_normal(r, &outward_normal);
+    rec
+  }
+}
+
+// The flat end at height y of a shape with the given radius there, facing
+// up or down. v runs from the center out to the rim.
+fn cap(o: &Point3, d: &Vec3, y: f64, radius: f64, up: bool, t_min: f64, t_max: f64) -> Option<LocalHit> {
+  let t = (y - o.y()) / d.y();
+  if !in_range(t, t_min, t_max) { return None }
+  let p = *o + t*(*d);
+  let distance = (p.x()*p.x() + p.z()*p.z()).sqrt();
+  if distance > radius { return None }
+  let outward_normal = Vec3::new(0.0, if up { 1.0 } else { -1.0 }, 0.0);
+  Some(LocalHit { t, outward_normal, uv: (around_y(&p), distance / radius), tangent: around_y_tangent(&p) })
+}
+
+fn nearest(a: Option<LocalHit>, b: Option<LocalHit>) -> Option<LocalHit> {
+  match (a, b) {
+    (Some(a), Some(b)) => Some(if b.t < a.t { b } else { a }),
+    (a, b) => a.or(b)
+  }
+}
+
+// Truncated cone from radius0 at the base to radius1 height above it,
+// either of which may be zero for a pointed cone. Caps close off the ends
+// with a radius.
+#[derive(Debug, Clone, Copy)]
+pub struct Cone<M: Material> {
+  base: Point3,
+  radius0: f64,
+  radius1: f64,
+  height: f64,
+  capped: bool,
+  material: M
+}
+
+impl<M: Material> Cone<M> {
+  pub fn new(base: Point3, radius0: f64, radius1: f64, height: f64, capped: bool, material: M) -> Self {
+    Self { base, radius0, radius1, height, capped, material }
+  }
+
+  pub fn cylinder(base: Point3, radius: f64, height: f64, capped: bool, material: M) -> Self {
+    Self::new(base, radius, radius, height, capped, material)
+  }
+}
+
+impl<M: Material> Hittable for Cone<M> {
+  fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _rng: &mut Rng) -> Option<HitRecord<'_>> {
+    let o = r.origin() - self.base;
+    let d = r.direction();
+
+    // x^2 + z^2 = (radius0 + slope*y)^2
+    let slope = (self.radius1 - self.radius0) / self.height;
+    let radius_o = self.radius0 + slope*o.y();
+    let a = d.x()*d.x() + d.z()*d.z() - slope*slope*d.y()*d.y();
+    let half_b = o.x()*d.x() + o.z()*d.z() - radius_o*slope*d.y();
+    let c = o.x()*o.x() + o.z()*o.z() - radius_o*radius_o;
+
+    let mut hit = quadratic_roots(a, half_b, c).and_then(|roots| roots.into_iter().find_map(|t| {
+      if !in_range(t, t_min, t_max) { return None }
+      let p = o + t*d;
+      if p.y() < 0.0 || p.y() > self.height { return None }
+      let outward_normal = Vec3::new(p.x(), -(self.radius0 + slope*p.y())*slope, p.z());
+      Some(LocalHit { t, outward_normal, uv: (around_y(&p), p.y() / self.height), tangent: around_y_tangent(&p) })
+    }));
+
+    if self.capped {
+      let t_max = hit.as_ref().map_or(t_max, |h| h.t);
+      if self.radius0 > 0.0 { hit = nearest(hit, cap(&o, &d, 0.0, self.radius0, false, t_min, t_max)) }
+      if self.radius1 > 0.0 { hit = nearest(hit, cap(&o, &d, self.height, self.radius1, true, t_min, t_max)) }
+    }
+    Some(hit?.record(r, &self.material))
+  }
+
+  fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+    let radius = self.radius0.max(self.radius1);
+    Some(AABB::new(self.base + Vec3::new(-radius, 0.0, -radius), self.base + Vec3::new(radius, self.height, radius)))
+  }
+
+  // The cone is the hull of its two end circles, so its box is theirs.
+  fn transformed_bounding_box(&self, to_world: &Mat4, _time0: f64, _time1: f64) -> Option<AABB> {
+    let bottom = to_world.transform_circle_bbox(&self.base, self.radius0);
+    let top = to_world.transform_circle_bbox(&(self.base + Vec3::new(0.0, self.height, 0.0)), self.radius1);
+    Some(AABB::surrounding_box(&bottom, &top))
+  }
+}
+
+// Bowl with its lowest point on base, widening to radius height above it,
+// where a cap may close it.
+#[derive(Debug, Clone, Copy)]
+pub struct Paraboloid<M: Material> {
+  base: Point3,
+  radius: f64,
+  height: f64,
+  capped: bool,
+  material: M
+}
+
+impl<M: Material> Paraboloid<M> {
+  pub fn new(base: Point3, radius: f64, height: f64, capped: bool, material: M) -> Self {
+    Self { base, radius, height, capped, material }
+  }
+}
+
+impl<M: Material> Hittable for Paraboloid<M> {
+  fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _rng: &mut Rng) -> Option<HitRecord<'_>> {
+    let o = r.origin() - self.base;
+    let d = r.direction();
+
+    // x^2 + z^2 = k*y
+    let k = self.radius*self.radius / self.height;
+    let a = d.x()*d.x() + d.z()*d.z();
+    let half_b = o.x()*d.x() + o.z()*d.z() - 0.5*k*d.y();
+    let c = o.x()*o.x() + o.z()*o.z() - k*o.y();
+
+    let mut hit = quadratic_roots(a, half_b, c).and_then(|roots| roots.into_iter().find_map(|t| {
+      if !in_range(t, t_min, t_max) { return None }
+      let p = o + t*d;
+      if p.y() > self.height { return None }
+      let outward_normal = Vec3::new(2.0*p.x(), -k, 2.0*p.z());
+      Some(LocalHit { t, outward_normal, uv: (around_y(&p), p.y() / self.height), tangent: around_y_tangent(&p) })
+    }));
+
+    if self.capped {
+      let t_max = hit.as_ref().map_or(t_max, |h| h.t);
+      hit = nearest(hit, cap(&o, &d, self.height, self.radius, true, t_min, t_max));
+    }
+    Some(hit?.record(r, &self.material))
+  }
+
+  fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+    Some(AABB::new(self.base + Vec3::new(-self.radius, 0.0, -self.radius), self.base + Vec3::new(self.radius, self.height, self.radius)))
+  }
+
+  // Unlike the cone's, the sides bulge out past the hull of the rim and the
+  // apex. At height y they reach a*y + radius*s*sqrt(y/height) from the
+  // apex along a world axis, with a and s the parts of the transform's row
+  // for that axis along and across Y, so the box around the rim and apex
+  // is widened to where that peaks.
+  fn transformed_bounding_box(&self, to_world: &Mat4, _time0: f64, _time1: f64) -> Option<AABB> {
+    let rim = to_world.transform_circle_bbox(&(self.base + Vec3::new(0.0, self.height, 0.0)), self.radius);
+    let apex = to_world.transform_point(&self.base);
+    let (x, y, z) = (to_world.transform_vector(&Vec3::new(1.0, 0.0, 0.0)), to_world.transform_vector(&Vec3::new(0.0, 1.0, 0.0)), to_world.transform_vector(&Vec3::new(0.0, 0.0, 1.0)));
+    let peak = |a: f64, s: f64| {
+      if a >= 0.0 { return 0.0 }
+      let y = ((self.radius*s / (2.0*a)).powi(2) / self.height).min(self.height);
+      a*y + self.radius*s*(y / self.height).sqrt()
+    };
+    let (mut min, mut max) = (rim.min(), rim.max());
+    for i in 0..3 {
+      let s = x[i].hypot(z[i]);
+      min[i] = min[i].min(apex[i]).min(apex[i] - peak(-y[i], s));
+      max[i] = max[i].max(apex[i]).max(apex[i] + peak(y[i], s));
+    }
+    Some(AABB::new(min, max))
+  }
+}
+
+// Ring around center in the XZ plane: a tube of minor_radius swept around a
+// circle of major_radius. v runs around the tube, from its outer equator
+// over the top.
+#[derive(Debug, Clone, Copy)]
+pub struct Torus<M: Material> {
+  center: Point3,
+  major_radius: f64,
+  minor_radius: f64,
+  material: M
+}
+
+impl<M: Material> Torus<M> {
+  pub fn new(center: Point3, major_radius: f64, minor_radius: f64, material: M) -> Self {
+    Self { center, major_radius, minor_radius, material }
+  }
+}
+
+impl<M: Material> Hittable for Torus<M> {
+  fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _rng: &mut Rng) -> Option<HitRecord<'_>> {
+    // The quartic is solved for the distance along a unit direction from
+    // where the ray passes closest to the center, which keeps its
+    // coefficients small wherever the ray starts.
+    let length = r.direction().length();
+    let d = r.direction() / length;
+    let start = -dot(&(r.origin() - self.center), &d);
+    let o = r.origin() - self.center + start*d;
+
+    // (|p|^2 - R^2 - r^2)^2 = 4 R^2 (r^2 - y^2), with |d| = 1.
+    let (major2, minor2) = (self.major_radius*self.major_radius, self.minor_radius*self.minor_radius);
+    let f = dot(&o, &d);
+    let e = o.length_squared() - major2 - minor2;
+    let coefficients = [
+      1.0,
+      4.0*f,
+      4.0*f*f + 2.0*e + 4.0*major2*d.y()*d.y(),
+      4.0*f*e + 8.0*major2*o.y()*d.y(),
+      e*e + 4.0*major2*(o.y()*o.y() - minor2)
+    ];
+
+    // Hits are within the sphere around the whole ring.
+    let reach = self.major_radius + self.minor_radius;
+    let half_chord_squared = reach*reach - o.length_squared();
+    if half_chord_squared < 0.0 { return None }
+    let half_chord = half_chord_squared.sqrt();
+
+    let (count, roots) = real_roots(&coefficients, -half_chord, half_chord);
+    let s = roots[..count].iter().copied()
+      .find(|s| in_range((start + s) / length, t_min, t_max))?;
+    let t = (start + s) / length;
+    let p = o + s*d;
+    let ring = (p.x()*p.x() + p.z()*p.z()).sqrt();
+    let outward_normal = p - Vec3::new(p.x(), 0.0, p.z()) * (self.major_radius / ring);
+    let v = p.y().atan2(ring - self.major_radius).rem_euclid(2.0*PI) / (2.0*PI);
+    Some(LocalHit { t, outward_normal, uv: (around_y(&p), v), tangent: around_y_tangent(&p) }.record(r, &self.material))
+  }
+
+  fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+    let reach = self.major_radius + self.minor_radius;
+    let extent = Vec3::new(reach, self.minor_radius, reach);
+    Some(AABB::new(self.center - extent, self.center + extent))
+  }
+
+  // The torus is a ball swept around a circle, so its box is the circle's
+  // grown by the ball's.
+  fn transformed_bounding_box(&self, to_world: &Mat4, _time0: f64, _time1: f64) -> Option<AABB> {
+    let circle = to_world.transform_circle_bbox(&self.center, self.major_radius);
+    let ball = to_world.transform_sphere_bbox(&self.center, self.minor_radius);
+    let reach = (ball.max() - ball.min()) / 2.0;
+    Some(AABB::new(circle.min() - reach, circle.max() + reach))
+  }
+}
+
+fn evaluate(coefficients: &[f64], x: f64) -> f64 {
+  coefficients.iter().fold(0.0, |sum, c| sum*x + c)
+}
+
+// Real roots between low and high of the polynomial with the given
+// coefficients, highest degree first and at most quartic, in increasing
+// order, as a count and the roots. Roots of the derivative split the range
+// into stretches where the polynomial only rises or falls, so each holds at
+// most one root, found by Newton's method kept inside the stretch by
+// bisection. Roots where it just touches zero are missed.
+fn real_roots(coefficients: &[f64], low: f64, high: f64) -> (usize, [f64; 4]) {
+  let degree = coefficients.len() - 1;
+  let mut roots = [0.0; 4];
+  if degree == 1 {
+    roots[0] = -coefficients[1] / coefficients[0];
+    return (usize::from(roots[0] >= low && roots[0] <= high), roots)
+  }
+
+  let mut derivative = [0.0; 4];
+  for i in 0..degree { derivative[i] = coefficients[i] * (degree - i) as f64 }
+  let derivative = &derivative[..degree];
+  let (turns, turning_points) = real_roots(derivative, low, high);
+  let mut ends = [low; 5];
+  ends[1..=turns].copy_from_slice(&turning_points[..turns]);
+  ends[turns + 1] = high;
+
+  let mut count = 0;
+  for pair in ends[..turns + 2].windows(2) {
+    let (mut low, mut high) = (pair[0], pair[1]);
+    let f_low = evaluate(coefficients, low);
+    let f_high = evaluate(coefficients, high);
+    if f_low == 0.0 { roots[count] = low; count += 1; continue }
+    if (f_low < 0.0) == (f_high < 0.0) { continue }
+    // Orient the bracket so the polynomial is negative at low.
+    if f_low > 0.0 { (low, high) = (high, low) }
+    let mut x = 0.5*(low + high);
+    for _ in 0..100 {
+      let f = evaluate(coefficients, x);
+      if f == 0.0 { break }
+      if f < 0.0 { low = x } else { high = x }
+      let newton = x - f / evaluate(derivative, x);
+      let next = if (newton - low)*(newton - high) < 0.0 { newton } else { 0.5*(low + high) };
+      if (next - x).abs() <= 1e-12*(1.0 + x.abs()) { x = next; break }
+      x = next;
+    }
+    roots[count] = x;
+    count += 1;
+  }
+  (count, roots)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{material::Lambertian, texture::SolidColor, vec3::Color, util::seeded_rng};
+
+  // Coefficients, highest degree first, of the polynomial with these roots.
+  fn from_roots(roots: &[f64]) -> Vec<f64> {
+    roots.iter().fold(vec![1.0], |p, r| {
+      let mut next = p.clone();
+      next.push(0.0);
+      for (i, c) in p.iter().enumerate() { next[i + 1] -= r * c }
+      next
+    })
+  }
+
+  fn assert_roots(coefficients: &[f64], low: f64, high: f64, expected: &[f64]) {
+    let (count, roots) = real_roots(coefficients, low, high);
+    assert_eq!(count, expected.len(), "{:?}", &roots[..count]);
+    for (root, want) in roots.iter().zip(expected) {
+      assert!((root - want).abs() < 1e-9, "{root} != {want}");
+    }
+  }
+
+  #[test]
+  fn quartic_with_four_roots() {
+    assert_roots(&from_roots(&[2.0, -3.0, 1.0, -1.0]), -10.0, 10.0, &[-3.0, -1.0, 1.0, 2.0]);
+    // Roots close together, and a leading coefficient other than one.
+    let coefficients: Vec<f64> = from_roots(&[0.5, 0.51, 4.0, 7.0]).iter().map(|c| -3.0 * c).collect();
+    assert_roots(&coefficients, -10.0, 10.0, &[0.5, 0.51, 4.0, 7.0]);
+  }
+
+  #[test]
+  fn quartic_roots_outside_range_are_left_out() {
+    assert_roots(&from_roots(&[2.0, -3.0, 1.0, -1.0]), 0.0, 1.5, &[1.0]);
+  }
+
+  #[test]
+  fn quartics_with_complex_roots() {
+    // (x^2 + 1)(x - 1)(x + 2)
+    assert_roots(&[1.0, 1.0, -1.0, 1.0, -2.0], -10.0, 10.0, &[-2.0, 1.0]);
+    // (x^2 + 1)(x^2 + 4)
+    assert_roots(&[1.0, 0.0, 5.0, 0.0, 4.0], -10.0, 10.0, &[]);
+  }
+
+  #[test]
+  fn ray_through_torus_hits_both_sides() {
+    let torus = Torus::new(Point3::zero(), 2.0, 0.5, Lambertian::new(SolidColor::new(Color::new(0.5, 0.5, 0.5))));
+    let r = Ray::new(Point3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+    let mut rng = seeded_rng(0);
+    let near = torus.hit(&r, 0.001, f64::INFINITY, &mut rng).unwrap();
+    assert!((near.t - 2.5).abs() < 1e-9);
+    let far = torus.hit(&r, near.t + 0.001, f64::INFINITY, &mut rng).unwrap();
+    assert!((far.t - 3.5).abs() < 1e-9);
+  }
+}
\ No newline at end of file
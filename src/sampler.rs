@@ -0,0 +1,390 @@
+use std::{f64::consts::PI, sync::OnceLock};
+
+use crate::{util::{Rng, mix, sample_rng, random_double}, vec3::Vec3};
+
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+// A source of sample values for one pixel sample at a time. Every call to
+// get_1d/get_2d consumes the next dimension, so callers must draw dimensions
+// in the same order for every sample: the camera takes the pixel offset, lens
+// and time, then each bounce takes the same fixed set of dimensions.
+pub trait Sampler {
+  fn start_pixel_sample(&mut self, x: i32, y: i32, index: u32);
+  fn get_1d(&mut self) -> f64;
+  fn get_2d(&mut self) -> (f64, f64);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SamplerKind {
+  Independent,
+  Stratified,
+  Halton,
+  Sobol
+}
+
+impl SamplerKind {
+  pub fn create(&self, samples_per_pixel: i32, seed: u64) -> Box<dyn Sampler> {
+    match self {
+      SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+      SamplerKind::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+      SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+      SamplerKind::Sobol => Box::new(SobolSampler::new(seed))
+    }
+  }
+}
+
+fn hash(seed: u64, x: i32, y: i32, dimension: u32) -> u64 {
+  mix(seed ^ mix((x as u32 as u64) << 32 | y as u32 as u64) ^ mix(dimension as u64 + 1))
+}
+
+pub struct IndependentSampler {
+  seed: u64,
+  rng: Rng
+}
+
+impl IndependentSampler {
+  pub fn new(seed: u64) -> Self { Self { seed, rng: sample_rng(seed, 0, 0) } }
+}
+
+impl Sampler for IndependentSampler {
+  fn start_pixel_sample(&mut self, x: i32, y: i32, index: u32) {
+    self.rng = sample_rng(mix(self.seed), hash(0, x, y, 0), index as u64);
+  }
+
+  fn get_1d(&mut self) -> f64 { random_double(&mut self.rng) }
+  fn get_2d(&mut self) -> (f64, f64) { (random_double(&mut self.rng), random_double(&mut self.rng)) }
+}
+
+// Jittered strata, with each dimension's strata assigned to sample indices by
+// an independent random permutation so dimensions don't correlate.
+pub struct StratifiedSampler {
+  x_samples: u32,
+  y_samples: u32,
+  seed: u64,
+  pixel: (i32, i32),
+  index: u32,
+  dimension: u32,
+  rng: Rng
+}
+
+impl StratifiedSampler {
+  pub fn new(samples_per_pixel: i32, seed: u64) -> Self {
+    let spp = samples_per_pixel.max(1) as u32;
+    let x_samples = (1..=spp).take_while(|x| x * x <= spp).filter(|x| spp.is_multiple_of(*x)).last().unwrap_or(1);
+    Self { x_samples, y_samples: spp / x_samples, seed, pixel: (0, 0), index: 0, dimension: 0, rng: sample_rng(seed, 0, 0) }
+  }
+
+  fn stratum(&mut self) -> u32 {
+    let count = self.x_samples * self.y_samples;
+    let h = hash(self.seed, self.pixel.0, self.pixel.1, self.dimension);
+    self.dimension += 1;
+    permutation_element(self.index % count, count, h as u32)
+  }
+}
+
+impl Sampler for StratifiedSampler {
+  fn start_pixel_sample(&mut self, x: i32, y: i32, index: u32) {
+    self.pixel = (x, y);
+    self.index = index;
+    self.dimension = 0;
+    self.rng = sample_rng(mix(self.seed ^ 1), hash(0, x, y, 0), index as u64);
+  }
+
+  fn get_1d(&mut self) -> f64 {
+    let stratum = self.stratum();
+    let count = (self.x_samples * self.y_samples) as f64;
+    ((stratum as f64 + random_double(&mut self.rng)) / count).min(ONE_MINUS_EPSILON)
+  }
+
+  fn get_2d(&mut self) -> (f64, f64) {
+    let stratum = self.stratum();
+    let (x, y) = (stratum % self.x_samples, stratum / self.x_samples);
+    (
+      ((x as f64 + random_double(&mut self.rng)) / self.x_samples as f64).min(ONE_MINUS_EPSILON),
+      ((y as f64 + random_double(&mut self.rng)) / self.y_samples as f64).min(ONE_MINUS_EPSILON)
+    )
+  }
+}
+
+// Halton sequence with one prime base per dimension, Owen-scrambled per pixel.
+pub struct HaltonSampler {
+  seed: u64,
+  pixel: (i32, i32),
+  index: u32,
+  dimension: u32
+}
+
+impl HaltonSampler {
+  pub fn new(seed: u64) -> Self { Self { seed, pixel: (0, 0), index: 0, dimension: 0 } }
+
+  fn next(&mut self) -> f64 {
+    let primes = primes();
+    let base = primes[self.dimension as usize % primes.len()];
+    let h = hash(self.seed, self.pixel.0, self.pixel.1, self.dimension);
+    self.dimension += 1;
+    owen_scrambled_radical_inverse(base, self.index as u64, h)
+  }
+}
+
+impl Sampler for HaltonSampler {
+  fn start_pixel_sample(&mut self, x: i32, y: i32, index: u32) {
+    self.pixel = (x, y);
+    self.index = index;
+    self.dimension = 0;
+  }
+
+  fn get_1d(&mut self) -> f64 { self.next() }
+  fn get_2d(&mut self) -> (f64, f64) { (self.next(), self.next()) }
+}
+
+const PRIME_COUNT: usize = 1000;
+
+fn primes() -> &'static [u64] {
+  static PRIMES: OnceLock<Vec<u64>> = OnceLock::new();
+  PRIMES.get_or_init(|| {
+    let mut primes = Vec::with_capacity(PRIME_COUNT);
+    let mut n = 2;
+    while primes.len() < PRIME_COUNT {
+      if primes.iter().take_while(|&&p| p * p <= n).all(|&p| n % p != 0) { primes.push(n) };
+      n += 1;
+    }
+    primes
+  })
+}
+
+fn owen_scrambled_radical_inverse(base: u64, mut a: u64, hash: u64) -> f64 {
+  let inv_base = 1.0 / base as f64;
+  let mut inv_base_m = 1.0;
+  let mut reversed_digits: u64 = 0;
+
+  // Stop once further digits can no longer change the result.
+  while 1.0 - (base - 1) as f64 * inv_base_m < 1.0 {
+    let next = a / base;
+    let digit = a - next * base;
+    // Each digit is permuted based on the digits above it.
+    let digit_hash = mix(hash ^ reversed_digits) as u32;
+    let digit = permutation_element(digit as u32, base as u32, digit_hash) as u64;
+    // Large bases run out of bits first.
+    let Some(reversed) = reversed_digits.checked_mul(base).and_then(|r| r.checked_add(digit)) else { break };
+    reversed_digits = reversed;
+    inv_base_m *= inv_base;
+    a = next;
+  }
+
+  (inv_base_m * reversed_digits as f64).min(ONE_MINUS_EPSILON)
+}
+
+// Sobol (0,2)-sequence, padded to any number of dimensions by giving every
+// dimension its own shuffle of the sample indices, and Owen-scrambled with the
+// hash-based nested uniform scramble from Burley's "Practical Hash-based Owen
+// Scrambling".
+pub struct SobolSampler {
+  seed: u64,
+  pixel: (i32, i32),
+  index: u32,
+  dimension: u32
+}
+
+impl SobolSampler {
+  pub fn new(seed: u64) -> Self { Self { seed, pixel: (0, 0), index: 0, dimension: 0 } }
+
+  fn next_seeds(&mut self) -> (u32, u32, u32) {
+    let h = hash(self.seed, self.pixel.0, self.pixel.1, self.dimension);
+    self.dimension += 1;
+    (h as u32, (h >> 32) as u32, mix(h) as u32)
+  }
+}
+
+impl Sampler for SobolSampler {
+  fn start_pixel_sample(&mut self, x: i32, y: i32, index: u32) {
+    self.pixel = (x, y);
+    self.index = index;
+    self.dimension = 0;
+  }
+
+  fn get_1d(&mut self) -> f64 {
+    let (shuffle, scramble, _) = self.next_seeds();
+    let index = nested_uniform_scramble(self.index, shuffle);
+    to_unit(nested_uniform_scramble(index.reverse_bits(), scramble))
+  }
+
+  fn get_2d(&mut self) -> (f64, f64) {
+    let (shuffle, scramble_x, scramble_y) = self.next_seeds();
+    let index = nested_uniform_scramble(self.index, shuffle);
+    (
+      to_unit(nested_uniform_scramble(index.reverse_bits(), scramble_x)),
+      to_unit(nested_uniform_scramble(sobol_second_dimension(index), scramble_y))
+    )
+  }
+}
+
+fn sobol_second_dimension(mut index: u32) -> u32 {
+  let mut v: u32 = 1 << 31;
+  let mut result = 0;
+  while index != 0 {
+    if index & 1 != 0 { result ^= v };
+    index >>= 1;
+    v ^= v >> 1;
+  }
+  result
+}
+
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+  x = x.wrapping_add(seed);
+  x ^= x.wrapping_mul(0x6c50b47c);
+  x ^= x.wrapping_mul(0xb82f1e52);
+  x ^= x.wrapping_mul(0xc7afe638);
+  x ^= x.wrapping_mul(0x8d22f6e6);
+  x
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+  laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+fn to_unit(x: u32) -> f64 {
+  (x as f64 / 4294967296.0).min(ONE_MINUS_EPSILON)
+}
+
+fn permutation_element(mut i: u32, l: u32, p: u32) -> u32 {
+  // Kensler's hashed permutation: element i of a random permutation of [0,l).
+  let mut w = l.wrapping_sub(1);
+  w |= w >> 1;
+  w |= w >> 2;
+  w |= w >> 4;
+  w |= w >> 8;
+  w |= w >> 16;
+  loop {
+    i ^= p;
+    i = i.wrapping_mul(0xe170893d);
+    i ^= p >> 16;
+    i ^= (i & w) >> 4;
+    i ^= p >> 8;
+    i = i.wrapping_mul(0x0929eb3f);
+    i ^= p >> 23;
+    i ^= (i & w) >> 1;
+    i = i.wrapping_mul(1 | p >> 27);
+    i = i.wrapping_mul(0x6935fa69);
+    i ^= (i & w) >> 11;
+    i = i.wrapping_mul(0x74dcb303);
+    i ^= (i & w) >> 2;
+    i = i.wrapping_mul(0x9e501cc3);
+    i ^= (i & w) >> 2;
+    i = i.wrapping_mul(0xc860a3df);
+    i &= w;
+    i ^= i >> 5;
+    if i < l { break }
+  }
+  (i.wrapping_add(p)) % l
+}
+
+// Warps from the unit square to common sampling domains.
+
+pub fn sample_unit_disk(u: (f64, f64)) -> Vec3 {
+  // Shirley-Chiu concentric mapping, which keeps strata compact.
+  let (a, b) = (2.0 * u.0 - 1.0, 2.0 * u.1 - 1.0);
+  if a == 0.0 && b == 0.0 { return Vec3::zero() }
+  let (r, theta) = if a.abs() > b.abs() {
+    (a, (PI / 4.0) * (b / a))
+  } else {
+    (b, PI / 2.0 - (PI / 4.0) * (a / b))
+  };
+  Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+}
+
+pub fn sample_unit_sphere(u: (f64, f64)) -> Vec3 {
+  // Uniformly distributed direction.
+  let z = 1.0 - 2.0 * u.0;
+  let r = (1.0 - z * z).max(0.0).sqrt();
+  let phi = 2.0 * PI * u.1;
+  Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+pub fn sample_unit_ball(u: (f64, f64), uc: f64) -> Vec3 {
+  // Uniformly distributed point inside the unit sphere.
+  uc.cbrt() * sample_unit_sphere(u)
+}
+
+pub fn sample_cone(u: (f64, f64), cos_theta_max: f64) -> Vec3 {
+  // Uniformly distributed direction within cos_theta_max of +Z.
+  let z = 1.0 + u.1*(cos_theta_max - 1.0);
+  let r = (1.0 - z*z).max(0.0).sqrt();
+  let phi = 2.0 * PI * u.0;
+  Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+pub fn sample_triangle(u: (f64, f64)) -> [f64; 3] {
+  // Uniformly distributed barycentric coordinates.
+  let su0 = u.0.sqrt();
+  let (b0, b1) = (1.0 - su0, u.1 * su0);
+  [b0, b1, 1.0 - b0 - b1]
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // The 2D points the first get_2d call gives for the first count samples of
+  // a pixel.
+  fn points(sampler: &mut dyn Sampler, count: u32) -> Vec<(f64, f64)> {
+    (0..count).map(|i| {
+      sampler.start_pixel_sample(3, 5, i);
+      sampler.get_2d()
+    }).collect()
+  }
+
+  // Whether every cell of a columns by rows grid holds exactly one point.
+  fn one_per_cell(points: &[(f64, f64)], columns: usize, rows: usize) -> bool {
+    let mut counts = vec![0; columns * rows];
+    for &(x, y) in points {
+      counts[(y * rows as f64) as usize * columns + (x * columns as f64) as usize] += 1;
+    }
+    counts.iter().all(|&c| c == 1)
+  }
+
+  #[test]
+  fn samples_stay_in_unit_interval() {
+    for kind in [SamplerKind::Independent, SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol] {
+      let mut sampler = kind.create(16, 1);
+      for i in 0..256 {
+        sampler.start_pixel_sample(i as i32 % 7, -2, i);
+        for _ in 0..40 {
+          let (x, y) = sampler.get_2d();
+          let z = sampler.get_1d();
+          assert!([x, y, z].iter().all(|v| (0.0..1.0).contains(v)), "{kind:?}");
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn sobol_fills_elementary_intervals() {
+    let mut sampler = SobolSampler::new(2);
+    for k in 0..=8 {
+      let points = points(&mut sampler, 1 << k);
+      for a in 0..=k {
+        assert!(one_per_cell(&points, 1 << a, 1 << (k - a)), "{k} {a}");
+      }
+    }
+  }
+
+  #[test]
+  fn halton_fills_strata() {
+    let mut sampler = HaltonSampler::new(2);
+    // Bases 2 and 3 for the first two dimensions.
+    assert!(one_per_cell(&points(&mut sampler, 36), 4, 9));
+    let xs: Vec<(f64, f64)> = points(&mut sampler, 16).iter().map(|&(x, _)| (x, 0.0)).collect();
+    assert!(one_per_cell(&xs, 16, 1));
+  }
+
+  #[test]
+  fn stratified_fills_strata() {
+    let mut sampler = StratifiedSampler::new(12, 2);
+    let points = points(&mut sampler, 12);
+    assert!(one_per_cell(&points, 3, 4));
+    let values: Vec<(f64, f64)> = (0..12).map(|i| {
+      sampler.start_pixel_sample(3, 5, i);
+      (sampler.get_1d(), 0.0)
+    }).collect();
+    assert!(one_per_cell(&values, 12, 1));
+  }
+}
\ No newline at end of file
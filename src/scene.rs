@@ -0,0 +1,1096 @@
+use std::{collections::HashMap, fmt, fs, io, path::{Path, PathBuf}, sync::Arc};
+
+use serde::{de, Deserialize, Deserializer};
+use serde_json::Value;
+
+use crate::{
+  vec3::{Point3, Vec3, Color, cross, unit_vector},
+  aabb::AABB,
+  camera::Camera,
+  bvh::BVH,
+  hittable_list::HittableList,
+  hittable::{Hittable, Transform},
+  mat4::Mat4,
+  sphere::Sphere,
+  moving_sphere::MovingSphere,
+  aarect::{xy_rect, xz_rect, yz_rect},
+  planar::{Quad, Disk, Polygon},
+  revolution::{Cone, Paraboloid, Torus},
+  csg::{Csg, CsgOperation},
+  sdf::{self, Sdf, SdfHittable},
+  cube::Cube,
+  triangle::{Triangle, MeshData, TriangleMesh},
+  obj::load_obj,
+  ply::load_ply,
+  stl::load_stl,
+  gltf_import::{load_gltf, GltfCamera, GltfError},
+  constant_medium::ConstantMedium,
+  material::{Material, Lambertian, Metal, Conductor, conductor_preset, Ior, Dialectric, RoughDielectric, DiffuseLight, Isotropic},
+  texture::{Texture, SolidColor, CheckerTexture, NoiseTexture, ImageTexture, VertexColorTexture},
+  environment::{Environment, ConstantEnvironment, GradientEnvironment, ImageEnvironment},
+  sky::SkyEnvironment,
+  punctual::{PunctualLight, PointLight, SpotLight, DirectionalLight},
+  principled::Principled,
+  light::Lights,
+  spectrum::{LAMBDA_MIN, LAMBDA_MAX},
+  util::Rng
+};
+
+pub struct Scene {
+  pub image: ImageSettings,
+  pub camera: CameraSettings,
+  pub background: Arc<dyn Environment>,
+  pub world: Box<dyn Hittable>,
+  pub lights: Lights
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImageSettings {
+  pub aspect_ratio: f64,
+  pub width: i32,
+  pub samples_per_pixel: i32,
+  pub max_depth: i32,
+  // Bounces before paths may be terminated by Russian roulette.
+  pub rr_depth: i32,
+  // Trace sampled wavelengths instead of RGB.
+  pub spectral: bool
+}
+
+impl Default for ImageSettings {
+  fn default() -> Self {
+    Self { aspect_ratio: 16.0 / 9.0, width: 400, samples_per_pixel: 100, max_depth: 50, rr_depth: 3, spectral: false }
+  }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraSettings {
+  pub lookfrom: Point3,
+  pub lookat: Point3,
+  pub vup: Vec3,
+  pub vfov: f64,
+  pub aperture: f64,
+  pub focus_dist: f64,
+  pub time0: f64,
+  pub time1: f64
+}
+
+impl Default for CameraSettings {
+  fn default() -> Self {
+    Self {
+      lookfrom: Point3::new(13.0, 2.0, 3.0),
+      lookat: Point3::zero(),
+      vup: Vec3::new(0.0, 1.0, 0.0),
+      vfov: 20.0,
+      aperture: 0.0,
+      focus_dist: 10.0,
+      time0: 0.0,
+      time1: 1.0
+    }
+  }
+}
+
+impl CameraSettings {
+  pub fn camera(&self, aspect_ratio: f64) -> Camera {
+    Camera::new(
+      self.lookfrom, self.lookat, self.vup, self.vfov, aspect_ratio,
+      self.aperture, self.focus_dist, self.time0, self.time1
+    )
+  }
+
+  // Placed and framed like a camera from a glTF file, keeping the lens and
+  // shutter settings.
+  fn with_gltf(self, camera: &GltfCamera) -> Self {
+    CameraSettings { lookfrom: camera.lookfrom, lookat: camera.lookat, vup: camera.vup, vfov: camera.vfov, ..self }
+  }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+  Io(PathBuf, io::Error),
+  Parse(PathBuf, serde_json::Error),
+  Image(PathBuf, image::ImageError),
+  Gltf(GltfError),
+  UnknownTexture(String, String),
+  UnknownMaterial(String, String),
+  CyclicReference(String, String),
+  Invalid(String, String)
+}
+
+impl fmt::Display for SceneError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      SceneError::Io(path, e) => write!(f, "could not read '{}': {e}", path.display()),
+      SceneError::Parse(path, e) => write!(f, "{}: {e}", path.display()),
+      SceneError::Image(path, e) => write!(f, "could not load image '{}': {e}", path.display()),
+      SceneError::Gltf(e) => write!(f, "{e}"),
+      SceneError::UnknownTexture(at, name) => write!(f, "{at}: unknown texture '{name}'"),
+      SceneError::UnknownMaterial(at, name) => write!(f, "{at}: unknown material '{name}'"),
+      SceneError::CyclicReference(at, name) => write!(f, "{at}: '{name}' refers to itself"),
+      SceneError::Invalid(at, msg) => write!(f, "{at}: {msg}")
+    }
+  }
+}
+
+impl std::error::Error for SceneError {}
+
+type Result<T> = std::result::Result<T, SceneError>;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+  #[serde(default)]
+  image: ImageSettings,
+  #[serde(default)]
+  camera: CameraSettings,
+  #[serde(default)]
+  background: BackgroundDesc,
+  #[serde(default)]
+  textures: HashMap<String, TextureDesc>,
+  #[serde(default)]
+  materials: HashMap<String, MaterialDesc>,
+  objects: Vec<ObjectDesc>
+}
+
+enum BackgroundDesc {
+  Color(Color),
+  Environment(EnvironmentDesc)
+}
+
+impl Default for BackgroundDesc {
+  fn default() -> Self { BackgroundDesc::Color(Color::zero()) }
+}
+
+impl<'de> Deserialize<'de> for BackgroundDesc {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+    match Value::deserialize(deserializer)? {
+      value @ Value::Array(_) => Color::deserialize(value).map(BackgroundDesc::Color).map_err(de::Error::custom),
+      value => EnvironmentDesc::deserialize(value).map(BackgroundDesc::Environment).map_err(de::Error::custom)
+    }
+  }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum EnvironmentDesc {
+  Constant { color: Color },
+  Gradient { bottom: Color, top: Color },
+  Image {
+    file: PathBuf,
+    // Degrees about the Y axis.
+    #[serde(default)]
+    rotation: f64,
+    #[serde(default = "one")]
+    intensity: f64
+  },
+  Sky {
+    sun_direction: Vec3,
+    #[serde(default = "default_turbidity")]
+    turbidity: f64,
+    #[serde(default = "default_ground_albedo")]
+    ground_albedo: Color,
+    #[serde(default = "one")]
+    intensity: f64
+  }
+}
+
+fn one() -> f64 { 1.0 }
+fn default_capped() -> bool { true }
+fn default_max_steps() -> u32 { 256 }
+fn default_epsilon() -> f64 { 1e-4 }
+fn default_power() -> f64 { 8.0 }
+fn default_iterations() -> u32 { 12 }
+fn default_turbidity() -> f64 { 3.0 }
+fn default_ground_albedo() -> Color { Color::new(0.3, 0.3, 0.3) }
+
+enum TextureRef {
+  Color(Color),
+  Named(String),
+  Inline(Box<TextureDesc>)
+}
+
+impl<'de> Deserialize<'de> for TextureRef {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+    match Value::deserialize(deserializer)? {
+      Value::String(name) => Ok(TextureRef::Named(name)),
+      Value::Number(n) => n.as_f64().map(|v| TextureRef::Color(Color::new(v, v, v))).ok_or_else(|| de::Error::custom("invalid number")),
+      value @ Value::Array(_) => Color::deserialize(value).map(TextureRef::Color).map_err(de::Error::custom),
+      value => TextureDesc::deserialize(value).map(|t| TextureRef::Inline(Box::new(t))).map_err(de::Error::custom)
+    }
+  }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDesc {
+  Solid { color: Color },
+  Checker { even: TextureRef, odd: TextureRef },
+  Noise { scale: f64 },
+  Image { file: PathBuf },
+  // Colors given at the vertices of meshes, white elsewhere.
+  VertexColor
+}
+
+// A constant index of refraction, a named glass or a dispersion model.
+enum IorRef {
+  Constant(f64),
+  Named(String),
+  Model(IorDesc)
+}
+
+impl<'de> Deserialize<'de> for IorRef {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+    match Value::deserialize(deserializer)? {
+      Value::String(name) => Ok(IorRef::Named(name)),
+      Value::Number(n) => n.as_f64().map(IorRef::Constant).ok_or_else(|| de::Error::custom("invalid number")),
+      value => IorDesc::deserialize(value).map(IorRef::Model).map_err(de::Error::custom)
+    }
+  }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum IorDesc {
+  Cauchy { a: f64, b: f64 },
+  Sellmeier { b: [f64; 3], c: [f64; 3] },
+  // Index at the d line and Abbe number.
+  Abbe { n: f64, v: f64 }
+}
+
+enum MaterialRef {
+  Named(String),
+  Inline(Box<MaterialDesc>)
+}
+
+impl<'de> Deserialize<'de> for MaterialRef {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+    match Value::deserialize(deserializer)? {
+      Value::String(name) => Ok(MaterialRef::Named(name)),
+      value => MaterialDesc::deserialize(value).map(|m| MaterialRef::Inline(Box::new(m))).map_err(de::Error::custom)
+    }
+  }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+  Lambertian { albedo: TextureRef },
+  Metal { albedo: TextureRef, #[serde(default)] fuzz: f64 },
+  // Glass is smooth and clear unless given a roughness, tint or thin walls.
+  Dielectric {
+    ir: IorRef,
+    roughness: Option<TextureRef>,
+    tint: Option<Color>,
+    #[serde(default = "one")]
+    tint_distance: f64,
+    #[serde(default)]
+    thin: bool
+  },
+  // Either a named metal or its complex index of refraction. roughness_u and
+  // roughness_v override roughness for anisotropic surfaces, along the
+  // direction texture u runs in and across it.
+  Conductor {
+    metal: Option<String>,
+    eta: Option<Color>, k: Option<Color>,
+    roughness: Option<TextureRef>,
+    roughness_u: Option<TextureRef>, roughness_v: Option<TextureRef>
+  },
+  // Disney-style uber material; scalar parameters default to a rough
+  // plastic and range from 0 to 1.
+  Principled {
+    base_color: Option<TextureRef>,
+    metallic: Option<TextureRef>,
+    roughness: Option<TextureRef>,
+    specular: Option<TextureRef>,
+    transmission: Option<TextureRef>,
+    clearcoat: Option<TextureRef>,
+    clearcoat_roughness: Option<TextureRef>,
+    sheen: Option<TextureRef>,
+    emission: Option<TextureRef>
+  },
+  DiffuseLight { emit: TextureRef },
+  Isotropic { albedo: TextureRef }
+}
+
+// Angles are in degrees, counterclockwise looking down the axis.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TransformDesc {
+  Translate { offset: Vec3 },
+  Scale { factor: ScaleRef },
+  Rotate { axis: Vec3, angle: f64 },
+  // About X, then Y, then Z.
+  Euler { angles: Vec3 },
+  // x, y, z, w.
+  Quaternion { rotation: [f64; 4] },
+  // Affine matrix by rows, acting on column vectors.
+  Matrix { rows: [[f64; 4]; 4] }
+}
+
+// Distance field shapes, centered on the origin until translated.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum SdfDesc {
+  Sphere { radius: f64 },
+  // Box of the given size with edges rounded off by radius.
+  RoundBox { size: Vec3, #[serde(default)] radius: f64 },
+  // Ring lying in the XZ plane.
+  Torus { major_radius: f64, minor_radius: f64 },
+  Mandelbulb {
+    #[serde(default = "default_power")]
+    power: f64,
+    #[serde(default = "default_iterations")]
+    iterations: u32
+  },
+  Union { shapes: Vec<SdfDesc> },
+  // Union blended together over about smoothness where the shapes meet.
+  SmoothUnion { shapes: Vec<SdfDesc>, smoothness: f64 },
+  Intersection { shapes: Vec<SdfDesc> },
+  Subtract { shape: Box<SdfDesc>, subtract: Vec<SdfDesc> },
+  Translate { offset: Vec3, shape: Box<SdfDesc> },
+  // Copies spacing apart along each axis with a non-zero spacing, limit
+  // copies to either side of the original, or without end if there is no
+  // limit.
+  Repeat { spacing: Vec3, limit: Option<[u32; 3]>, shape: Box<SdfDesc> },
+  // Twisted about the Y axis by rate degrees for each unit up it.
+  Twist { rate: f64, shape: Box<SdfDesc> }
+}
+
+// The same factor on every axis, or one per axis.
+enum ScaleRef {
+  Uniform(f64),
+  Axes(Vec3)
+}
+
+impl<'de> Deserialize<'de> for ScaleRef {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+    match Value::deserialize(deserializer)? {
+      Value::Number(n) => n.as_f64().map(ScaleRef::Uniform).ok_or_else(|| de::Error::custom("invalid number")),
+      value => Vec3::deserialize(value).map(ScaleRef::Axes).map_err(de::Error::custom)
+    }
+  }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDesc {
+  Sphere { center: Point3, radius: f64, material: MaterialRef },
+  MovingSphere {
+    center0: Point3, center1: Point3,
+    time0: f64, time1: f64,
+    radius: f64,
+    material: MaterialRef
+  },
+  XyRect { x0: f64, x1: f64, y0: f64, y1: f64, k: f64, material: MaterialRef },
+  XzRect { x0: f64, x1: f64, z0: f64, z1: f64, k: f64, material: MaterialRef },
+  YzRect { y0: f64, y1: f64, z0: f64, z1: f64, k: f64, material: MaterialRef },
+  // Parallelogram with a corner at origin and sides along u and v, facing
+  // along u cross v.
+  Quad { origin: Point3, u: Vec3, v: Vec3, material: MaterialRef },
+  Disk { center: Point3, normal: Vec3, radius: f64, material: MaterialRef },
+  // Flat and convex, facing the side the vertices run counterclockwise
+  // around.
+  Polygon { vertices: Vec<Point3>, material: MaterialRef },
+  Cube { p0: Point3, p1: Point3, material: MaterialRef },
+  // Upright along +Y, standing on base. Caps close off the flat ends.
+  Cylinder {
+    base: Point3, radius: f64, height: f64,
+    #[serde(default = "default_capped")]
+    capped: bool,
+    material: MaterialRef
+  },
+  // A pointed cone, or a frustum with a top_radius.
+  Cone {
+    base: Point3, radius: f64, #[serde(default)] top_radius: f64, height: f64,
+    #[serde(default = "default_capped")]
+    capped: bool,
+    material: MaterialRef
+  },
+  // Bowl with its lowest point on base, radius wide at height.
+  Paraboloid {
+    base: Point3, radius: f64, height: f64,
+    #[serde(default = "default_capped")]
+    capped: bool,
+    material: MaterialRef
+  },
+  // Ring lying in the XZ plane.
+  Torus { center: Point3, major_radius: f64, minor_radius: f64, material: MaterialRef },
+  // Surface of a distance field, found by stepping rays towards it. Shapes
+  // without bounds of their own, like endless repeats, need bounds as the
+  // corners of a box to search. Rays give up on it after max_steps, and
+  // stop within epsilon of it.
+  Sdf {
+    shape: SdfDesc,
+    bounds: Option<[Point3; 2]>,
+    #[serde(default = "default_max_steps")]
+    max_steps: u32,
+    #[serde(default = "default_epsilon")]
+    epsilon: f64,
+    material: MaterialRef
+  },
+  Triangle { vertices: [Point3; 3], material: MaterialRef },
+  // Indexed triangles. normals, uvs and colors are optional and given per
+  // vertex; either one material, or materials with face_materials picking
+  // one for each face.
+  Mesh {
+    positions: Vec<Point3>,
+    #[serde(default)]
+    normals: Vec<Vec3>,
+    #[serde(default)]
+    uvs: Vec<(f64, f64)>,
+    #[serde(default)]
+    colors: Vec<Color>,
+    indices: Vec<[usize; 3]>,
+    material: Option<MaterialRef>,
+    #[serde(default)]
+    materials: Vec<MaterialRef>,
+    #[serde(default)]
+    face_materials: Vec<usize>
+  },
+  // Wavefront OBJ model with its MTL materials, or material for every face.
+  // groups keeps only the faces in the named groups or objects. Faces
+  // without an MTL material are colored by the vertex colors if it has any.
+  Obj { file: PathBuf, material: Option<MaterialRef>, groups: Option<Vec<String>> },
+  // PLY model, ASCII or binary. Without a material it is diffuse, colored
+  // by its vertex colors if it has any; materials can use them through a
+  // vertex_color texture.
+  Ply { file: PathBuf, material: Option<MaterialRef> },
+  // STL model, ASCII or binary, plain grey without a material.
+  Stl { file: PathBuf, material: Option<MaterialRef> },
+  // Meshes and lights of a glTF 2.0 scene. camera takes the placement and
+  // field of view, and the aspect ratio if it has one, from the file's first
+  // perspective camera.
+  Gltf { file: PathBuf, #[serde(default)] camera: bool },
+  ConstantMedium { boundary: Box<ObjectDesc>, density: f64, albedo: TextureRef },
+  // Constructive solid geometry over closed objects. Cut surfaces take the
+  // material of the objects they were cut with.
+  Union { objects: Vec<ObjectDesc> },
+  Intersection { objects: Vec<ObjectDesc> },
+  Difference { object: Box<ObjectDesc>, subtract: Vec<ObjectDesc> },
+  Translate { offset: Vec3, object: Box<ObjectDesc> },
+  RotateY { angle: f64, object: Box<ObjectDesc> },
+  // Steps applied to object in order, the first one first.
+  Transform { transforms: Vec<TransformDesc>, object: Box<ObjectDesc> },
+  // Punctual lights. Point and spot lights with a position1 move there from
+  // position over the camera's shutter interval.
+  PointLight { position: Point3, position1: Option<Point3>, intensity: Color },
+  SpotLight {
+    position: Point3, position1: Option<Point3>,
+    direction: Vec3,
+    angle: f64, falloff_start: Option<f64>,
+    intensity: Color
+  },
+  DirectionalLight { direction: Vec3, irradiance: Color }
+}
+
+impl Scene {
+  pub fn new(image: ImageSettings, camera: CameraSettings, background: Arc<dyn Environment>, objects: Vec<Box<dyn Hittable>>, punctual: Vec<Box<dyn PunctualLight>>, rng: &mut Rng) -> Scene {
+    let (objects, lights) = Lights::collect(objects, &background, punctual);
+    // Scenes of nothing but punctual lights still render, as background.
+    let world: Box<dyn Hittable> = if objects.is_empty() { Box::new(HittableList::new()) } else { Box::new(BVH::new(objects, camera.time0, camera.time1, rng)) };
+    Scene { image, camera, background, world, lights }
+  }
+
+  pub fn load<P: AsRef<Path>>(path: P, rng: &mut Rng) -> Result<Scene> {
+    let path = path.as_ref();
+    if matches!(path.extension().and_then(|e| e.to_str()), Some("gltf" | "glb")) { return Scene::load_gltf(path, rng) }
+
+    let source = fs::read_to_string(path).map_err(|e| SceneError::Io(path.to_path_buf(), e))?;
+    Scene::from_json(&source, path, rng)
+  }
+
+  // A scene description read from path, whose directory files in it are
+  // relative to.
+  fn from_json(source: &str, path: &Path, rng: &mut Rng) -> Result<Scene> {
+    let desc: SceneDesc = serde_json::from_str(source).map_err(|e| SceneError::Parse(path.to_path_buf(), e))?;
+    let base_dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    Loader::new(base_dir, desc.textures, desc.materials, rng).build(desc.image, desc.camera, desc.background, desc.objects)
+  }
+
+  // A glTF file on its own, seen through its own camera or else from the
+  // front, under a sky gradient.
+  fn load_gltf(path: &Path, rng: &mut Rng) -> Result<Scene> {
+    let mut image = ImageSettings::default();
+    let mut camera = CameraSettings { vfov: 40.0, ..CameraSettings::default() };
+    let gltf = load_gltf(path, camera.time0, camera.time1, rng).map_err(SceneError::Gltf)?;
+
+    match &gltf.camera {
+      Some(found) => {
+        camera = camera.with_gltf(found);
+        image.aspect_ratio = found.aspect_ratio.unwrap_or(image.aspect_ratio);
+      },
+      None => {
+        let bbox = gltf.objects.iter().filter_map(|object| object.bounding_box(camera.time0, camera.time1))
+          .reduce(|a, b| AABB::surrounding_box(&a, &b))
+          .ok_or_else(|| SceneError::Invalid(path.display().to_string(), "has neither a camera nor meshes to place one around".to_string()))?;
+        let center = 0.5 * (bbox.min() + bbox.max());
+        let radius = (0.5 * (bbox.max() - bbox.min()).length()).max(1e-3);
+        camera.lookat = center;
+        camera.lookfrom = center + unit_vector(Vec3::new(0.0, 0.3, 1.0)) * (radius / (0.5 * camera.vfov).to_radians().sin());
+      }
+    }
+
+    let background = Arc::new(GradientEnvironment::new(Color::new(1.0, 1.0, 1.0), Color::new(0.5, 0.7, 1.0)));
+    Ok(Scene::new(image, camera, background, gltf.objects, gltf.punctual, rng))
+  }
+}
+
+struct Loader<'a> {
+  base_dir: PathBuf,
+  texture_descs: HashMap<String, TextureDesc>,
+  material_descs: HashMap<String, MaterialDesc>,
+  textures: HashMap<String, Arc<dyn Texture>>,
+  materials: HashMap<String, Arc<dyn Material>>,
+  resolving: Vec<String>,
+  rng: &'a mut Rng
+}
+
+impl<'a> Loader<'a> {
+  fn new(base_dir: PathBuf, texture_descs: HashMap<String, TextureDesc>, material_descs: HashMap<String, MaterialDesc>, rng: &'a mut Rng) -> Self {
+    Self { base_dir, texture_descs, material_descs, textures: HashMap::new(), materials: HashMap::new(), resolving: Vec::new(), rng }
+  }
+
+  fn build(mut self, mut image: ImageSettings, mut camera: CameraSettings, background: BackgroundDesc, objects: Vec<ObjectDesc>) -> Result<Scene> {
+    check(image.aspect_ratio > 0.0, "image.aspect_ratio", "must be positive")?;
+    check(image.width > 0, "image.width", "must be positive")?;
+    check(image.samples_per_pixel > 0, "image.samples_per_pixel", "must be positive")?;
+    check(image.max_depth > 0, "image.max_depth", "must be positive")?;
+    check(image.rr_depth >= 0, "image.rr_depth", "must not be negative")?;
+    check(camera.vfov > 0.0 && camera.vfov < 180.0, "camera.vfov", "must be between 0 and 180 degrees")?;
+    check(camera.aperture >= 0.0, "camera.aperture", "must not be negative")?;
+    check(camera.focus_dist > 0.0, "camera.focus_dist", "must be positive")?;
+    check(camera.lookfrom.x() != camera.lookat.x() || camera.lookfrom.y() != camera.lookat.y() || camera.lookfrom.z() != camera.lookat.z(),
+      "camera", "lookfrom and lookat must differ")?;
+    check(!objects.is_empty(), "objects", "scene has no objects")?;
+
+    // Every named texture and material is built, in name order, whether or
+    // not anything uses it, so broken ones are still reported.
+    let mut names: Vec<String> = self.texture_descs.keys().cloned().collect();
+    names.sort();
+    for name in names { self.texture(&TextureRef::Named(name), "textures")?; }
+    let mut names: Vec<String> = self.material_descs.keys().cloned().collect();
+    names.sort();
+    for name in names { self.material(&MaterialRef::Named(name), "materials")?; }
+
+    let mut world: Vec<Box<dyn Hittable>> = Vec::new();
+    let mut punctual: Vec<Box<dyn PunctualLight>> = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+      let at = format!("objects[{i}]");
+      if let ObjectDesc::Gltf { file, camera: use_camera } = object {
+        let gltf = load_gltf(&self.base_dir.join(file), camera.time0, camera.time1, self.rng).map_err(|e| SceneError::Invalid(at.clone(), e.to_string()))?;
+        if *use_camera {
+          let found = gltf.camera.ok_or_else(|| SceneError::Invalid(format!("{at}.camera"), "file has no perspective camera".to_string()))?;
+          camera = camera.with_gltf(&found);
+          image.aspect_ratio = found.aspect_ratio.unwrap_or(image.aspect_ratio);
+        }
+        world.extend(gltf.objects);
+        punctual.extend(gltf.punctual);
+        continue
+      }
+      match self.punctual_light(object, &camera, &at)? {
+        Some(light) => punctual.push(light),
+        None => world.push(self.object(object, &at)?)
+      }
+    }
+
+    let background = self.environment(&background, "background")?;
+    Ok(Scene::new(image, camera, background, world, punctual, self.rng))
+  }
+
+  fn environment(&mut self, background: &BackgroundDesc, at: &str) -> Result<Arc<dyn Environment>> {
+    Ok(match background {
+      BackgroundDesc::Color(color) => Arc::new(ConstantEnvironment::new(*color)),
+      BackgroundDesc::Environment(EnvironmentDesc::Constant { color }) => Arc::new(ConstantEnvironment::new(*color)),
+      BackgroundDesc::Environment(EnvironmentDesc::Gradient { bottom, top }) => Arc::new(GradientEnvironment::new(*bottom, *top)),
+      BackgroundDesc::Environment(EnvironmentDesc::Image { file, rotation, intensity }) => {
+        check(*intensity >= 0.0, &format!("{at}.intensity"), "must not be negative")?;
+        let path = self.base_dir.join(file);
+        let environment = ImageEnvironment::load(&path.to_string_lossy(), *rotation, *intensity).map_err(|e| SceneError::Image(path, e))?;
+        Arc::new(environment)
+      },
+      BackgroundDesc::Environment(EnvironmentDesc::Sky { sun_direction, turbidity, ground_albedo, intensity }) => {
+        check(sun_direction.length() > 0.0, &format!("{at}.sun_direction"), "must not be zero")?;
+        check((2.0..=10.0).contains(turbidity), &format!("{at}.turbidity"), "must be between 2 and 10")?;
+        check(*intensity >= 0.0, &format!("{at}.intensity"), "must not be negative")?;
+        Arc::new(SkyEnvironment::new(*sun_direction, *turbidity, *ground_albedo, *intensity))
+      }
+    })
+  }
+
+  fn texture(&mut self, texture: &TextureRef, at: &str) -> Result<Arc<dyn Texture>> {
+    match texture {
+      TextureRef::Color(c) => Ok(Arc::new(SolidColor::new(*c))),
+      TextureRef::Inline(desc) => self.texture_desc(desc, at),
+      TextureRef::Named(name) => {
+        if let Some(texture) = self.textures.get(name) { return Ok(texture.clone()) }
+        if self.resolving.contains(name) { return Err(SceneError::CyclicReference(at.to_string(), name.clone())) }
+
+        let desc = self.texture_descs.remove(name).ok_or_else(|| SceneError::UnknownTexture(at.to_string(), name.clone()))?;
+        self.resolving.push(name.clone());
+        let texture = self.texture_desc(&desc, &format!("textures.{name}"));
+        self.resolving.pop();
+
+        let texture = texture?;
+        self.textures.insert(name.clone(), texture.clone());
+        Ok(texture)
+      }
+    }
+  }
+
+  fn texture_or(&mut self, texture: &Option<TextureRef>, default: Color, at: &str) -> Result<Arc<dyn Texture>> {
+    match texture {
+      Some(texture) => self.texture(texture, at),
+      None => Ok(Arc::new(SolidColor::new(default)))
+    }
+  }
+
+  fn texture_desc(&mut self, desc: &TextureDesc, at: &str) -> Result<Arc<dyn Texture>> {
+    Ok(match desc {
+      TextureDesc::Solid { color } => Arc::new(SolidColor::new(*color)),
+      TextureDesc::Checker { even, odd } => {
+        let even = self.texture(even, &format!("{at}.even"))?;
+        let odd = self.texture(odd, &format!("{at}.odd"))?;
+        Arc::new(CheckerTexture::new(even, odd))
+      },
+      TextureDesc::Noise { scale } => {
+        check(*scale > 0.0, &format!("{at}.scale"), "must be positive")?;
+        Arc::new(NoiseTexture::new(*scale, self.rng))
+      },
+      TextureDesc::Image { file } => {
+        let path = self.base_dir.join(file);
+        let texture = ImageTexture::load(&path.to_string_lossy()).map_err(|e| SceneError::Image(path, e))?;
+        Arc::new(texture)
+      },
+      TextureDesc::VertexColor => Arc::new(VertexColorTexture)
+    })
+  }
+
+  fn material(&mut self, material: &MaterialRef, at: &str) -> Result<Arc<dyn Material>> {
+    match material {
+      MaterialRef::Inline(desc) => self.material_desc(desc, at),
+      MaterialRef::Named(name) => {
+        if let Some(material) = self.materials.get(name) { return Ok(material.clone()) }
+
+        let desc = self.material_descs.remove(name).ok_or_else(|| SceneError::UnknownMaterial(at.to_string(), name.clone()))?;
+        let material = self.material_desc(&desc, &format!("materials.{name}"))?;
+        self.materials.insert(name.clone(), material.clone());
+        Ok(material)
+      }
+    }
+  }
+
+  fn material_desc(&mut self, desc: &MaterialDesc, at: &str) -> Result<Arc<dyn Material>> {
+    Ok(match desc {
+      MaterialDesc::Lambertian { albedo } => Arc::new(Lambertian::new(self.texture(albedo, &format!("{at}.albedo"))?)),
+      MaterialDesc::Metal { albedo, fuzz } => {
+        check((0.0..=1.0).contains(fuzz), &format!("{at}.fuzz"), "must be between 0 and 1")?;
+        Arc::new(Metal::new(self.texture(albedo, &format!("{at}.albedo"))?, *fuzz))
+      },
+      MaterialDesc::Dielectric { ir, roughness, tint, tint_distance, thin } => {
+        let ir = self.ior(ir, &format!("{at}.ir"))?;
+        if roughness.is_none() && tint.is_none() && !thin { return Ok(Arc::new(Dialectric { ir })) }
+
+        check(!(*thin && roughness.is_some()), &format!("{at}.roughness"), "thin-walled glass is always smooth")?;
+        let tint = tint.unwrap_or(Color::new(1.0, 1.0, 1.0));
+        check((0..3).all(|i| tint[i] > 0.0 && tint[i] <= 1.0), &format!("{at}.tint"), "components must be between 0 (exclusive) and 1")?;
+        check(*tint_distance > 0.0, &format!("{at}.tint_distance"), "must be positive")?;
+        let roughness = self.texture_or(roughness, Color::zero(), &format!("{at}.roughness"))?;
+        Arc::new(RoughDielectric::new(ir, roughness, tint, *tint_distance, *thin))
+      },
+      MaterialDesc::Conductor { metal, eta, k, roughness, roughness_u, roughness_v } => {
+        let (eta, k) = match (metal, eta, k) {
+          (Some(name), None, None) => conductor_preset(name)
+            .ok_or_else(|| SceneError::Invalid(format!("{at}.metal"), format!("unknown metal '{name}' (expected gold, copper, aluminum or silver)")))?,
+          (None, Some(eta), Some(k)) => (*eta, *k),
+          _ => return Err(SceneError::Invalid(at.to_string(), "needs either metal or both eta and k".to_string()))
+        };
+        let roughness = self.texture_or(roughness, Color::zero(), &format!("{at}.roughness"))?;
+        let roughness_u = match roughness_u {
+          Some(r) => self.texture(r, &format!("{at}.roughness_u"))?,
+          None => roughness.clone()
+        };
+        let roughness_v = match roughness_v {
+          Some(r) => self.texture(r, &format!("{at}.roughness_v"))?,
+          None => roughness
+        };
+        Arc::new(Conductor::new(eta, k, roughness_u, roughness_v))
+      },
+      MaterialDesc::Principled { base_color, metallic, roughness, specular, transmission, clearcoat, clearcoat_roughness, sheen, emission } => {
+        Arc::new(Principled {
+          base_color: self.texture_or(base_color, Color::new(0.8, 0.8, 0.8), &format!("{at}.base_color"))?,
+          metallic: self.texture_or(metallic, Color::zero(), &format!("{at}.metallic"))?,
+          roughness: self.texture_or(roughness, Color::new(0.5, 0.5, 0.5), &format!("{at}.roughness"))?,
+          specular: self.texture_or(specular, Color::new(0.5, 0.5, 0.5), &format!("{at}.specular"))?,
+          transmission: self.texture_or(transmission, Color::zero(), &format!("{at}.transmission"))?,
+          clearcoat: self.texture_or(clearcoat, Color::zero(), &format!("{at}.clearcoat"))?,
+          clearcoat_roughness: self.texture_or(clearcoat_roughness, Color::new(0.03, 0.03, 0.03), &format!("{at}.clearcoat_roughness"))?,
+          sheen: self.texture_or(sheen, Color::zero(), &format!("{at}.sheen"))?,
+          emission: match emission {
+            Some(emission) => Some(self.texture(emission, &format!("{at}.emission"))?),
+            None => None
+          }
+        })
+      },
+      MaterialDesc::DiffuseLight { emit } => Arc::new(DiffuseLight::new(self.texture(emit, &format!("{at}.emit"))?)),
+      MaterialDesc::Isotropic { albedo } => Arc::new(Isotropic::new(self.texture(albedo, &format!("{at}.albedo"))?))
+    })
+  }
+
+  fn ior(&self, desc: &IorRef, at: &str) -> Result<Ior> {
+    let ior = match desc {
+      IorRef::Constant(n) => Ior::Constant(*n),
+      IorRef::Named(name) => Ior::preset(name)
+        .ok_or_else(|| SceneError::Invalid(at.to_string(), format!("unknown glass '{name}' (expected bk7, fused_silica or sf11)")))?,
+      IorRef::Model(IorDesc::Cauchy { a, b }) => Ior::Cauchy { a: *a, b: *b },
+      IorRef::Model(IorDesc::Sellmeier { b, c }) => Ior::Sellmeier { b: *b, c: *c },
+      IorRef::Model(IorDesc::Abbe { n, v }) => {
+        check(*v > 0.0, &format!("{at}.v"), "must be positive")?;
+        Ior::abbe(*n, *v)
+      }
+    };
+    check([None, Some(LAMBDA_MIN), Some(LAMBDA_MAX)].into_iter().all(|l| ior.at(l) > 0.0), at, "must be positive across the visible range")?;
+    Ok(ior)
+  }
+
+  fn object(&mut self, desc: &ObjectDesc, at: &str) -> Result<Box<dyn Hittable>> {
+    Ok(match desc {
+      ObjectDesc::Sphere { center, radius, material } => {
+        check(*radius != 0.0, &format!("{at}.radius"), "must not be zero")?;
+        Box::new(Sphere::new(*center, *radius, self.material(material, &format!("{at}.material"))?))
+      },
+      ObjectDesc::MovingSphere { center0, center1, time0, time1, radius, material } => {
+        check(*radius != 0.0, &format!("{at}.radius"), "must not be zero")?;
+        check(time0 != time1, &format!("{at}.time1"), "must differ from time0")?;
+        let material = self.material(material, &format!("{at}.material"))?;
+        Box::new(MovingSphere::new(*center0, *center1, *time0, *time1, *radius, material))
+      },
+      ObjectDesc::XyRect { x0, x1, y0, y1, k, material } => {
+        check(x0 < x1 && y0 < y1, at, "rectangle must have x0 < x1 and y0 < y1")?;
+        Box::new(xy_rect(*x0, *x1, *y0, *y1, *k, self.material(material, &format!("{at}.material"))?))
+      },
+      ObjectDesc::XzRect { x0, x1, z0, z1, k, material } => {
+        check(x0 < x1 && z0 < z1, at, "rectangle must have x0 < x1 and z0 < z1")?;
+        Box::new(xz_rect(*x0, *x1, *z0, *z1, *k, self.material(material, &format!("{at}.material"))?))
+      },
+      ObjectDesc::YzRect { y0, y1, z0, z1, k, material } => {
+        check(y0 < y1 && z0 < z1, at, "rectangle must have y0 < y1 and z0 < z1")?;
+        Box::new(yz_rect(*y0, *y1, *z0, *z1, *k, self.material(material, &format!("{at}.material"))?))
+      },
+      ObjectDesc::Quad { origin, u, v, material } => {
+        check(cross(u, v).length() > 0.0, at, "quad sides u and v must not be parallel")?;
+        Box::new(Quad::new(*origin, *u, *v, self.material(material, &format!("{at}.material"))?))
+      },
+      ObjectDesc::Disk { center, normal, radius, material } => {
+        check(normal.length() > 0.0, &format!("{at}.normal"), "must not be zero")?;
+        check(*radius > 0.0, &format!("{at}.radius"), "must be positive")?;
+        Box::new(Disk::new(*center, *normal, *radius, self.material(material, &format!("{at}.material"))?))
+      },
+      ObjectDesc::Polygon { vertices, material } => {
+        let material = self.material(material, &format!("{at}.material"))?;
+        Box::new(Polygon::new(vertices.clone(), material).map_err(|e| SceneError::Invalid(format!("{at}.vertices"), e))?)
+      },
+      ObjectDesc::Cube { p0, p1, material } => {
+        check(p0.x() < p1.x() && p0.y() < p1.y() && p0.z() < p1.z(), at, "cube must have p0 < p1 on every axis")?;
+        Box::new(Cube::new(*p0, *p1, self.material(material, &format!("{at}.material"))?))
+      },
+      ObjectDesc::Cylinder { base, radius, height, capped, material } => {
+        check(*radius > 0.0, &format!("{at}.radius"), "must be positive")?;
+        check(*height > 0.0, &format!("{at}.height"), "must be positive")?;
+        Box::new(Cone::cylinder(*base, *radius, *height, *capped, self.material(material, &format!("{at}.material"))?))
+      },
+      ObjectDesc::Cone { base, radius, top_radius, height, capped, material } => {
+        check(*radius >= 0.0 && *top_radius >= 0.0 && radius.max(*top_radius) > 0.0, at, "radius and top_radius must not be negative, nor both zero")?;
+        check(*height > 0.0, &format!("{at}.height"), "must be positive")?;
+        Box::new(Cone::new(*base, *radius, *top_radius, *height, *capped, self.material(material, &format!("{at}.material"))?))
+      },
+      ObjectDesc::Paraboloid { base, radius, height, capped, material } => {
+        check(*radius > 0.0, &format!("{at}.radius"), "must be positive")?;
+        check(*height > 0.0, &format!("{at}.height"), "must be positive")?;
+        Box::new(Paraboloid::new(*base, *radius, *height, *capped, self.material(material, &format!("{at}.material"))?))
+      },
+      ObjectDesc::Torus { center, major_radius, minor_radius, material } => {
+        check(*major_radius > 0.0, &format!("{at}.major_radius"), "must be positive")?;
+        check(*minor_radius > 0.0, &format!("{at}.minor_radius"), "must be positive")?;
+        Box::new(Torus::new(*center, *major_radius, *minor_radius, self.material(material, &format!("{at}.material"))?))
+      },
+      ObjectDesc::Sdf { shape, bounds, max_steps, epsilon, material } => {
+        check(*max_steps > 0, &format!("{at}.max_steps"), "must be positive")?;
+        check(*epsilon > 0.0, &format!("{at}.epsilon"), "must be positive")?;
+        let shape = sdf_shape(shape, &format!("{at}.shape"))?;
+        let bounds = match bounds {
+          Some([p0, p1]) => {
+            check(p0.x() < p1.x() && p0.y() < p1.y() && p0.z() < p1.z(), &format!("{at}.bounds"), "must have the first corner < the second on every axis")?;
+            AABB::new(*p0, *p1)
+          },
+          None => shape.bounds().ok_or_else(|| SceneError::Invalid(format!("{at}.bounds"), "needed for a shape without bounds".to_string()))?
+        };
+        let material = self.material(material, &format!("{at}.material"))?;
+        Box::new(SdfHittable::new(shape, bounds, material).with_max_steps(*max_steps).with_epsilon(*epsilon))
+      },
+      ObjectDesc::Triangle { vertices: [v0, v1, v2], material } => {
+        check(cross(&(*v1 - *v0), &(*v2 - *v0)).length() > 0.0, &format!("{at}.vertices"), "triangle must not be degenerate")?;
+        Box::new(Triangle::new(*v0, *v1, *v2, self.material(material, &format!("{at}.material"))?))
+      },
+      ObjectDesc::Mesh { positions, normals, uvs, colors, indices, material, materials, face_materials } => {
+        let materials = match (material, materials.is_empty()) {
+          (Some(material), true) => vec![self.material(material, &format!("{at}.material"))?],
+          (None, false) => materials.iter().enumerate()
+            .map(|(i, m)| self.material(m, &format!("{at}.materials[{i}]")))
+            .collect::<Result<Vec<_>>>()?,
+          _ => return Err(SceneError::Invalid(at.to_string(), "needs either material or materials".to_string()))
+        };
+        let mesh = MeshData {
+          positions: positions.clone(),
+          normals: normals.clone(),
+          uvs: uvs.clone(),
+          colors: colors.clone(),
+          indices: indices.clone(),
+          materials,
+          face_materials: face_materials.clone()
+        };
+        mesh.validate().map_err(|e| SceneError::Invalid(at.to_string(), e))?;
+        Box::new(TriangleMesh::new(Arc::new(mesh), self.rng))
+      },
+      ObjectDesc::Obj { file, material, groups } => {
+        let material = material.as_ref().map(|m| self.material(m, &format!("{at}.material"))).transpose()?;
+        let mesh = load_obj(&self.base_dir.join(file), material, groups.as_deref()).map_err(|e| SceneError::Invalid(at.to_string(), e.to_string()))?;
+        Box::new(TriangleMesh::new(Arc::new(mesh), self.rng))
+      },
+      ObjectDesc::Ply { file, material } => {
+        let material = material.as_ref().map(|m| self.material(m, &format!("{at}.material"))).transpose()?;
+        let mesh = load_ply(&self.base_dir.join(file), material).map_err(|e| SceneError::Invalid(at.to_string(), e.to_string()))?;
+        Box::new(TriangleMesh::new(Arc::new(mesh), self.rng))
+      },
+      ObjectDesc::Stl { file, material } => {
+        let material = material.as_ref().map(|m| self.material(m, &format!("{at}.material"))).transpose()?;
+        let mesh = load_stl(&self.base_dir.join(file), material).map_err(|e| SceneError::Invalid(at.to_string(), e.to_string()))?;
+        Box::new(TriangleMesh::new(Arc::new(mesh), self.rng))
+      },
+      ObjectDesc::ConstantMedium { boundary, density, albedo } => {
+        check(*density > 0.0, &format!("{at}.density"), "must be positive")?;
+        let boundary = self.object(boundary, &format!("{at}.boundary"))?;
+        Box::new(ConstantMedium::new(boundary, *density, self.texture(albedo, &format!("{at}.albedo"))?))
+      },
+      ObjectDesc::Union { objects } => self.csg(objects, CsgOperation::Union, &format!("{at}.objects"))?,
+      ObjectDesc::Intersection { objects } => self.csg(objects, CsgOperation::Intersection, &format!("{at}.objects"))?,
+      ObjectDesc::Difference { object, subtract } => {
+        let object = self.object(object, &format!("{at}.object"))?;
+        let subtract = self.csg(subtract, CsgOperation::Union, &format!("{at}.subtract"))?;
+        Box::new(Csg::new(object, subtract, CsgOperation::Difference))
+      },
+      ObjectDesc::Translate { offset, object } => Box::new(Transform::translate(self.object(object, &format!("{at}.object"))?, *offset)),
+      ObjectDesc::RotateY { angle, object } => Box::new(Transform::rotate_y(self.object(object, &format!("{at}.object"))?, *angle)),
+      ObjectDesc::Transform { transforms, object } => {
+        let mut to_world = Mat4::identity();
+        for (i, step) in transforms.iter().enumerate() {
+          to_world = transform_step(step, &format!("{at}.transforms[{i}]"))? * to_world;
+        }
+        check(to_world.inverse().is_some(), &format!("{at}.transforms"), "must not flatten the object")?;
+        Box::new(Transform::new(self.object(object, &format!("{at}.object"))?, to_world))
+      },
+      ObjectDesc::PointLight { .. } | ObjectDesc::SpotLight { .. } | ObjectDesc::DirectionalLight { .. } =>
+        return Err(SceneError::Invalid(at.to_string(), "punctual lights can only be placed directly in objects".to_string())),
+      ObjectDesc::Gltf { .. } => return Err(SceneError::Invalid(at.to_string(), "glTF scenes can only be placed directly in objects".to_string()))
+    })
+  }
+
+  // Combines the objects in order, each with those before it.
+  fn csg(&mut self, objects: &[ObjectDesc], operation: CsgOperation, at: &str) -> Result<Box<dyn Hittable>> {
+    check(!objects.is_empty(), at, "must not be empty")?;
+    let mut combined = self.object(&objects[0], &format!("{at}[0]"))?;
+    for (i, object) in objects.iter().enumerate().skip(1) {
+      combined = Box::new(Csg::new(combined, self.object(object, &format!("{at}[{i}]"))?, operation));
+    }
+    Ok(combined)
+  }
+
+  fn punctual_light(&self, desc: &ObjectDesc, camera: &CameraSettings, at: &str) -> Result<Option<Box<dyn PunctualLight>>> {
+    Ok(Some(match desc {
+      ObjectDesc::PointLight { position, position1, intensity } => {
+        Box::new(PointLight::new(*position, position1.unwrap_or(*position), camera.time0, camera.time1, *intensity))
+      },
+      ObjectDesc::SpotLight { position, position1, direction, angle, falloff_start, intensity } => {
+        check(direction.length() > 0.0, &format!("{at}.direction"), "must not be zero")?;
+        check(*angle > 0.0 && *angle <= 180.0, &format!("{at}.angle"), "must be between 0 and 180 degrees")?;
+        let falloff_start = falloff_start.unwrap_or(*angle);
+        check(falloff_start >= 0.0 && falloff_start <= *angle, &format!("{at}.falloff_start"), "must be between 0 and angle")?;
+        let position1 = position1.unwrap_or(*position);
+        Box::new(SpotLight::new(*position, position1, camera.time0, camera.time1, *direction, *angle, falloff_start, *intensity))
+      },
+      ObjectDesc::DirectionalLight { direction, irradiance } => {
+        check(direction.length() > 0.0, &format!("{at}.direction"), "must not be zero")?;
+        Box::new(DirectionalLight::new(*direction, *irradiance))
+      },
+      _ => return Ok(None)
+    }))
+  }
+}
+
+fn sdf_shape(desc: &SdfDesc, at: &str) -> Result<Box<dyn Sdf>> {
+  Ok(match desc {
+    SdfDesc::Sphere { radius } => {
+      check(*radius > 0.0, &format!("{at}.radius"), "must be positive")?;
+      Box::new(sdf::Sphere::new(*radius))
+    },
+    SdfDesc::RoundBox { size, radius } => {
+      check(size.x() > 0.0 && size.y() > 0.0 && size.z() > 0.0, &format!("{at}.size"), "must be positive on every axis")?;
+      check(*radius >= 0.0 && 2.0 * *radius <= size.x().min(size.y()).min(size.z()), &format!("{at}.radius"), "must be between 0 and half the smallest side")?;
+      Box::new(sdf::RoundBox::new(*size, *radius))
+    },
+    SdfDesc::Torus { major_radius, minor_radius } => {
+      check(*major_radius > 0.0, &format!("{at}.major_radius"), "must be positive")?;
+      check(*minor_radius > 0.0, &format!("{at}.minor_radius"), "must be positive")?;
+      Box::new(sdf::Torus::new(*major_radius, *minor_radius))
+    },
+    SdfDesc::Mandelbulb { power, iterations } => {
+      check(*power > 1.0, &format!("{at}.power"), "must be greater than 1")?;
+      check(*iterations > 0, &format!("{at}.iterations"), "must be positive")?;
+      Box::new(sdf::Mandelbulb::new(*power, *iterations))
+    },
+    SdfDesc::Union { shapes } => sdf_fold(shapes, &format!("{at}.shapes"), |a, b| Box::new(sdf::Union::new(a, b)))?,
+    SdfDesc::SmoothUnion { shapes, smoothness } => {
+      check(*smoothness > 0.0, &format!("{at}.smoothness"), "must be positive")?;
+      sdf_fold(shapes, &format!("{at}.shapes"), |a, b| Box::new(sdf::SmoothUnion::new(a, b, *smoothness)))?
+    },
+    SdfDesc::Intersection { shapes } => sdf_fold(shapes, &format!("{at}.shapes"), |a, b| Box::new(sdf::Intersection::new(a, b)))?,
+    SdfDesc::Subtract { shape, subtract } => {
+      let shape = sdf_shape(shape, &format!("{at}.shape"))?;
+      let subtract = sdf_fold(subtract, &format!("{at}.subtract"), |a, b| Box::new(sdf::Union::new(a, b)))?;
+      Box::new(sdf::Subtract::new(shape, subtract))
+    },
+    SdfDesc::Translate { offset, shape } => Box::new(sdf::Translate::new(sdf_shape(shape, &format!("{at}.shape"))?, *offset)),
+    SdfDesc::Repeat { spacing, limit, shape } => {
+      check((0..3).all(|a| spacing[a] >= 0.0), &format!("{at}.spacing"), "must not be negative")?;
+      Box::new(sdf::Repeat::new(sdf_shape(shape, &format!("{at}.shape"))?, *spacing, *limit))
+    },
+    SdfDesc::Twist { rate, shape } => {
+      let shape = sdf_shape(shape, &format!("{at}.shape"))?;
+      check(shape.bounds().is_some(), &format!("{at}.shape"), "must be bounded to twist")?;
+      Box::new(sdf::Twist::new(shape, *rate))
+    }
+  })
+}
+
+// Combines the shapes in order, each with those before it.
+fn sdf_fold(shapes: &[SdfDesc], at: &str, combine: impl Fn(Box<dyn Sdf>, Box<dyn Sdf>) -> Box<dyn Sdf>) -> Result<Box<dyn Sdf>> {
+  check(!shapes.is_empty(), at, "must not be empty")?;
+  let mut combined = sdf_shape(&shapes[0], &format!("{at}[0]"))?;
+  for (i, shape) in shapes.iter().enumerate().skip(1) {
+    combined = combine(combined, sdf_shape(shape, &format!("{at}[{i}]"))?);
+  }
+  Ok(combined)
+}
+
+fn transform_step(desc: &TransformDesc, at: &str) -> Result<Mat4> {
+  let m = match desc {
+    TransformDesc::Translate { offset } => Mat4::translation(*offset),
+    TransformDesc::Scale { factor: ScaleRef::Uniform(f) } => Mat4::scale(Vec3::new(*f, *f, *f)),
+    TransformDesc::Scale { factor: ScaleRef::Axes(factors) } => Mat4::scale(*factors),
+    TransformDesc::Rotate { axis, angle } => {
+      check(axis.length_squared() > 0.0, &format!("{at}.axis"), "must not be zero")?;
+      Mat4::rotation(*axis, *angle)
+    },
+    TransformDesc::Euler { angles } => Mat4::euler(*angles),
+    TransformDesc::Quaternion { rotation } => {
+      check(rotation.iter().any(|&c| c != 0.0), &format!("{at}.rotation"), "must not be zero")?;
+      Mat4::quaternion(*rotation)
+    },
+    TransformDesc::Matrix { rows } => {
+      let m = Mat4::from_rows(*rows);
+      check(m.is_affine(), &format!("{at}.rows"), "last row must be [0, 0, 0, 1]")?;
+      m
+    }
+  };
+  check(m.inverse().is_some(), at, "must not flatten the object")?;
+  Ok(m)
+}
+
+fn check(condition: bool, at: &str, msg: &str) -> Result<()> {
+  if condition { Ok(()) } else { Err(SceneError::Invalid(at.to_string(), msg.to_string())) }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::util::seeded_rng;
+
+  fn load(json: &str) -> Result<Scene> {
+    Scene::from_json(json, Path::new("scenes/test.json"), &mut seeded_rng(0))
+  }
+
+  fn error(json: &str) -> String {
+    load(json).err().expect("scene should not load").to_string()
+  }
+
+  // A scene around the given objects, textures and materials.
+  fn scene(objects: &str, textures: &str, materials: &str) -> String {
+    format!(r#"{{ "textures": {{ {textures} }}, "materials": {{ {materials} }}, "objects": [{objects}] }}"#)
+  }
+
+  const BALL: &str = r#"{ "type": "sphere", "center": [0, 0, 0], "radius": 1, "material": "grey" }"#;
+  const GREY: &str = r#""grey": { "type": "lambertian", "albedo": 0.5 }"#;
+
+  #[test]
+  fn loads_a_valid_scene() {
+    let json = r#"{
+      "image": { "width": 64, "samples_per_pixel": 4 },
+      "camera": { "lookfrom": [0, 0, 5], "lookat": [0, 0, 0] },
+      "background": [0.1, 0.2, 0.3],
+      "textures": {
+        "white": { "type": "solid", "color": [1, 1, 1] },
+        "checks": { "type": "checker", "even": "white", "odd": [0, 0, 0] }
+      },
+      "materials": {
+        "floor": { "type": "lambertian", "albedo": "checks" },
+        "light": { "type": "diffuse_light", "emit": 4 }
+      },
+      "objects": [
+        { "type": "sphere", "center": [0, -1000, 0], "radius": 999, "material": "floor" },
+        { "type": "sphere", "center": [2, 1, 0], "radius": 0.5, "material": "light" },
+        { "type": "sphere", "center": [-2, 1, 0], "radius": 1, "material": { "type": "metal", "albedo": "white", "fuzz": 0.1 } }
+      ]
+    }"#;
+    let scene = match load(json) { Ok(scene) => scene, Err(e) => panic!("{e}") };
+    assert_eq!(scene.image.width, 64);
+    assert_eq!(scene.image.samples_per_pixel, 4);
+    let bbox = scene.world.bounding_box(0.0, 1.0).unwrap();
+    assert!((bbox.min().x() + 999.0).abs() < 1e-9 && (bbox.max().y() - 2.0).abs() < 1e-9);
+  }
+
+  #[test]
+  fn reports_parse_errors_against_the_file() {
+    let message = error(r#"{ "objects": [], "colour": 1 }"#);
+    assert!(message.starts_with("scenes/test.json: unknown field `colour`"), "{message}");
+  }
+
+  #[test]
+  fn reports_invalid_values_by_path() {
+    assert_eq!(error(&format!(r#"{{ "image": {{ "width": 0 }}, "objects": [{BALL}], "materials": {{ {GREY} }} }}"#)), "image.width: must be positive");
+    assert_eq!(error(&scene("", "", "")), "objects: scene has no objects");
+    let flat = r#"{ "type": "sphere", "center": [0, 0, 0], "radius": 0, "material": "grey" }"#;
+    assert_eq!(error(&scene(&format!("{BALL}, {flat}"), "", GREY)), "objects[1].radius: must not be zero");
+    let rough = r#"{ "type": "sphere", "center": [0, 0, 0], "radius": 1, "material": { "type": "metal", "albedo": 1, "fuzz": 3 } }"#;
+    assert_eq!(error(&scene(rough, "", "")), "objects[0].material.fuzz: must be between 0 and 1");
+  }
+
+  #[test]
+  fn reports_unknown_references() {
+    assert_eq!(error(&scene(BALL, "", "")), "objects[0].material: unknown material 'grey'");
+    let grey = r#""grey": { "type": "lambertian", "albedo": "stone" }"#;
+    assert_eq!(error(&scene(BALL, "", grey)), "materials.grey.albedo: unknown texture 'stone'");
+  }
+
+  #[test]
+  fn reports_texture_cycles() {
+    let textures = r#""a": { "type": "checker", "even": "b", "odd": 0 }, "b": { "type": "checker", "even": 1, "odd": "a" }"#;
+    assert_eq!(error(&scene(BALL, textures, GREY)), "textures.b.odd: 'a' refers to itself");
+  }
+
+  #[test]
+  fn reports_broken_definitions_nothing_uses() {
+    assert_eq!(error(&scene(BALL, r#""unused": { "type": "noise", "scale": 0 }"#, GREY)), "textures.unused.scale: must be positive");
+    let unused = r#""unused": { "type": "metal", "albedo": 1, "fuzz": 2 }"#;
+    assert_eq!(error(&scene(BALL, "", &format!("{GREY}, {unused}"))), "materials.unused.fuzz: must be between 0 and 1");
+  }
+}
\ No newline at end of file
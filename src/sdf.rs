@@ -0,0 +1,410 @@
+use crate::{vec3::{Point3, Vec3, dot, unit_vector}, hittable::{Hittable, HitRecord}, sphere::{get_sphere_uv, sphere_tangent}, ray::Ray, material::Material, aabb::AABB, util::Rng};
+
+// Signed distance to a surface: negative inside, positive outside, and
+// never more than the true distance once divided by lipschitz.
+pub trait Sdf: Send + Sync {
+  fn distance(&self, p: &Point3) -> f64;
+
+  // Box holding the whole surface, unless it goes on forever.
+  fn bounds(&self) -> Option<AABB> { None }
+
+  // How much faster than the true distance the function can change, for
+  // shapes distorted enough to overestimate.
+  fn lipschitz(&self) -> f64 { 1.0 }
+}
+
+impl<S: Sdf + ?Sized> Sdf for Box<S> {
+  fn distance(&self, p: &Point3) -> f64 { (**self).distance(p) }
+  fn bounds(&self) -> Option<AABB> { (**self).bounds() }
+  fn lipschitz(&self) -> f64 { (**self).lipschitz() }
+}
+
+fn cube_bounds(half: Vec3) -> AABB {
+  AABB::new(-half, half)
+}
+
+// Shapes centered on the origin.
+
+pub struct Sphere {
+  radius: f64
+}
+
+impl Sphere {
+  pub fn new(radius: f64) -> Self { Self { radius } }
+}
+
+impl Sdf for Sphere {
+  fn distance(&self, p: &Point3) -> f64 { p.length() - self.radius }
+  fn bounds(&self) -> Option<AABB> { Some(cube_bounds(Vec3::new(self.radius, self.radius, self.radius))) }
+}
+
+// Box of the given size with its edges rounded off by radius.
+pub struct RoundBox {
+  half_size: Vec3,
+  radius: f64
+}
+
+impl RoundBox {
+  pub fn new(size: Vec3, radius: f64) -> Self { Self { half_size: size / 2.0, radius } }
+}
+
+impl Sdf for RoundBox {
+  fn distance(&self, p: &Point3) -> f64 {
+    let q = Vec3::new(p.x().abs(), p.y().abs(), p.z().abs()) - self.half_size + Vec3::new(self.radius, self.radius, self.radius);
+    let outside = Vec3::new(q.x().max(0.0), q.y().max(0.0), q.z().max(0.0)).length();
+    let inside = q.x().max(q.y()).max(q.z()).min(0.0);
+    outside + inside - self.radius
+  }
+
+  fn bounds(&self) -> Option<AABB> { Some(cube_bounds(self.half_size)) }
+}
+
+// Ring in the XZ plane.
+pub struct Torus {
+  major_radius: f64,
+  minor_radius: f64
+}
+
+impl Torus {
+  pub fn new(major_radius: f64, minor_radius: f64) -> Self { Self { major_radius, minor_radius } }
+}
+
+impl Sdf for Torus {
+  fn distance(&self, p: &Point3) -> f64 {
+    let ring = (p.x()*p.x() + p.z()*p.z()).sqrt() - self.major_radius;
+    (ring*ring + p.y()*p.y()).sqrt() - self.minor_radius
+  }
+
+  fn bounds(&self) -> Option<AABB> {
+    let reach = self.major_radius + self.minor_radius;
+    Some(cube_bounds(Vec3::new(reach, self.minor_radius, reach)))
+  }
+}
+
+// The power 8 Mandelbulb and its relatives, by the usual distance estimate
+// from the derivative of the iteration.
+pub struct Mandelbulb {
+  power: f64,
+  iterations: u32
+}
+
+impl Mandelbulb {
+  pub fn new(power: f64, iterations: u32) -> Self { Self { power, iterations } }
+}
+
+impl Sdf for Mandelbulb {
+  fn distance(&self, p: &Point3) -> f64 {
+    let mut z = *p;
+    let mut dr = 1.0;
+    let mut r = z.length();
+    for _ in 0..self.iterations {
+      if r > 2.0 { break }
+      let theta = (z.z() / r).acos() * self.power;
+      let phi = z.y().atan2(z.x()) * self.power;
+      dr = r.powf(self.power - 1.0) * self.power * dr + 1.0;
+      z = r.powf(self.power) * Vec3::new(theta.sin()*phi.cos(), theta.sin()*phi.sin(), theta.cos()) + *p;
+      r = z.length();
+    }
+    0.5 * r.ln() * r / dr
+  }
+
+  fn bounds(&self) -> Option<AABB> { Some(cube_bounds(Vec3::new(1.25, 1.25, 1.25))) }
+}
+
+// Combinators.
+
+pub struct Union<A: Sdf, B: Sdf> {
+  a: A,
+  b: B
+}
+
+impl<A: Sdf, B: Sdf> Union<A, B> {
+  pub fn new(a: A, b: B) -> Self { Self { a, b } }
+}
+
+impl<A: Sdf, B: Sdf> Sdf for Union<A, B> {
+  fn distance(&self, p: &Point3) -> f64 { self.a.distance(p).min(self.b.distance(p)) }
+  fn bounds(&self) -> Option<AABB> { Some(AABB::surrounding_box(&self.a.bounds()?, &self.b.bounds()?)) }
+  fn lipschitz(&self) -> f64 { self.a.lipschitz().max(self.b.lipschitz()) }
+}
+
+// Union blended over a distance of about smoothness where the shapes meet.
+pub struct SmoothUnion<A: Sdf, B: Sdf> {
+  a: A,
+  b: B,
+  smoothness: f64
+}
+
+impl<A: Sdf, B: Sdf> SmoothUnion<A, B> {
+  pub fn new(a: A, b: B, smoothness: f64) -> Self { Self { a, b, smoothness } }
+}
+
+impl<A: Sdf, B: Sdf> Sdf for SmoothUnion<A, B> {
+  fn distance(&self, p: &Point3) -> f64 {
+    // Polynomial smooth minimum, which is at most smoothness/4 below the
+    // plain one.
+    let (a, b, k) = (self.a.distance(p), self.b.distance(p), self.smoothness);
+    let h = (k - (a - b).abs()).max(0.0) / k;
+    a.min(b) - h*h*k/4.0
+  }
+
+  fn bounds(&self) -> Option<AABB> {
+    let union = AABB::surrounding_box(&self.a.bounds()?, &self.b.bounds()?);
+    let pad = Vec3::new(1.0, 1.0, 1.0) * self.smoothness/4.0;
+    Some(AABB::new(union.min() - pad, union.max() + pad))
+  }
+
+  fn lipschitz(&self) -> f64 { self.a.lipschitz().max(self.b.lipschitz()) }
+}
+
+pub struct Intersection<A: Sdf, B: Sdf> {
+  a: A,
+  b: B
+}
+
+impl<A: Sdf, B: Sdf> Intersection<A, B> {
+  pub fn new(a: A, b: B) -> Self { Self { a, b } }
+}
+
+impl<A: Sdf, B: Sdf> Sdf for Intersection<A, B> {
+  fn distance(&self, p: &Point3) -> f64 { self.a.distance(p).max(self.b.distance(p)) }
+
+  fn bounds(&self) -> Option<AABB> {
+    match (self.a.bounds(), self.b.bounds()) {
+      (Some(a), Some(b)) => Some(AABB::intersection(&a, &b)),
+      (a, b) => a.or(b)
+    }
+  }
+
+  fn lipschitz(&self) -> f64 { self.a.lipschitz().max(self.b.lipschitz()) }
+}
+
+// a with b carved out of it.
+pub struct Subtract<A: Sdf, B: Sdf> {
+  a: A,
+  b: B
+}
+
+impl<A: Sdf, B: Sdf> Subtract<A, B> {
+  pub fn new(a: A, b: B) -> Self { Self { a, b } }
+}
+
+impl<A: Sdf, B: Sdf> Sdf for Subtract<A, B> {
+  fn distance(&self, p: &Point3) -> f64 { self.a.distance(p).max(-self.b.distance(p)) }
+  fn bounds(&self) -> Option<AABB> { self.a.bounds() }
+  fn lipschitz(&self) -> f64 { self.a.lipschitz().max(self.b.lipschitz()) }
+}
+
+pub struct Translate<S: Sdf> {
+  shape: S,
+  offset: Vec3
+}
+
+impl<S: Sdf> Translate<S> {
+  pub fn new(shape: S, offset: Vec3) -> Self { Self { shape, offset } }
+}
+
+impl<S: Sdf> Sdf for Translate<S> {
+  fn distance(&self, p: &Point3) -> f64 { self.shape.distance(&(*p - self.offset)) }
+  fn bounds(&self) -> Option<AABB> { self.shape.bounds().map(|b| AABB::new(b.min() + self.offset, b.max() + self.offset)) }
+  fn lipschitz(&self) -> f64 { self.shape.lipschitz() }
+}
+
+// Copies of a shape spacing apart along each axis with a non-zero spacing,
+// limit copies to either side of the original or endlessly without one.
+// The shape should fit within its own cell.
+pub struct Repeat<S: Sdf> {
+  shape: S,
+  spacing: Vec3,
+  limit: Option<[u32; 3]>
+}
+
+impl<S: Sdf> Repeat<S> {
+  pub fn new(shape: S, spacing: Vec3, limit: Option<[u32; 3]>) -> Self { Self { shape, spacing, limit } }
+}
+
+impl<S: Sdf> Sdf for Repeat<S> {
+  fn distance(&self, p: &Point3) -> f64 {
+    let mut q = *p;
+    for a in 0..3 {
+      let s = self.spacing[a];
+      if s == 0.0 { continue }
+      let mut cell = (p[a] / s).round();
+      if let Some(limit) = self.limit { cell = cell.clamp(-(limit[a] as f64), limit[a] as f64) }
+      q[a] -= s * cell;
+    }
+    self.shape.distance(&q)
+  }
+
+  fn bounds(&self) -> Option<AABB> {
+    let limit = self.limit?;
+    let b = self.shape.bounds()?;
+    let reach = Vec3::new(
+      self.spacing.x().abs() * limit[0] as f64,
+      self.spacing.y().abs() * limit[1] as f64,
+      self.spacing.z().abs() * limit[2] as f64
+    );
+    Some(AABB::new(b.min() - reach, b.max() + reach))
+  }
+
+  fn lipschitz(&self) -> f64 { self.shape.lipschitz() }
+}
+
+// Shape twisted about the Y axis by rate degrees for each unit up it. The
+// twist stretches distances most far from the axis, so it needs a bounded
+// shape to know by how much.
+pub struct Twist<S: Sdf> {
+  shape: S,
+  rate: f64,
+  lipschitz: f64,
+  bounds: AABB
+}
+
+impl<S: Sdf> Twist<S> {
+  pub fn new(shape: S, rate: f64) -> Self {
+    let b = shape.bounds().expect("twisted shape should be bounded");
+    let radius = [b.min().x(), b.max().x()].iter()
+      .flat_map(|x| [b.min().z(), b.max().z()].map(|z| (x*x + z*z).sqrt()))
+      .fold(0.0, f64::max);
+    let rate = rate.to_radians();
+    let lipschitz = shape.lipschitz() * (1.0 + (rate*radius).powi(2)).sqrt();
+    let bounds = AABB::new(Point3::new(-radius, b.min().y(), -radius), Point3::new(radius, b.max().y(), radius));
+    Self { shape, rate, lipschitz, bounds }
+  }
+}
+
+impl<S: Sdf> Sdf for Twist<S> {
+  fn distance(&self, p: &Point3) -> f64 {
+    // Turn the point back by the twist at its height.
+    let (sin, cos) = (-self.rate * p.y()).sin_cos();
+    self.shape.distance(&Point3::new(cos*p.x() + sin*p.z(), p.y(), -sin*p.x() + cos*p.z()))
+  }
+
+  fn bounds(&self) -> Option<AABB> { Some(self.bounds) }
+  fn lipschitz(&self) -> f64 { self.lipschitz }
+}
+
+// Renders the zero surface of a distance function by sphere tracing: rays
+// step forward by the distance to the surface, which can't overshoot it,
+// until within epsilon of it or out of max_steps.
+pub struct SdfHittable<S: Sdf, M: Material> {
+  sdf: S,
+  bounds: AABB,
+  material: M,
+  max_steps: u32,
+  epsilon: f64
+}
+
+impl<S: Sdf, M: Material> SdfHittable<S, M> {
+  pub fn new(sdf: S, bounds: AABB, material: M) -> Self {
+    Self { sdf, bounds, material, max_steps: 256, epsilon: 1e-4 }
+  }
+
+  pub fn with_max_steps(self, max_steps: u32) -> Self { Self { max_steps, ..self } }
+  pub fn with_epsilon(self, epsilon: f64) -> Self { Self { epsilon, ..self } }
+
+  // Marching starts where rays enter this box, far enough out that they are
+  // never already at the surface there.
+  fn padded_bounds(&self) -> AABB {
+    let pad = Vec3::new(1.0, 1.0, 1.0) * 2.0*self.epsilon;
+    AABB::new(self.bounds.min() - pad, self.bounds.max() + pad)
+  }
+
+  // Gradient from four samples at the corners of a tetrahedron.
+  fn normal(&self, p: &Point3) -> Vec3 {
+    let h = self.epsilon;
+    [Vec3::new(1.0, -1.0, -1.0), Vec3::new(-1.0, -1.0, 1.0), Vec3::new(-1.0, 1.0, -1.0), Vec3::new(1.0, 1.0, 1.0)].iter()
+      .fold(Vec3::zero(), |n, k| n + *k * self.sdf.distance(&(*p + h*(*k))))
+  }
+}
+
+impl<S: Sdf, M: Material> Hittable for SdfHittable<S, M> {
+  fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _rng: &mut Rng) -> Option<HitRecord<'_>> {
+    let (mut t, t_end) = self.padded_bounds().clip(r, t_min, t_max)?;
+    let speed = r.direction().length() * self.sdf.lipschitz();
+
+    // Which side of the surface the ray is on isn't known while it is still
+    // within epsilon of it, as when leaving it after a bounce, so it edges
+    // forward until clear.
+    let mut side = 0.0;
+    for _ in 0..self.max_steps {
+      let distance = self.sdf.distance(&r.at(t));
+      if side == 0.0 {
+        if distance.abs() < self.epsilon {
+          t += self.epsilon / speed;
+          if t > t_end { return None }
+          continue
+        }
+        side = distance.signum();
+      }
+
+      let distance = side * distance;
+      if distance < self.epsilon {
+        let p = r.at(t);
+        let outward_normal = unit_vector(self.normal(&p));
+        let (u, v) = get_sphere_uv(&outward_normal);
+        let mut rec = HitRecord { p, normal: outward_normal, material: &self.material, t, u, v, tangent: sphere_tangent(&outward_normal), color: None, front_face: true };
+        rec.set_face_normal(r, &outward_normal);
+        // Grazing hits can have a normal at right angles to the ray; the
+        // side it was found from decides.
+        if dot(&r.direction(), &outward_normal) == 0.0 { rec.front_face = side > 0.0 }
+        return Some(rec)
+      }
+      t += distance / speed;
+      if t > t_end { return None }
+    }
+    None
+  }
+
+  fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+    Some(self.padded_bounds())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{material::Lambertian, texture::SolidColor, vec3::Color, util::seeded_rng};
+
+  fn ball() -> SdfHittable<Sphere, Lambertian<SolidColor>> {
+    let bounds = AABB::new(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0));
+    SdfHittable::new(Sphere::new(1.0), bounds, Lambertian::new(SolidColor::new(Color::new(0.5, 0.5, 0.5))))
+  }
+
+  #[test]
+  fn hit_matches_analytic_sphere() {
+    let ball = ball();
+    let mut rng = seeded_rng(0);
+    // The direction needn't be a unit vector.
+    for (y, speed) in [(0.0, 1.0), (0.3, 2.0), (0.9, 0.5)] {
+      let r = Ray::new(Point3::new(-5.0, y, 0.0), Vec3::new(speed, 0.0, 0.0), 0.0);
+      let rec = ball.hit(&r, 0.001, f64::INFINITY, &mut rng).unwrap();
+      let exact = (5.0 - (1.0 - y*y).sqrt()) / speed;
+      assert!((rec.t - exact).abs() * speed < 1e-3, "{} != {exact}", rec.t);
+      assert!(rec.front_face);
+      assert!((rec.normal - unit_vector(rec.p - Point3::zero())).length() < 1e-3);
+    }
+    let r = Ray::new(Point3::new(-5.0, 1.5, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+    assert!(ball.hit(&r, 0.001, f64::INFINITY, &mut rng).is_none());
+  }
+
+  #[test]
+  fn leaving_the_surface_does_not_hit_it_again() {
+    let ball = ball();
+    let mut rng = seeded_rng(0);
+    let r = Ray::new(Point3::new(-5.0, 0.3, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+    let rec = ball.hit(&r, 0.001, f64::INFINITY, &mut rng).unwrap();
+    let bounce = Ray::new(rec.p, rec.normal, 0.0);
+    assert!(ball.hit(&bounce, 0.001, f64::INFINITY, &mut rng).is_none());
+  }
+
+  #[test]
+  fn exits_from_inside() {
+    let ball = ball();
+    let mut rng = seeded_rng(0);
+    let r = Ray::new(Point3::new(0.0, 0.0, 0.2), Vec3::new(0.0, 1.0, 0.0), 0.0);
+    let rec = ball.hit(&r, 0.001, f64::INFINITY, &mut rng).unwrap();
+    assert!((rec.t - (1.0f64 - 0.04).sqrt()).abs() < 1e-3, "{}", rec.t);
+    assert!(!rec.front_face);
+  }
+}
\ No newline at end of file
@@ -0,0 +1,156 @@
+use std::f64::consts::PI;
+
+use crate::{environment::Environment, onb::Onb, sampler::sample_cone, vec3::{Vec3, Color, dot, unit_vector}};
+
+// Converts the model's luminance in kcd/m^2 to scene radiance, chosen so that
+// a white diffuse surface under a high sun comes out close to 1.
+const RADIANCE_SCALE: f64 = 0.03;
+
+// Luminance of the sun's disk outside the atmosphere, in kcd/m^2.
+const SUN_LUMINANCE: f64 = 1.88e6;
+const SUN_ANGULAR_RADIUS: f64 = 0.2665;
+
+// Coefficients of the Perez distribution for one of Y, x or y.
+#[derive(Debug, Clone, Copy)]
+struct Perez {
+  a: f64, b: f64, c: f64, d: f64, e: f64
+}
+
+impl Perez {
+  fn f(&self, cos_theta: f64, gamma: f64) -> f64 {
+    (1.0 + self.a*(self.b / cos_theta.max(1e-4)).exp()) * (1.0 + self.c*(self.d*gamma).exp() + self.e*gamma.cos().powi(2))
+  }
+}
+
+// Preetham, Shirley and Smits' "A Practical Analytic Model for Daylight",
+// with a sun disk whose color follows the same turbidity. Below the horizon is
+// a diffuse ground lit by the sky and sun.
+#[derive(Debug, Clone, Copy)]
+pub struct SkyEnvironment {
+  sun_direction: Vec3,
+  perez: [Perez; 3],
+  // Zenith Y, x, y divided by the Perez function at the zenith.
+  zenith: [f64; 3],
+  sun_radiance: Color,
+  cos_sun_max: f64,
+  ground: Color,
+  intensity: f64
+}
+
+impl SkyEnvironment {
+  pub fn new(sun_direction: Vec3, turbidity: f64, ground_albedo: Color, intensity: f64) -> Self {
+    let sun_direction = unit_vector(sun_direction);
+    let t = turbidity;
+    // The model only covers a sun above the horizon.
+    let theta_s = sun_direction.y().clamp(0.0, 1.0).acos();
+
+    let perez = [
+      Perez { a: 0.1787*t - 1.4630, b: -0.3554*t + 0.4275, c: -0.0227*t + 5.3251, d: 0.1206*t - 2.5771, e: -0.0670*t + 0.3703 },
+      Perez { a: -0.0193*t - 0.2592, b: -0.0665*t + 0.0008, c: -0.0004*t + 0.2125, d: -0.0641*t - 0.8989, e: -0.0033*t + 0.0452 },
+      Perez { a: -0.0167*t - 0.2608, b: -0.0950*t + 0.0092, c: -0.0079*t + 0.2102, d: -0.0441*t - 1.6537, e: -0.0109*t + 0.0529 }
+    ];
+
+    let chi = (4.0/9.0 - t/120.0) * (PI - 2.0*theta_s);
+    let zenith_y = (4.0453*t - 4.9710)*chi.tan() - 0.2155*t + 2.4192;
+    let polynomial = |c: [[f64; 4]; 3]| {
+      let angles = [theta_s.powi(3), theta_s.powi(2), theta_s, 1.0];
+      let row = |r: [f64; 4]| (0..4).map(|i| r[i]*angles[i]).sum::<f64>();
+      t*t*row(c[0]) + t*row(c[1]) + row(c[2])
+    };
+    let zenith_x = polynomial([
+      [0.00166, -0.00375, 0.00209, 0.0],
+      [-0.02903, 0.06377, -0.03202, 0.00394],
+      [0.11693, -0.21196, 0.06052, 0.25886]
+    ]);
+    let zenith_y_chroma = polynomial([
+      [0.00275, -0.00610, 0.00317, 0.0],
+      [-0.04214, 0.08970, -0.04153, 0.00516],
+      [0.15346, -0.26756, 0.06670, 0.26688]
+    ]);
+    let zenith = [zenith_y, zenith_x, zenith_y_chroma];
+
+    let mut sky = Self {
+      sun_direction, perez,
+      zenith: [0, 1, 2].map(|i| zenith[i] / perez[i].f(1.0, theta_s)),
+      sun_radiance: RADIANCE_SCALE * SUN_LUMINANCE * sun_transmittance(theta_s, turbidity),
+      cos_sun_max: SUN_ANGULAR_RADIUS.to_radians().cos(),
+      ground: Color::zero(),
+      intensity
+    };
+    if sun_direction.y() <= 0.0 { sky.sun_radiance = Color::zero() }
+
+    sky.ground = ground_albedo * sky.horizontal_irradiance() / PI;
+    sky
+  }
+
+  fn sky(&self, direction: &Vec3) -> Color {
+    let cos_theta = direction.y();
+    let gamma = dot(direction, &self.sun_direction).clamp(-1.0, 1.0).acos();
+    let [luminance, x, y] = [0, 1, 2].map(|i| self.zenith[i] * self.perez[i].f(cos_theta, gamma));
+    RADIANCE_SCALE * xyy_to_rgb(x, y, luminance)
+  }
+
+  fn horizontal_irradiance(&self) -> Color {
+    // Midpoint rule over the upper hemisphere.
+    let (n_theta, n_phi) = (32, 64);
+    let mut irradiance = Color::zero();
+    for i in 0..n_theta {
+      let theta = (i as f64 + 0.5) / n_theta as f64 * PI / 2.0;
+      for j in 0..n_phi {
+        let phi = (j as f64 + 0.5) / n_phi as f64 * 2.0 * PI;
+        let direction = Vec3::new(theta.sin()*phi.cos(), theta.cos(), theta.sin()*phi.sin());
+        let solid_angle = theta.sin() * (PI / 2.0 / n_theta as f64) * (2.0 * PI / n_phi as f64);
+        irradiance += self.sky(&direction) * (theta.cos() * solid_angle);
+      }
+    }
+
+    let sun_solid_angle = 2.0*PI*(1.0 - self.cos_sun_max);
+    irradiance + self.sun_radiance * (sun_solid_angle * self.sun_direction.y().max(0.0))
+  }
+}
+
+impl Environment for SkyEnvironment {
+  fn value(&self, direction: &Vec3) -> Color {
+    let d = unit_vector(*direction);
+    if d.y() < 0.0 { return self.intensity * self.ground }
+
+    let mut radiance = self.sky(&d);
+    if dot(&d, &self.sun_direction) >= self.cos_sun_max { radiance += self.sun_radiance }
+    self.intensity * radiance
+  }
+
+  // Only the sun is sampled; the much dimmer sky is left to BSDF sampling.
+  fn is_light(&self) -> bool { self.intensity > 0.0 && self.sun_direction.y() > 0.0 }
+
+  fn pdf_value(&self, direction: &Vec3) -> f64 {
+    if dot(&unit_vector(*direction), &self.sun_direction) < self.cos_sun_max { return 0.0 }
+    1.0 / (2.0*PI*(1.0 - self.cos_sun_max))
+  }
+
+  fn random(&self, u: (f64, f64)) -> Vec3 {
+    Onb::build_from_w(&self.sun_direction).local(&sample_cone(u, self.cos_sun_max))
+  }
+}
+
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Color {
+  if y <= 0.0 { return Color::zero() }
+  let (cx, cy, cz) = (x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+  Color::new(
+    (3.2406*cx - 1.5372*cy - 0.4986*cz).max(0.0),
+    (-0.9689*cx + 1.8758*cy + 0.0415*cz).max(0.0),
+    (0.0557*cx - 0.2040*cy + 1.0570*cz).max(0.0)
+  )
+}
+
+// Rayleigh and aerosol extinction along the sun's path through the
+// atmosphere, at representative red, green and blue wavelengths.
+fn sun_transmittance(theta_s: f64, turbidity: f64) -> Color {
+  let air_mass = 1.0 / (theta_s.cos() + 0.15*(93.885 - theta_s.to_degrees()).powf(-1.253));
+  let beta = 0.04608*turbidity - 0.04586;
+  let [r, g, b] = [0.65, 0.55, 0.45].map(|lambda: f64| {
+    let rayleigh = (-0.008735 * lambda.powf(-4.08) * air_mass).exp();
+    let aerosol = (-beta * lambda.powf(-1.3) * air_mass).exp();
+    rayleigh * aerosol
+  });
+  Color::new(r, g, b)
+}
\ No newline at end of file
@@ -0,0 +1,118 @@
+use std::{array, sync::OnceLock};
+
+use crate::vec3::{Color, Vec3};
+
+// Wavelengths in nanometres sampled by the spectral renderer.
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 780.0;
+
+const SAMPLES: usize = 3;
+
+// Wavelengths carried by one path: a hero wavelength chosen uniformly and
+// the others rotated from it by equal steps through the visible range, each
+// stored in one channel of a Color.
+#[derive(Debug, Clone, Copy)]
+pub struct SampledWavelengths {
+  lambda: [f64; SAMPLES]
+}
+
+impl SampledWavelengths {
+  pub fn sample(u: f64) -> Self {
+    let range = LAMBDA_MAX - LAMBDA_MIN;
+    let hero = u * range;
+    Self { lambda: array::from_fn(|i| LAMBDA_MIN + (hero + i as f64 * range / SAMPLES as f64) % range) }
+  }
+
+  pub fn hero(&self) -> f64 { self.lambda[0] }
+
+  // Drops all but the hero wavelength from values, scaling it up so that it
+  // stands in for the rest.
+  pub fn keep_hero(&self, values: Color) -> Color {
+    Color::new(SAMPLES as f64 * values.x(), 0.0, 0.0)
+  }
+
+  // Values at each wavelength of a smooth spectrum with the given RGB color.
+  pub fn upsample(&self, rgb: Color) -> Color {
+    let spectrum = rgb_to_spectrum(&rgb);
+    let bin = |lambda: f64| (((lambda - LAMBDA_MIN) / (SMITS_MAX - LAMBDA_MIN) * SMITS_BINS as f64) as usize).min(SMITS_BINS - 1);
+    Color::new(spectrum[bin(self.lambda[0])], spectrum[bin(self.lambda[1])], spectrum[bin(self.lambda[2])])
+  }
+
+  // Linear sRGB estimate of radiance sampled at these wavelengths, balanced
+  // so that a constant spectrum is white.
+  pub fn to_rgb(self, values: Color) -> Color {
+    let mut xyz = Vec3::zero();
+    for i in 0..SAMPLES {
+      xyz += values[i] * cie_xyz(self.lambda[i]);
+    }
+    let rgb = xyz_to_rgb(&(xyz * ((LAMBDA_MAX - LAMBDA_MIN) / SAMPLES as f64)));
+    let white = white_rgb();
+    Color::new(rgb.x() / white.x(), rgb.y() / white.y(), rgb.z() / white.z())
+  }
+}
+
+// CIE 1931 colour matching functions, using the multi-lobe Gaussian fit of
+// Wyman, Sloan and Shirley.
+fn cie_xyz(lambda: f64) -> Vec3 {
+  let g = |mu: f64, sigma1: f64, sigma2: f64| {
+    let t = (lambda - mu) / if lambda < mu { sigma1 } else { sigma2 };
+    (-0.5*t*t).exp()
+  };
+  Vec3::new(
+    1.056*g(599.8, 37.9, 31.0) + 0.362*g(442.0, 16.0, 26.7) - 0.065*g(501.1, 20.4, 26.2),
+    0.821*g(568.8, 46.9, 40.5) + 0.286*g(530.9, 16.3, 31.1),
+    1.217*g(437.0, 11.8, 36.0) + 0.681*g(459.0, 26.0, 13.8)
+  )
+}
+
+fn xyz_to_rgb(xyz: &Vec3) -> Color {
+  Color::new(
+    3.2404542*xyz.x() - 1.5371385*xyz.y() - 0.4985314*xyz.z(),
+    -0.9692660*xyz.x() + 1.8760108*xyz.y() + 0.0415560*xyz.z(),
+    0.0556434*xyz.x() - 0.2040259*xyz.y() + 1.0572252*xyz.z()
+  )
+}
+
+// RGB of a constant unit spectrum over the sampled range.
+fn white_rgb() -> Color {
+  static WHITE: OnceLock<Color> = OnceLock::new();
+  *WHITE.get_or_init(|| {
+    let mut xyz = Vec3::zero();
+    let mut lambda = LAMBDA_MIN + 0.5;
+    while lambda < LAMBDA_MAX {
+      xyz += cie_xyz(lambda);
+      lambda += 1.0;
+    }
+    xyz_to_rgb(&xyz)
+  })
+}
+
+// Smits' RGB to spectrum conversion: the color is built from white plus the
+// smoothest spectra for the secondary and primary colors that make up the
+// rest, tabulated in bins from LAMBDA_MIN to SMITS_MAX.
+const SMITS_MAX: f64 = 720.0;
+const SMITS_BINS: usize = 10;
+
+const WHITE: [f64; SMITS_BINS] = [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000];
+const CYAN: [f64; SMITS_BINS] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000];
+const MAGENTA: [f64; SMITS_BINS] = [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959];
+const YELLOW: [f64; SMITS_BINS] = [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840];
+const RED: [f64; SMITS_BINS] = [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149];
+const GREEN: [f64; SMITS_BINS] = [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025];
+const BLUE: [f64; SMITS_BINS] = [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496];
+
+fn rgb_to_spectrum(rgb: &Color) -> [f64; SMITS_BINS] {
+  let (r, g, b) = (rgb.x(), rgb.y(), rgb.z());
+  let mix = |w: f64, c1: f64, s1: &[f64; SMITS_BINS], c2: f64, s2: &[f64; SMITS_BINS]| {
+    array::from_fn(|i| w*WHITE[i] + c1*s1[i] + c2*s2[i])
+  };
+  if r <= g && r <= b {
+    if g <= b { mix(r, g - r, &CYAN, b - g, &BLUE) } else { mix(r, b - r, &CYAN, g - b, &GREEN) }
+  } else if g <= r && g <= b {
+    if r <= b { mix(g, r - g, &MAGENTA, b - r, &BLUE) } else { mix(g, b - g, &MAGENTA, r - b, &RED) }
+  } else if r <= g {
+    mix(b, r - b, &YELLOW, g - r, &GREEN)
+  } else {
+    mix(b, g - b, &YELLOW, r - g, &RED)
+  }
+}
\ No newline at end of file
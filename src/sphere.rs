@@ -1,6 +1,6 @@
 use std::f64::consts::PI;
 
-use crate::{vec3::{Point3, dot, Vec3}, hittable::{Hittable, HitRecord}, ray::Ray, material::Material, aabb::AABB};
+use crate::{vec3::{Point3, dot, Vec3}, onb::Onb, sampler::sample_cone, hittable::{Hittable, HitRecord}, ray::Ray, material::Material, aabb::AABB, mat4::Mat4, util::Rng};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Sphere<M: Material> {
@@ -23,12 +23,18 @@ pub fn get_sphere_uv(p: &Point3) -> (f64, f64) {
   (phi / (2.0*PI), theta / PI)
 }
 
+// Direction u increases in at p on the unit sphere: around the Y axis,
+// vanishing at the poles.
+pub fn sphere_tangent(p: &Point3) -> Vec3 {
+  Vec3::new(p.z(), 0.0, -p.x())
+}
+
 impl<M: Material> Sphere<M> {
   pub fn new(center: Point3, radius: f64, material: M) -> Self { Self { center, radius, material } }
 }
 
 impl<M: Material> Hittable for Sphere<M> {
-  fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+  fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _rng: &mut Rng) -> Option<HitRecord<'_>> {
     let oc = r.origin() - self.center;
     let a = r.direction().length_squared();
     let half_b = dot(&oc, &r.direction());
@@ -49,12 +55,39 @@ impl<M: Material> Hittable for Sphere<M> {
     let p = r.at(t);
     let outward_normal = (p - self.center) / self.radius;
     let (u, v) = get_sphere_uv(&outward_normal);
-    let mut rec = HitRecord { t, p, material: &self.material, normal: outward_normal, front_face: true, u, v };
+    let mut rec = HitRecord { t, p, material: &self.material, normal: outward_normal, front_face: true, u, v, tangent: sphere_tangent(&outward_normal), color: None };
     rec.set_face_normal(r, &outward_normal);
 
     Some(rec)
   }
 
+  fn is_light(&self) -> bool { self.material.is_emissive() }
+
+  fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+    // Uniform over the cone of directions the sphere subtends, which is only
+    // defined from outside it.
+    let oc = *o - self.center;
+    let distance_squared = oc.length_squared();
+    let radius_squared = self.radius*self.radius;
+    if distance_squared <= radius_squared { return 0.0 }
+
+    let a = v.length_squared();
+    let half_b = dot(&oc, v);
+    let discriminant = half_b*half_b - a*(distance_squared - radius_squared);
+    if discriminant < 0.0 || (-half_b + discriminant.sqrt()) / a <= 0.0 { return 0.0 }
+
+    let cos_theta_max = (1.0 - radius_squared/distance_squared).sqrt();
+    1.0 / (2.0*PI*(1.0 - cos_theta_max))
+  }
+
+  fn random(&self, o: &Point3, u: (f64, f64)) -> Vec3 {
+    let direction = self.center - *o;
+    let distance_squared = direction.length_squared();
+    let cos_theta_max = (1.0 - (self.radius*self.radius/distance_squared).min(1.0)).sqrt();
+
+    Onb::build_from_w(&direction).local(&sample_cone(u, cos_theta_max))
+  }
+
   fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
     let radius_vec = Vec3::new(self.radius, self.radius, self.radius);
     Some(AABB::new(
@@ -62,4 +95,8 @@ impl<M: Material> Hittable for Sphere<M> {
       self.center + radius_vec
     ))
   }
+
+  fn transformed_bounding_box(&self, to_world: &Mat4, _time0: f64, _time1: f64) -> Option<AABB> {
+    Some(to_world.transform_sphere_bbox(&self.center, self.radius))
+  }
 }
\ No newline at end of file
@@ -0,0 +1,196 @@
+use std::{collections::HashMap, fmt, fs, path::{Path, PathBuf}, sync::Arc};
+
+use crate::{
+  vec3::{Point3, Color},
+  material::{Material, Lambertian},
+  triangle::MeshData
+};
+
+#[derive(Debug)]
+pub struct StlError {
+  path: PathBuf,
+  message: String
+}
+
+impl fmt::Display for StlError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}: {}", self.path.display(), self.message)
+  }
+}
+
+impl std::error::Error for StlError {}
+
+// Loads the triangles of an STL file, binary or ASCII, as one mesh in
+// material, or plain grey without one. STL repeats every vertex for each
+// triangle using it, so vertices at exactly the same position are merged;
+// facet normals are ignored in favour of the winding.
+pub fn load_stl(path: &Path, material: Option<Arc<dyn Material>>) -> Result<MeshData<Arc<dyn Material>>, StlError> {
+  let error = |message: String| StlError { path: path.to_path_buf(), message };
+  let bytes = fs::read(path).map_err(|e| error(format!("could not read file: {e}")))?;
+
+  // ASCII files start with "solid", but so do some binary ones, which are
+  // told apart by their size matching their triangle count.
+  let binary_size = bytes.get(80..84).map(|count| 84 + 50 * u32::from_le_bytes(count.try_into().expect("4 bytes")) as usize);
+  let mut builder = MeshBuilder::default();
+  if binary_size == Some(bytes.len()) {
+    for facet in bytes[84..].chunks_exact(50) {
+      let coordinate = |i: usize| f64::from(f32::from_le_bytes(facet[i*4..i*4 + 4].try_into().expect("4 bytes")));
+      // The facet normal comes first.
+      builder.triangle([3, 6, 9].map(|i| Point3::new(coordinate(i), coordinate(i + 1), coordinate(i + 2))));
+    }
+  } else if bytes.starts_with(b"solid") {
+    let text = std::str::from_utf8(&bytes).map_err(|_| error("ASCII STL is not valid UTF-8".to_string()))?;
+    read_ascii(text, &mut builder).map_err(error)?;
+  } else {
+    return Err(error(match binary_size {
+      Some(size) => format!("binary STL should be {size} bytes for its triangle count, but is {}", bytes.len()),
+      None => "file is too short to be an STL file".to_string()
+    }))
+  }
+
+  let mut mesh = builder.mesh;
+  mesh.materials.push(material.unwrap_or_else(|| Arc::new(Lambertian::solid(Color::new(0.8, 0.8, 0.8)))));
+  mesh.validate().map_err(error)?;
+  Ok(mesh)
+}
+
+fn read_ascii(text: &str, builder: &mut MeshBuilder) -> Result<(), String> {
+  let mut polygon = Vec::with_capacity(3);
+  for (i, line) in text.lines().enumerate() {
+    let at = |message: &str| format!("line {}: {message}", i + 1);
+    let mut tokens = line.split_whitespace();
+    match tokens.next() {
+      Some("vertex") => {
+        let v = tokens.map(|t| t.parse::<f64>().map_err(|_| at(&format!("invalid number '{t}'")))).collect::<Result<Vec<f64>, String>>()?;
+        if v.len() != 3 { return Err(at(&format!("expected 3 coordinates, got {}", v.len()))) }
+        polygon.push(Point3::new(v[0], v[1], v[2]));
+      },
+      // Loops are meant to be triangles, but polygons are split into fans.
+      Some("endloop") => {
+        if polygon.len() < 3 { return Err(at(&format!("loop has only {} vertices", polygon.len()))) }
+        for j in 2..polygon.len() {
+          builder.triangle([polygon[0], polygon[j - 1], polygon[j]]);
+        }
+        polygon.clear();
+      },
+      Some("solid" | "facet" | "outer" | "endfacet" | "endsolid") | None => {},
+      Some(keyword) => return Err(at(&format!("unexpected '{keyword}'")))
+    }
+  }
+  Ok(())
+}
+
+// Builds an indexed mesh, sharing vertices with bitwise equal positions.
+struct MeshBuilder {
+  mesh: MeshData<Arc<dyn Material>>,
+  vertices: HashMap<[u64; 3], usize>
+}
+
+impl Default for MeshBuilder {
+  fn default() -> Self {
+    Self {
+      mesh: MeshData {
+        positions: Vec::new(),
+        normals: Vec::new(),
+        uvs: Vec::new(),
+        colors: Vec::new(),
+        indices: Vec::new(),
+        materials: Vec::new(),
+        face_materials: Vec::new()
+      },
+      vertices: HashMap::new()
+    }
+  }
+}
+
+impl MeshBuilder {
+  fn triangle(&mut self, vertices: [Point3; 3]) {
+    let face = vertices.map(|p| {
+      // Adding zero turns -0 into 0 so that both merge.
+      let key = [p.x(), p.y(), p.z()].map(|c| (c + 0.0).to_bits());
+      let positions = &mut self.mesh.positions;
+      *self.vertices.entry(key).or_insert_with(|| {
+        positions.push(p);
+        positions.len() - 1
+      })
+    });
+    self.mesh.indices.push(face);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn load(test: &str, bytes: &[u8]) -> (PathBuf, Result<MeshData<Arc<dyn Material>>, StlError>) {
+    let path = std::env::temp_dir().join(format!("raytracer-stl-{test}-{}.stl", std::process::id()));
+    fs::write(&path, bytes).unwrap();
+    let mesh = load_stl(&path, None);
+    fs::remove_file(&path).unwrap();
+    (path, mesh)
+  }
+
+  // Two triangles of a unit quad, which share an edge.
+  const QUAD: [[[f32; 3]; 3]; 2] = [
+    [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]],
+    [[0.0, 0.0, 0.0], [1.0, 1.0, 0.0], [-0.0, 1.0, 0.0]]
+  ];
+
+  fn binary(header: &[u8]) -> Vec<u8> {
+    let mut bytes = header.to_vec();
+    bytes.resize(80, b' ');
+    bytes.extend((QUAD.len() as u32).to_le_bytes());
+    for facet in QUAD {
+      bytes.extend([0.0f32, 0.0, 1.0].iter().flat_map(|c| c.to_le_bytes()));
+      bytes.extend(facet.iter().flatten().flat_map(|c| c.to_le_bytes()));
+      bytes.extend([0, 0]);
+    }
+    bytes
+  }
+
+  fn positions(mesh: &MeshData<Arc<dyn Material>>) -> Vec<[f64; 3]> {
+    mesh.positions.iter().map(|p| [p.x(), p.y(), p.z()]).collect()
+  }
+
+  #[test]
+  fn shared_vertices_are_merged() {
+    let mut text = "solid quad\n".to_string();
+    for facet in QUAD {
+      text += "  facet normal 0 0 1\n    outer loop\n";
+      for v in facet { text += &format!("      vertex {} {} {}\n", v[0], v[1], v[2]) }
+      text += "    endloop\n  endfacet\n";
+    }
+    text += "endsolid quad\n";
+    let (_, mesh) = load("ascii", text.as_bytes());
+    let mesh = mesh.unwrap();
+    // -0 and 0 are the same vertex.
+    assert_eq!(positions(&mesh), vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]]);
+    assert_eq!(mesh.indices, vec![[0, 1, 2], [0, 2, 3]]);
+  }
+
+  #[test]
+  fn binary_files_may_start_with_solid() {
+    let (_, ascii_header) = load("solid-header", &binary(b"solid exported by a tool that should know better"));
+    let (_, plain_header) = load("binary", &binary(b"binary"));
+    let (ascii_header, plain_header) = (ascii_header.unwrap(), plain_header.unwrap());
+    assert_eq!(positions(&ascii_header), positions(&plain_header));
+    assert_eq!(ascii_header.indices, vec![[0, 1, 2], [0, 2, 3]]);
+  }
+
+  #[test]
+  fn sizes_that_do_not_match_the_count_are_rejected() {
+    let mut bytes = binary(b"binary");
+    bytes.pop();
+    let (path, mesh) = load("short", &bytes);
+    match mesh {
+      Ok(_) => panic!("expected an error"),
+      Err(e) => assert_eq!(e.to_string(), format!("{}: binary STL should be 184 bytes for its triangle count, but is 183", path.display()))
+    }
+
+    let (path, mesh) = load("loop", b"solid bad\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nendloop\n");
+    match mesh {
+      Ok(_) => panic!("expected an error"),
+      Err(e) => assert_eq!(e.to_string(), format!("{}: line 6: loop has only 2 vertices", path.display()))
+    }
+  }
+}
\ No newline at end of file
@@ -1,9 +1,27 @@
-use image::{io::Reader as ImageReader, RgbImage};
+use std::sync::Arc;
 
-use crate::{vec3::{Point3, Color}, perlin::Perlin};
+use image::{io::Reader as ImageReader, RgbImage, ImageResult};
 
-pub trait Texture: Sync {
+use crate::{vec3::{Point3, Color}, hittable::HitRecord, perlin::Perlin, util::Rng};
+
+pub trait Texture: Send + Sync {
   fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+
+  // Value at a ray hit, for textures that need more of it than the surface
+  // coordinates and point.
+  fn value_at(&self, rec: &HitRecord) -> Color {
+    self.value(rec.u, rec.v, &rec.p)
+  }
+}
+
+impl<T: Texture + ?Sized> Texture for Arc<T> {
+  fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+    (**self).value(u, v, p)
+  }
+
+  fn value_at(&self, rec: &HitRecord) -> Color {
+    (**self).value_at(rec)
+  }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -13,9 +31,6 @@ pub struct SolidColor {
 
 impl SolidColor {
   pub fn new(c: Color) -> Self { Self { color_value: c } }
-  pub fn new_rgb(red: f64, green: f64, blue: f64) -> Self {
-    Self { color_value: Color::new(red, green, blue) }
-  }
 }
 
 impl Texture for SolidColor {
@@ -40,14 +55,32 @@ impl CheckerTexture<SolidColor, SolidColor> {
   }
 }
 
+fn is_odd(p: &Point3) -> bool {
+  (10.0*p.x()).sin()*(10.0*p.y()).sin()*(10.0*p.z()).sin() < 0.0
+}
+
 impl<O: Texture, E: Texture> Texture for CheckerTexture<O, E> {
   fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
-      let sines = (10.0*p.x()).sin()*(10.0*p.y()).sin()*(10.0*p.z()).sin();
-      if sines < 0.0 {
-        self.odd.value(u, v, p)
-      } else {
-        self.even.value(u, v, p)
-      }
+    if is_odd(p) { self.odd.value(u, v, p) } else { self.even.value(u, v, p) }
+  }
+
+  fn value_at(&self, rec: &HitRecord) -> Color {
+    if is_odd(&rec.p) { self.odd.value_at(rec) } else { self.even.value_at(rec) }
+  }
+}
+
+// Colors given at the vertices of a mesh, blended across its faces. White
+// on surfaces without them.
+#[derive(Debug, Clone, Copy)]
+pub struct VertexColorTexture;
+
+impl Texture for VertexColorTexture {
+  fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+    Color::new(1.0, 1.0, 1.0)
+  }
+
+  fn value_at(&self, rec: &HitRecord) -> Color {
+    rec.color.unwrap_or(Color::new(1.0, 1.0, 1.0))
   }
 }
 
@@ -58,7 +91,7 @@ pub struct NoiseTexture {
 }
 
 impl NoiseTexture {
-  pub fn new(scale: f64) -> Self { Self { noise: Perlin::new(), scale } }
+  pub fn new(scale: f64, rng: &mut Rng) -> Self { Self { noise: Perlin::new(rng), scale } }
 }
 
 impl Texture for NoiseTexture {
@@ -68,30 +101,15 @@ impl Texture for NoiseTexture {
   }
 }
 
-const BYTES_PER_PIXEL: i32 = 3;
-
 #[derive(Debug, Clone)]
 pub struct ImageTexture {
   img: Option<RgbImage>
 }
 
 impl ImageTexture {
-  pub fn new(filename: &str) -> Self {
-    let img = match ImageReader::open(filename) {
-      Ok(reader) => {
-        match reader.decode() {
-          Ok(image) => image.as_rgb8().cloned(),
-          Err(_) => None
-        }
-      },
-      Err(_) => None
-    };
-
-    if img.is_none() {
-      eprintln!("ERROR: Could not load texture image file '{filename}'.");
-    }
-
-    Self { img }
+  pub fn load(filename: &str) -> ImageResult<Self> {
+    let image = ImageReader::open(filename)?.decode()?;
+    Ok(Self { img: Some(image.to_rgb8()) })
   }
 }
 
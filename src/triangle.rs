@@ -0,0 +1,318 @@
+use std::sync::Arc;
+
+use crate::{material::Material, hittable::{Hittable, HitRecord}, aabb::AABB, bvh::BVH, ray::Ray, vec3::{Point3, Vec3, Color, cross, dot, unit_vector}, sampler::sample_triangle, util::Rng};
+
+// Watertight ray-triangle intersection (Woop, Benthin and Wald). Vertices are
+// moved into a space where the ray starts at the origin and runs along +Z,
+// so an edge shared by two triangles is tested identically for both and
+// rays can't slip through between them. Returns t and the barycentric
+// coordinates of the hit.
+fn intersect(r: &Ray, p: [&Point3; 3], t_min: f64, t_max: f64) -> Option<(f64, [f64; 3])> {
+  let d = r.direction();
+  let kz = (0..3).max_by(|&a, &b| d[a].abs().total_cmp(&d[b].abs())).unwrap();
+  let (kx, ky) = ((kz + 1) % 3, (kz + 2) % 3);
+  let permute = |v: Vec3| Vec3::new(v[kx], v[ky], v[kz]);
+
+  let d = permute(d);
+  let (sx, sy, sz) = (-d.x() / d.z(), -d.y() / d.z(), 1.0 / d.z());
+  let [p0, p1, p2] = p.map(|p| {
+    let p = permute(*p - r.origin());
+    Vec3::new(p.x() + sx*p.z(), p.y() + sy*p.z(), sz*p.z())
+  });
+
+  // Edge functions; the ray passes inside if they all have the same sign.
+  let e0 = p1.x()*p2.y() - p1.y()*p2.x();
+  let e1 = p2.x()*p0.y() - p2.y()*p0.x();
+  let e2 = p0.x()*p1.y() - p0.y()*p1.x();
+  if (e0 < 0.0 || e1 < 0.0 || e2 < 0.0) && (e0 > 0.0 || e1 > 0.0 || e2 > 0.0) { return None }
+  let det = e0 + e1 + e2;
+  if det == 0.0 { return None }
+
+  let t = (e0*p0.z() + e1*p1.z() + e2*p2.z()) / det;
+  if t < t_min || t > t_max { return None }
+  Some((t, [e0 / det, e1 / det, e2 / det]))
+}
+
+fn bounding_box(p: [&Point3; 3]) -> AABB {
+  // Padded so that triangles in an axis plane still have some thickness.
+  let pad = Vec3::new(0.0001, 0.0001, 0.0001);
+  let min = Point3::new(p[0].x().min(p[1].x()).min(p[2].x()), p[0].y().min(p[1].y()).min(p[2].y()), p[0].z().min(p[1].z()).min(p[2].z()));
+  let max = Point3::new(p[0].x().max(p[1].x()).max(p[2].x()), p[0].y().max(p[1].y()).max(p[2].y()), p[0].z().max(p[1].z()).max(p[2].z()));
+  AABB::new(min - pad, max + pad)
+}
+
+fn interpolate(b: &[f64; 3], v: [Vec3; 3]) -> Vec3 {
+  b[0]*v[0] + b[1]*v[1] + b[2]*v[2]
+}
+
+// A flat triangle with vertices in counter-clockwise order seen from the
+// front. u and v are the barycentric coordinates of the second and third
+// vertices.
+#[derive(Debug, Clone, Copy)]
+pub struct Triangle<M: Material> {
+  vertices: [Point3; 3],
+  material: M
+}
+
+impl<M: Material> Triangle<M> {
+  pub fn new(v0: Point3, v1: Point3, v2: Point3, material: M) -> Self {
+    Self { vertices: [v0, v1, v2], material }
+  }
+
+  fn points(&self) -> [&Point3; 3] {
+    [&self.vertices[0], &self.vertices[1], &self.vertices[2]]
+  }
+
+  fn normal(&self) -> Vec3 {
+    cross(&(self.vertices[1] - self.vertices[0]), &(self.vertices[2] - self.vertices[0]))
+  }
+}
+
+impl<M: Material> Hittable for Triangle<M> {
+  fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _rng: &mut Rng) -> Option<HitRecord<'_>> {
+    let (t, b) = intersect(r, self.points(), t_min, t_max)?;
+    let mut rec = HitRecord {
+      p: interpolate(&b, self.vertices),
+      normal: Vec3::zero(),
+      material: &self.material,
+      t,
+      u: b[1],
+      v: b[2],
+      tangent: self.vertices[1] - self.vertices[0],
+      color: None,
+      front_face: true
+    };
+    rec.set_face_normal(r, &unit_vector(self.normal()));
+    Some(rec)
+  }
+
+  fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+    Some(bounding_box(self.points()))
+  }
+
+  fn is_light(&self) -> bool { self.material.is_emissive() }
+
+  fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+    let Some((t, _)) = intersect(&Ray::new(*o, *v, 0.0), self.points(), 0.001, f64::INFINITY) else { return 0.0 };
+    let normal = self.normal();
+    let area = normal.length() / 2.0;
+    let cosine = dot(v, &normal).abs() / (v.length() * normal.length());
+    t*t*v.length_squared() / (cosine * area)
+  }
+
+  fn random(&self, o: &Point3, u: (f64, f64)) -> Vec3 {
+    interpolate(&sample_triangle(u), self.vertices) - *o
+  }
+}
+
+// Buffers shared by all the triangles of a mesh. Faces index into
+// positions, and into normals, uvs and colors when those are given per
+// vertex for smooth shading and texturing. face_materials, if given, picks
+// each face's entry in materials; otherwise every face uses the first.
+pub struct MeshData<M: Material> {
+  pub positions: Vec<Point3>,
+  pub normals: Vec<Vec3>,
+  pub uvs: Vec<(f64, f64)>,
+  pub colors: Vec<Color>,
+  pub indices: Vec<[usize; 3]>,
+  pub materials: Vec<M>,
+  pub face_materials: Vec<usize>
+}
+
+impl<M: Material> MeshData<M> {
+  pub fn validate(&self) -> Result<(), String> {
+    if self.indices.is_empty() { return Err("mesh has no faces".to_string()) }
+    if self.materials.is_empty() { return Err("mesh has no materials".to_string()) }
+    if !self.normals.is_empty() && self.normals.len() != self.positions.len() {
+      return Err(format!("{} normals for {} vertices", self.normals.len(), self.positions.len()))
+    }
+    if !self.uvs.is_empty() && self.uvs.len() != self.positions.len() {
+      return Err(format!("{} uvs for {} vertices", self.uvs.len(), self.positions.len()))
+    }
+    if !self.colors.is_empty() && self.colors.len() != self.positions.len() {
+      return Err(format!("{} colors for {} vertices", self.colors.len(), self.positions.len()))
+    }
+    if !self.face_materials.is_empty() && self.face_materials.len() != self.indices.len() {
+      return Err(format!("{} face materials for {} faces", self.face_materials.len(), self.indices.len()))
+    }
+    if let Some((face, _)) = self.indices.iter().enumerate().find(|(_, f)| f.iter().any(|&i| i >= self.positions.len())) {
+      return Err(format!("face {face} refers to a vertex past the {} given", self.positions.len()))
+    }
+    if let Some(face) = self.face_materials.iter().position(|&m| m >= self.materials.len()) {
+      return Err(format!("face {face} refers to a material past the {} given", self.materials.len()))
+    }
+    Ok(())
+  }
+}
+
+struct MeshTriangle<M: Material> {
+  mesh: Arc<MeshData<M>>,
+  face: usize
+}
+
+impl<M: Material> MeshTriangle<M> {
+  fn points(&self) -> [&Point3; 3] {
+    self.mesh.indices[self.face].map(|i| &self.mesh.positions[i])
+  }
+}
+
+impl<M: Material> Hittable for MeshTriangle<M> {
+  fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _rng: &mut Rng) -> Option<HitRecord<'_>> {
+    let mesh = &*self.mesh;
+    let (t, b) = intersect(r, self.points(), t_min, t_max)?;
+    let [i0, i1, i2] = mesh.indices[self.face];
+    let p = [mesh.positions[i0], mesh.positions[i1], mesh.positions[i2]];
+
+    // Faces whose winding disagrees with their vertex normals are turned
+    // around, so which side is the front follows the normals when given.
+    let mut geometric = unit_vector(cross(&(p[1] - p[0]), &(p[2] - p[0])));
+    let shading = if mesh.normals.is_empty() {
+      geometric
+    } else {
+      let shading = unit_vector(interpolate(&b, [mesh.normals[i0], mesh.normals[i1], mesh.normals[i2]]));
+      if dot(&shading, &geometric) < 0.0 { geometric = -geometric }
+      shading
+    };
+    let (u, v, tangent) = if mesh.uvs.is_empty() {
+      (b[1], b[2], p[1] - p[0])
+    } else {
+      let uv = [mesh.uvs[i0], mesh.uvs[i1], mesh.uvs[i2]];
+      // dp/du, from how the edges change position against how they change
+      // u and v.
+      let (du1, dv1, du2, dv2) = (uv[1].0 - uv[0].0, uv[1].1 - uv[0].1, uv[2].0 - uv[0].0, uv[2].1 - uv[0].1);
+      let determinant = du1*dv2 - du2*dv1;
+      let tangent = if determinant.abs() > 1e-12 { (dv2*(p[1] - p[0]) - dv1*(p[2] - p[0])) / determinant } else { p[1] - p[0] };
+      (b[0]*uv[0].0 + b[1]*uv[1].0 + b[2]*uv[2].0, b[0]*uv[0].1 + b[1]*uv[1].1 + b[2]*uv[2].1, tangent)
+    };
+    let color = if mesh.colors.is_empty() {
+      None
+    } else {
+      Some(interpolate(&b, [mesh.colors[i0], mesh.colors[i1], mesh.colors[i2]]))
+    };
+
+    let front_face = dot(&r.direction(), &geometric) < 0.0;
+    Some(HitRecord {
+      p: interpolate(&b, p),
+      normal: if front_face { shading } else { -shading },
+      material: &mesh.materials[mesh.face_materials.get(self.face).copied().unwrap_or(0)],
+      t, u, v, tangent, color,
+      front_face
+    })
+  }
+
+  fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+    Some(bounding_box(self.points()))
+  }
+}
+
+// Triangles sharing one set of buffers, with a BVH of their own so the mesh
+// goes into the scene's BVH as a single object. Emissive meshes only light
+// the scene through rays that happen to hit them, since they aren't sampled
+// as lights.
+pub struct TriangleMesh {
+  bvh: BVH
+}
+
+impl TriangleMesh {
+  pub fn new<M: Material + 'static>(mesh: Arc<MeshData<M>>, rng: &mut Rng) -> Self {
+    let faces: Vec<Box<dyn Hittable>> = (0..mesh.indices.len())
+      .map(|face| Box::new(MeshTriangle { mesh: mesh.clone(), face }) as Box<dyn Hittable>)
+      .collect();
+    Self { bvh: BVH::new(faces, 0.0, 0.0, rng) }
+  }
+}
+
+impl Hittable for TriangleMesh {
+  fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut Rng) -> Option<HitRecord<'_>> {
+    self.bvh.hit(r, t_min, t_max, rng)
+  }
+
+  fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
+    self.bvh.bounding_box(time0, time1)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{material::Lambertian, texture::SolidColor, util::{seeded_rng, random_double}};
+
+  fn grey() -> Lambertian<SolidColor> { Lambertian::new(SolidColor::new(Color::new(0.5, 0.5, 0.5))) }
+
+  fn mesh(positions: Vec<Point3>, indices: Vec<[usize; 3]>) -> MeshData<Lambertian<SolidColor>> {
+    MeshData { positions, normals: Vec::new(), uvs: Vec::new(), colors: Vec::new(), indices, materials: vec![grey()], face_materials: Vec::new() }
+  }
+
+  #[test]
+  fn hit_reports_distance_and_barycentrics() {
+    let p = [Point3::new(0.0, 0.0, 0.0), Point3::new(2.0, 0.0, 0.0), Point3::new(0.0, 2.0, 0.0)];
+    let r = Ray::new(Point3::new(0.5, 0.25, 3.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+    let (t, b) = intersect(&r, [&p[0], &p[1], &p[2]], 0.001, f64::INFINITY).unwrap();
+    assert!((t - 3.0).abs() < 1e-12);
+    assert!((b[0] - 0.625).abs() < 1e-12 && (b[1] - 0.25).abs() < 1e-12 && (b[2] - 0.125).abs() < 1e-12);
+    // Both sides are hit, and nothing outside the edges.
+    assert!(intersect(&Ray::new(Point3::new(0.5, 0.25, -3.0), Vec3::new(0.0, 0.0, 1.0), 0.0), [&p[0], &p[1], &p[2]], 0.001, f64::INFINITY).is_some());
+    assert!(intersect(&Ray::new(Point3::new(1.5, 1.0, 3.0), Vec3::new(0.0, 0.0, -1.0), 0.0), [&p[0], &p[1], &p[2]], 0.001, f64::INFINITY).is_none());
+  }
+
+  // Rays from random places aimed at target, and how many of them miss every
+  // triangle of the mesh.
+  fn misses(data: MeshData<Lambertian<SolidColor>>, target: impl Fn(f64) -> Point3) -> usize {
+    let mut rng = seeded_rng(1);
+    let mesh = TriangleMesh::new(Arc::new(data), &mut rng);
+    (0..5000).filter(|_| {
+      let origin = Point3::new(random_double(&mut rng) - 0.5, random_double(&mut rng) - 0.5, 1.0 + random_double(&mut rng)) * 3.0;
+      let r = Ray::new(origin, target(random_double(&mut rng)) - origin, 0.0);
+      mesh.hit(&r, 0.001, f64::INFINITY, &mut rng).is_none()
+    }).count()
+  }
+
+  #[test]
+  fn rays_through_shared_edges_do_not_slip_through() {
+    // A tilted quad split along its diagonal, rays aimed along the diagonal.
+    let (a, b, c, d) = (Point3::new(-0.7, -0.9, 0.1), Point3::new(1.1, -0.6, -0.2), Point3::new(0.8, 1.3, 0.3), Point3::new(-0.9, 0.7, -0.1));
+    assert_eq!(misses(mesh(vec![a, b, c, d], vec![[0, 1, 2], [0, 2, 3]]), |s| a + s * (c - a)), 0);
+  }
+
+  #[test]
+  fn rays_through_shared_vertices_do_not_slip_through() {
+    // A fan of triangles around a vertex slightly off the origin.
+    let center = Point3::new(0.013, -0.021, 0.007);
+    let mut positions = vec![center];
+    for i in 0..7 {
+      let angle = i as f64 * std::f64::consts::TAU / 7.0;
+      positions.push(Point3::new(angle.cos(), angle.sin(), 0.1 * (i % 2) as f64));
+    }
+    let indices = (0..7).map(|i| [0, 1 + i, 1 + (i + 1) % 7]).collect();
+    assert_eq!(misses(mesh(positions, indices), |_| center), 0);
+  }
+
+  #[test]
+  fn validate_rejects_out_of_range_indices() {
+    let positions = vec![Point3::zero(), Point3::new(1.0, 0.0, 0.0), Point3::new(0.0, 1.0, 0.0)];
+    assert!(mesh(positions.clone(), vec![[0, 1, 2]]).validate().is_ok());
+    assert_eq!(mesh(positions.clone(), vec![[0, 1, 2], [2, 1, 3]]).validate(), Err("face 1 refers to a vertex past the 3 given".to_string()));
+
+    let mut data = mesh(positions.clone(), vec![[0, 1, 2], [0, 2, 1]]);
+    data.face_materials = vec![0, 1];
+    assert_eq!(data.validate(), Err("face 1 refers to a material past the 1 given".to_string()));
+    data.face_materials = vec![0];
+    assert_eq!(data.validate(), Err("1 face materials for 2 faces".to_string()));
+
+    let mut data = mesh(positions.clone(), vec![[0, 1, 2]]);
+    data.normals = vec![Vec3::new(0.0, 0.0, 1.0)];
+    assert_eq!(data.validate(), Err("1 normals for 3 vertices".to_string()));
+    assert_eq!(mesh(positions, Vec::new()).validate(), Err("mesh has no faces".to_string()));
+  }
+
+  #[test]
+  fn vertex_normals_decide_the_front() {
+    // Wound clockwise seen from +Z, but with normals pointing at +Z.
+    let mut data = mesh(vec![Point3::zero(), Point3::new(0.0, 1.0, 0.0), Point3::new(1.0, 0.0, 0.0)], vec![[0, 1, 2]]);
+    data.normals = vec![Vec3::new(0.0, 0.0, 1.0); 3];
+    let mut rng = seeded_rng(2);
+    let mesh = TriangleMesh::new(Arc::new(data), &mut rng);
+    let rec = mesh.hit(&Ray::new(Point3::new(0.2, 0.2, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0), 0.001, f64::INFINITY, &mut rng).unwrap();
+    assert!(rec.front_face && rec.normal.z() > 0.0);
+  }
+}
\ No newline at end of file
@@ -1,17 +1,37 @@
-pub fn random_double() -> f64 {
+use rand::{Rng as _, SeedableRng};
+use rand_pcg::Pcg32;
+
+pub type Rng = Pcg32;
+
+pub fn seeded_rng(seed: u64) -> Rng {
+  Rng::seed_from_u64(seed)
+}
+
+pub fn sample_rng(seed: u64, pixel: u64, sample: u64) -> Rng {
+  // An independent stream for every (pixel, sample) pair, so the result does
+  // not depend on which thread renders a pixel or in what order.
+  Rng::new(mix(seed ^ mix(pixel ^ mix(sample))), pixel)
+}
+
+pub fn mix(mut z: u64) -> u64 {
+  // SplitMix64 finalizer.
+  z = z.wrapping_add(0x9e3779b97f4a7c15);
+  z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+  z ^ (z >> 31)
+}
+
+pub fn random_double(rng: &mut Rng) -> f64 {
   // Returns a random real in [0,1).
-  rand::random::<f64>()
+  rng.gen::<f64>()
 }
-pub fn random_int(min: i32, max: i32) -> i32 {
-  random_double_in_range(min as f64, (max+1) as f64) as i32
+pub fn random_int(rng: &mut Rng, min: i32, max: i32) -> i32 {
+  random_double_in_range(rng, min as f64, (max+1) as f64) as i32
 }
 
-pub fn random_double_in_range(min: f64, max: f64) -> f64 {
+pub fn random_double_in_range(rng: &mut Rng, min: f64, max: f64) -> f64 {
   // Returns a random real in [min,max).
-  min + (max-min)*random_double()
-}
-pub fn divide_into_parts(n: i32, m: i32) -> Vec<i32> {
-  (0..m).map(|i| (n / m as i32) + ((i + 1) <= (n % m as i32)) as i32).collect()
+  min + (max-min)*random_double(rng)
 }
 
 pub fn fmin(a: f64, b: f64) -> f64 {
@@ -1,8 +1,11 @@
 use std::{ops, fmt};
 
-use crate::util::{random_double, random_double_in_range, fmin};
+use serde::Deserialize;
 
-#[derive(Debug, Clone, Copy)]
+use crate::util::{Rng, random_double, random_double_in_range, fmin};
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(from = "[f64; 3]")]
 pub struct Vec3 {
   e: [f64; 3]
 }
@@ -14,26 +17,20 @@ impl Vec3 {
   pub fn zero() -> Self { Vec3 { e: [0.0, 0.0, 0.0] } }
   pub fn new(e0: f64, e1: f64, e2: f64) -> Self { Vec3 { e: [e0, e1, e2] } }
 
-  pub fn random() -> Self {
-    Vec3 { e: [random_double(), random_double(), random_double()] }
+  pub fn random(rng: &mut Rng) -> Self {
+    Vec3 { e: [random_double(rng), random_double(rng), random_double(rng)] }
   }
-  pub fn random_in_range(min: f64, max: f64) -> Self {
-    Vec3 { e: [random_double_in_range(min, max), random_double_in_range(min, max), random_double_in_range(min, max)] }
+  pub fn random_in_range(rng: &mut Rng, min: f64, max: f64) -> Self {
+    Vec3 { e: [random_double_in_range(rng, min, max), random_double_in_range(rng, min, max), random_double_in_range(rng, min, max)] }
   }
-  pub fn random_in_unit_sphere() -> Self {
+  pub fn random_in_unit_sphere(rng: &mut Rng) -> Self {
     loop {
-      let p = Vec3::random_in_range(-1.0, 1.0);
+      let p = Vec3::random_in_range(rng, -1.0, 1.0);
       if p.length_squared() < 1.0 { break p };
     }
   }
-  pub fn random_unit_vector() -> Self {
-    unit_vector(Vec3::random_in_unit_sphere())
-  }
-  pub fn random_in_unit_disk() -> Self {
-    loop {
-      let p = Vec3::new(random_double_in_range(-1.0, 1.0), random_double_in_range(-1.0, 1.0), 0.0);
-      if p.length_squared() < 1.0 { break p };
-    }
+  pub fn random_unit_vector(rng: &mut Rng) -> Self {
+    unit_vector(Vec3::random_in_unit_sphere(rng))
   }
 
   pub fn x(&self) -> f64 { self.e[0] }
@@ -55,6 +52,10 @@ impl Vec3 {
   }
 }
 
+impl From<[f64; 3]> for Vec3 {
+  fn from(e: [f64; 3]) -> Self { Vec3 { e } }
+}
+
 impl ops::Neg for Vec3 {
   type Output = Vec3;
 